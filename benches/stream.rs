@@ -0,0 +1,146 @@
+//! Reads large template output from interpreter and compiler streams with small and large buffers.
+//!
+//! Every stream is also read through `DrainStream`, the way streams buffered output before
+//! `OutputBuffer`, as a baseline to compare with.
+//!
+//! Run with `cargo bench --bench stream`.
+
+extern crate little;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{ self, Read };
+use std::time::{ Duration, Instant };
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::optimizer::Optimizer;
+
+const CHUNK_LEN: usize = 64 * 1024;
+const CHUNKS: usize = 64;
+const ROUNDS: u32 = 5;
+
+/// String value, enough to output constants.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+struct Value(String);
+
+impl LittleValue for Value { }
+
+impl IdentifyValue for Value {
+    fn identify_value(&self) -> Option<Fingerprint> {
+        None
+    }
+
+    fn hash_value<H: Sha1Hasher>(&self, _hasher: &mut H) -> Result<(), ()> {
+        Err(())
+    }
+}
+
+impl GetProperty<Value> for Value {
+    fn get_property(&self, _name: Value) -> Option<Value> {
+        None
+    }
+}
+
+impl IterateValue<Value> for Value { }
+
+impl OperateValue<Value> for Value { }
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Template that outputs the same large constant `CHUNKS` times.
+fn template() -> Template<Value> {
+    Template::empty()
+        .with_constant(Constant(0), Value(String::from_utf8(vec![b'x'; CHUNK_LEN]).unwrap()))
+        .with_instructions((0..CHUNKS).map(|_| Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None }))
+}
+
+/// Stream that keeps unread output in a `Vec` and drains read bytes from its front,
+/// so the rest of the output is moved on every read.
+///
+/// Output of an instruction is read from the wrapped stream in one piece.
+struct DrainStream<R> {
+    inner: R,
+    buf: Vec<u8>,
+    chunk: Vec<u8>,
+}
+
+impl<R: Read> DrainStream<R> {
+    fn new(inner: R) -> DrainStream<R> {
+        DrainStream {
+            inner: inner,
+            buf: Vec::new(),
+            chunk: vec![0; CHUNK_LEN],
+        }
+    }
+}
+
+impl<R: Read> Read for DrainStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.buf.len() < buf.len() {
+            match try!(self.inner.read(&mut self.chunk)) {
+                0 => break,
+                len => self.buf.extend_from_slice(&self.chunk[..len]),
+            }
+        }
+        let len = if self.buf.len() < buf.len() { self.buf.len() } else { buf.len() };
+        buf[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Reads whole output with buffer of `read_len` bytes, returns time of the fastest round.
+fn read_through<R: Read, F: Fn() -> R>(stream: F, read_len: usize) -> Duration {
+    let mut buf = vec![0; read_len];
+    let mut fastest = None;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        let mut stream = stream();
+        let mut total = 0;
+        loop {
+            match stream.read(&mut buf).unwrap() {
+                0 => break,
+                len => total += len,
+            }
+        }
+        assert_eq!(CHUNK_LEN * CHUNKS, total);
+        let elapsed = started.elapsed();
+        if fastest.map_or(true, |fastest| elapsed < fastest) {
+            fastest = Some(elapsed);
+        }
+    }
+    fastest.unwrap()
+}
+
+fn print(name: &str, read_len: usize, elapsed: Duration) {
+    let output_mib = (CHUNK_LEN * CHUNKS) as f64 / (1024.0 * 1024.0);
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    println!("{:<20} read {:>8} bytes: {:>10.3} ms, {:>8.1} MiB/s", name, read_len, secs * 1000.0, output_mib / secs);
+}
+
+fn main() {
+    let funs: HashMap<&str, &Function<Value>> = HashMap::new();
+
+    let mut i = Interpreter::new();
+    i.set_optimizer(Optimizer::empty());
+    let interpreted = i.build("stream", template(), &funs).unwrap();
+
+    let mut c = Compiler::new();
+    c.set_optimizer(Optimizer::empty());
+    let compiled = c.build("stream", template(), &funs).unwrap();
+
+    let output_mib = (CHUNK_LEN * CHUNKS) as f64 / (1024.0 * 1024.0);
+    println!("output of {} MiB, fastest of {} rounds", output_mib, ROUNDS);
+    for &read_len in &[16, 256, 4 * 1024, 64 * 1024, 1024 * 1024] {
+        print("interpreter", read_len, read_through(|| interpreted.execute(Value::default()), read_len));
+        print("interpreter, drain", read_len, read_through(|| DrainStream::new(interpreted.execute(Value::default())), read_len));
+        print("compiler", read_len, read_through(|| compiled.execute(Value::default()), read_len));
+        print("compiler, drain", read_len, read_through(|| DrainStream::new(compiled.execute(Value::default())), read_len));
+    }
+}
@@ -1,10 +1,11 @@
 extern crate little;
 
 use std::collections::HashMap;
-use std::io::{ Read, Write };
+use std::io::{ self, Read, Write };
 use std::fmt;
 
 use little::*;
+use little::bytecode::{ self, ValueSerializer, Serializer };
 use little::interpreter::Interpreter;
 
 /// Simple value implementation.
@@ -31,12 +32,33 @@ impl IdentifyValue for Value {
     }
 }
 
+/// Optional, serialization is only needed to store built templates in cache.
+impl ValueSerializer for Value {
+    fn serialize_value<O: io::Write>(&self, writer: &mut O) -> Result<u64, bytecode::Error> {
+        match *self {
+            Value::Null => "".to_string().serialize(writer),
+            Value::Str(ref s) => s.serialize(writer),
+        }
+    }
+
+    fn deserialize_value<I: io::Read>(reader: &mut I) -> Result<(u64, Value), bytecode::Error> {
+        let (len, s) = try!(String::deserialize(reader));
+        Ok((len, if s.is_empty() { Value::Null } else { Value::Str(s) }))
+    }
+}
+
 impl GetProperty<Value> for Value {
     fn get_property(&self, name: Value) -> Option<Value> {
         unreachable!();
     }
 }
 
+/// Values that can not be iterated in `for` loops can use default implementation.
+impl IterateValue<Value> for Value { }
+
+/// The same for values that do not support operators.
+impl OperateValue<Value> for Value { }
+
 /// And also requires Default trait.
 impl Default for Value {
     fn default() -> Value {
@@ -77,7 +99,7 @@ fn main() {
             // Call function mapped to 0 with 2 arguments and put the return value in stack.
             Instruction::Call { call: Call(0), argc: 2, push_result_to_stack: true },
             // Result is on the stack, output the stack top.
-            Instruction::Output { location: Mem::StackTop1 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
         ])
         // Map "join" function to 0. Actual function will be received when interpreter is
         // constructed.
@@ -0,0 +1,664 @@
+/*!
+Text form of templates.
+
+`disassemble` prints any `Template` as text, and `assemble` parses it back,
+so that the same template is produced:
+
+```text
+.bindings 2
+
+.call 0 "nth"
+
+.const 0 0x020500000000000000576f726c64 ; "World"
+
+    push params
+@l1:
+    cjump.eq @l5, const 0
+    push binding 0
+    call 0, 2
+    output top1
+    pop 1
+    jump @l1
+@l5:
+    pop 1
+```
+
+Every line contains a directive, a label or an instruction. Text after `;`
+is a comment.
+
+Directives describe tables: `.bindings <capacity>`, `.call <index> "<name>"`
+and `.const <index> 0x<bytes>`, where constant bytes are the value written
+with `ValueSerializer`. Blocks are listed as `.block <pc> "<name>"`, followed
+by `.super <pc> "<name>"` for every `super` call of the block.
+
+Source map is written as `.spans <count>` with the length of span table,
+`.file <n> "<name>"` for every source file and `.span <pc> <file>:<line>:<column>`
+for every instruction that has a span. Spans are checked against the instructions
+of the whole text, so they can not reach past the last instruction.
+
+Labels are written as `@name:` and mark the position of the next instruction.
+Jump targets are either `@name` labels or instruction numbers.
+
+Memory locations are `const <n>`, `binding <n>`, `param <n>` (parameter named by
+constant `n`), `params`, `top1` and `top2` (top and second from top stack values).
+
+Instructions are `output <mem>` (`output.<none|html|attr|json|url> <mem>` with
+escaping other than inherited), `property <mem>`, `push <mem>`, `pop <times>`,
+`jump <target>`, `cjump.<eq|ne|gt|lt|gte|lte> <target>, <mem>`,
+`call <n>, <argc>` (result is pushed to stack), `call.void <n>, <argc>`,
+`load binding <n>, <mem>`, `iter.start <mem>`, `iter.next <target>, binding <n>`,
+`op.<add|sub|mul|div|rem> <mem>`, `neg`, `not`, `cmp.<eq|ne|gt|lt|gte|lte> <mem>`,
+`include <name mem>, <mem>`, `call.local <target>`, `return` and `interupt <code>`.
+*/
+
+use std::collections::{ BTreeSet, HashMap };
+use std::fmt;
+use std::fmt::Write;
+use std::io::Cursor;
+
+use bytecode::{ self, ValueSerializer };
+use {
+    AsmError,
+    AsmErrorKind,
+    Binding,
+    Block,
+    Call,
+    Cond,
+    Constant,
+    Escape,
+    Instruction,
+    Mem,
+    Operator,
+    SourceMap,
+    SourceSpan,
+    Template,
+};
+
+/// Print template as text.
+///
+/// Fails only if a constant value can not be serialized.
+pub fn disassemble<V: ValueSerializer + fmt::Display>(template: &Template<V>) -> Result<String, bytecode::Error> {
+    let mut out = String::new();
+
+    writeln!(out, ".bindings {}", template.bindings_capacity).unwrap();
+
+    let mut calls: Vec<_> = template.calls_template.iter()
+        .map(|(name, &Call(call))| (call, name))
+        .collect();
+    calls.sort();
+    if !calls.is_empty() {
+        out.push('\n');
+    }
+    for (call, name) in calls {
+        writeln!(out, ".call {} {:?}", call, name).unwrap();
+    }
+
+    let mut constants: Vec<_> = template.constants.iter().collect();
+    constants.sort_by_key(|&(&Constant(constant), _)| constant);
+    if !constants.is_empty() {
+        out.push('\n');
+    }
+    for (&Constant(constant), value) in constants {
+        let mut bytes = Vec::new();
+        try!(value.serialize_value(&mut bytes));
+        write!(out, ".const {} 0x", constant).unwrap();
+        for b in bytes {
+            write!(out, "{:02x}", b).unwrap();
+        }
+        writeln!(out, " ; {:?}", value.to_string()).unwrap();
+    }
+
+    if !template.blocks.is_empty() {
+        out.push('\n');
+    }
+    for block in &template.blocks {
+        writeln!(out, ".block {} {:?}", block.pc, block.name).unwrap();
+        for pc in &block.super_calls {
+            writeln!(out, ".super {} {:?}", pc, block.name).unwrap();
+        }
+    }
+
+    if let Some(ref source_map) = template.source_map {
+        writeln!(out, "\n.spans {}", source_map.spans.len()).unwrap();
+        for (index, file) in source_map.files.iter().enumerate() {
+            writeln!(out, ".file {} {:?}", index, file).unwrap();
+        }
+        for (pc, span) in source_map.spans.iter().enumerate() {
+            if let Some(span) = *span {
+                writeln!(out, ".span {} {}:{}:{}", pc, span.file, span.line, span.column).unwrap();
+            }
+        }
+    }
+
+    let len = template.instructions.len();
+    let mut labels = BTreeSet::new();
+    for instruction in &template.instructions {
+        match *instruction {
+            Instruction::Jump { pc }
+            | Instruction::CondJump { pc, .. }
+            | Instruction::IterNext { pc, .. }
+            | Instruction::CallLocal { pc } if pc as usize <= len => {
+                labels.insert(pc as usize);
+            },
+            _ => (),
+        }
+    }
+
+    if len > 0 || !labels.is_empty() {
+        out.push('\n');
+    }
+    for (pc, instruction) in template.instructions.iter().enumerate() {
+        if labels.contains(&pc) {
+            writeln!(out, "@l{}:", pc).unwrap();
+        }
+        writeln!(out, "    {}", InstructionText { instruction: instruction, len: len }).unwrap();
+    }
+    if labels.contains(&len) {
+        writeln!(out, "@l{}:", len).unwrap();
+    }
+
+    Ok(out)
+}
+
+/// Parse template from text.
+pub fn assemble<V: ValueSerializer>(source: &str) -> Result<Template<V>, AsmError> {
+    let mut template = Template::empty();
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut jumps: Vec<(usize, &str, usize)> = Vec::new();
+    let mut spans: Vec<(SpanDirective, usize)> = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let text = strip_comment(line).trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        if text.starts_with('@') && text.ends_with(':') {
+            let name = text[1 .. text.len() - 1].trim();
+            if labels.insert(name, template.instructions.len()).is_some() {
+                return Err(AsmError::new(AsmErrorKind::DuplicateLabel(name.into()), line_number));
+            }
+            continue;
+        }
+
+        let (word, rest) = split_word(text);
+        if word.starts_with('.') {
+            let directive = try!(parse_directive(&mut template, word, rest).map_err(|kind| AsmError::new(kind, line_number)));
+            if let Some(directive) = directive {
+                spans.push((directive, line_number));
+            }
+        } else {
+            let (instruction, label) = try!(parse_instruction(word, rest).map_err(|kind| AsmError::new(kind, line_number)));
+            if let Some(label) = label {
+                jumps.push((template.instructions.len(), label, line_number));
+            }
+            template.push_instruction(instruction);
+        }
+    }
+
+    for (index, label, line_number) in jumps {
+        let target = match labels.get(label) {
+            Some(&target) => target,
+            None => return Err(AsmError::new(AsmErrorKind::UndefinedLabel(label.into()), line_number)),
+        };
+        if target > u16::MAX as usize {
+            return Err(AsmError::new(AsmErrorKind::TooManyInstructions, line_number));
+        }
+        match template.instructions[index] {
+            Instruction::Jump { ref mut pc }
+            | Instruction::CondJump { ref mut pc, .. }
+            | Instruction::IterNext { ref mut pc, .. }
+            | Instruction::CallLocal { ref mut pc } => *pc = target as u16,
+            _ => unreachable!(),
+        }
+    }
+
+    let len = template.instructions.len();
+    for (directive, line_number) in spans {
+        let source_map = source_map(&mut template);
+        match directive {
+            SpanDirective::Len(count) => {
+                if count > len {
+                    return Err(AsmError::new(AsmErrorKind::SpanOutOfBounds { pc: count - 1, len: len }, line_number));
+                }
+                source_map.spans.resize(count, None);
+            },
+            SpanDirective::Span(pc, span) => {
+                if pc >= len {
+                    return Err(AsmError::new(AsmErrorKind::SpanOutOfBounds { pc: pc, len: len }, line_number));
+                }
+                if source_map.spans.len() <= pc {
+                    source_map.spans.resize(pc + 1, None);
+                }
+                source_map.spans[pc] = Some(span);
+            },
+        }
+    }
+
+    Ok(template)
+}
+
+/// Source map directive, applied when the number of instructions is known.
+enum SpanDirective {
+    /// `.spans <count>`.
+    Len(usize),
+    /// `.span <pc> <file>:<line>:<column>`.
+    Span(usize, SourceSpan),
+}
+
+struct InstructionText<'a> {
+    instruction: &'a Instruction,
+    len: usize,
+}
+
+impl<'a> InstructionText<'a> {
+    fn fmt_target(&self, f: &mut fmt::Formatter, pc: u16) -> fmt::Result {
+        if pc as usize <= self.len {
+            write!(f, "@l{}", pc)
+        } else {
+            write!(f, "{}", pc)
+        }
+    }
+}
+
+impl<'a> fmt::Display for InstructionText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.instruction {
+            Instruction::Output { location, escape: Escape::Inherit } => write!(f, "output {}", MemText(location)),
+            Instruction::Output { location, escape } => write!(f, "output.{} {}", escape.name(), MemText(location)),
+            Instruction::Property { name } => write!(f, "property {}", MemText(name)),
+            Instruction::Push { location } => write!(f, "push {}", MemText(location)),
+            Instruction::Pop { times } => write!(f, "pop {}", times),
+            Instruction::Jump { pc } => {
+                try!(write!(f, "jump "));
+                self.fmt_target(f, pc)
+            },
+            Instruction::CondJump { pc, location, test } => {
+                try!(write!(f, "cjump.{} ", cond_name(test)));
+                try!(self.fmt_target(f, pc));
+                write!(f, ", {}", MemText(location))
+            },
+            Instruction::Call { call: Call(call), argc, push_result_to_stack: true } => write!(f, "call {}, {}", call, argc),
+            Instruction::Call { call: Call(call), argc, push_result_to_stack: false } => write!(f, "call.void {}, {}", call, argc),
+            Instruction::Load { binding: Binding(binding), location } => write!(f, "load binding {}, {}", binding, MemText(location)),
+            Instruction::Interupt { code } => write!(f, "interupt {}", code),
+            Instruction::IterStart { location } => write!(f, "iter.start {}", MemText(location)),
+            Instruction::IterNext { binding: Binding(binding), pc } => {
+                try!(write!(f, "iter.next "));
+                try!(self.fmt_target(f, pc));
+                write!(f, ", binding {}", binding)
+            },
+            Instruction::Operate { op, location } => write!(f, "op.{} {}", op.name(), MemText(location)),
+            Instruction::Negate => write!(f, "neg"),
+            Instruction::Not => write!(f, "not"),
+            Instruction::Compare { location, test } => write!(f, "cmp.{} {}", cond_name(test), MemText(location)),
+            Instruction::Include { name, location } => write!(f, "include {}, {}", MemText(name), MemText(location)),
+            Instruction::CallLocal { pc } => {
+                try!(write!(f, "call.local "));
+                self.fmt_target(f, pc)
+            },
+            Instruction::Return => write!(f, "return"),
+        }
+    }
+}
+
+struct MemText(Mem);
+
+impl fmt::Display for MemText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Mem::Const(Constant(c)) => write!(f, "const {}", c),
+            Mem::Binding(Binding(b)) => write!(f, "binding {}", b),
+            Mem::Parameter { name: Constant(c) } => write!(f, "param {}", c),
+            Mem::Parameters => write!(f, "params"),
+            Mem::StackTop1 => write!(f, "top1"),
+            Mem::StackTop2 => write!(f, "top2"),
+        }
+    }
+}
+
+fn cond_name(cond: Cond) -> &'static str {
+    match cond {
+        Cond::Eq => "eq",
+        Cond::Ne => "ne",
+        Cond::Gt => "gt",
+        Cond::Lt => "lt",
+        Cond::Gte => "gte",
+        Cond::Lte => "lte",
+    }
+}
+
+fn parse_directive<V: ValueSerializer>(template: &mut Template<V>, word: &str, rest: &str) -> Result<Option<SpanDirective>, AsmErrorKind> {
+    match word {
+        ".bindings" => {
+            template.bindings_capacity = try!(parse_number(rest));
+        },
+        ".call" => {
+            let (index, name) = split_word(rest);
+            let name = match parse_string(name) {
+                Some(name) => name,
+                None => return Err(AsmErrorKind::InvalidOperand(name.into())),
+            };
+            template.calls_template.push(name, Call(try!(parse_number(index))));
+        },
+        ".const" => {
+            let (index, bytes) = split_word(rest);
+            let index = Constant(try!(parse_number(index)));
+            let bytes = match parse_hex(bytes) {
+                Some(bytes) => bytes,
+                None => return Err(AsmErrorKind::InvalidOperand(bytes.into())),
+            };
+            let mut input = Cursor::new(&bytes[..]);
+            match V::deserialize_value(&mut input) {
+                Ok((_, value)) if input.position() == bytes.len() as u64 => {
+                    template.push_constant(index, value);
+                },
+                _ => return Err(AsmErrorKind::InvalidValue),
+            }
+        },
+        ".block" | ".super" => {
+            let (pc, name) = split_word(rest);
+            let pc = try!(parse_number(pc));
+            let name = match parse_string(name) {
+                Some(name) => name,
+                None => return Err(AsmErrorKind::InvalidOperand(name.into())),
+            };
+            if word == ".block" {
+                template.blocks.push(Block::new(name, pc));
+            } else {
+                match template.blocks.iter_mut().find(|b| b.name == name) {
+                    Some(block) => block.super_calls.push(pc),
+                    None => return Err(AsmErrorKind::InvalidOperand(name)),
+                }
+            }
+        },
+        ".spans" => {
+            return Ok(Some(SpanDirective::Len(try!(parse_number(rest)))));
+        },
+        ".file" => {
+            let (index, name) = split_word(rest);
+            let index: usize = try!(parse_number(index));
+            let name = match parse_string(name) {
+                Some(name) => name,
+                None => return Err(AsmErrorKind::InvalidOperand(name.into())),
+            };
+            let source_map = source_map(template);
+            if index != source_map.files.len() {
+                return Err(AsmErrorKind::InvalidOperand(rest.into()));
+            }
+            source_map.files.push(name);
+        },
+        ".span" => {
+            let (pc, location) = split_word(rest);
+            let pc: usize = try!(parse_number(pc));
+            let parts: Vec<&str> = location.split(':').collect();
+            if parts.len() != 3 {
+                return Err(AsmErrorKind::InvalidOperand(location.into()));
+            }
+            let span = SourceSpan::new(
+                try!(parse_number(parts[0])),
+                try!(parse_number(parts[1])),
+                try!(parse_number(parts[2]))
+            );
+            return Ok(Some(SpanDirective::Span(pc, span)));
+        },
+        _ => return Err(AsmErrorKind::UnknownDirective(word.into())),
+    };
+    Ok(None)
+}
+
+/// Returns template source map, creating it if template has none.
+fn source_map<V>(template: &mut Template<V>) -> &mut SourceMap {
+    if template.source_map.is_none() {
+        template.source_map = Some(SourceMap::new());
+    }
+    template.source_map.as_mut().unwrap()
+}
+
+/// Parses instruction, also returns label name if its jump target is a label.
+fn parse_instruction<'s>(word: &str, rest: &'s str) -> Result<(Instruction, Option<&'s str>), AsmErrorKind> {
+    let operands: Vec<&str> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').map(|o| o.trim()).collect()
+    };
+    let expect = |expected: usize| if operands.len() == expected {
+        Ok(())
+    } else {
+        Err(AsmErrorKind::OperandCount { expected: expected, found: operands.len() })
+    };
+
+    let mut label = None;
+    let instruction = match word {
+        "output" => {
+            try!(expect(1));
+            Instruction::Output { location: try!(parse_mem(operands[0])), escape: Escape::Inherit }
+        },
+        "property" => {
+            try!(expect(1));
+            Instruction::Property { name: try!(parse_mem(operands[0])) }
+        },
+        "push" => {
+            try!(expect(1));
+            Instruction::Push { location: try!(parse_mem(operands[0])) }
+        },
+        "pop" => {
+            try!(expect(1));
+            Instruction::Pop { times: try!(parse_number(operands[0])) }
+        },
+        "jump" => {
+            try!(expect(1));
+            let (pc, target_label) = try!(parse_target(operands[0]));
+            label = target_label;
+            Instruction::Jump { pc: pc }
+        },
+        "call" | "call.void" => {
+            try!(expect(2));
+            Instruction::Call {
+                call: Call(try!(parse_number(operands[0]))),
+                argc: try!(parse_number(operands[1])),
+                push_result_to_stack: word == "call",
+            }
+        },
+        "load" => {
+            try!(expect(2));
+            let binding = match try!(parse_mem(operands[0])) {
+                Mem::Binding(binding) => binding,
+                _ => return Err(AsmErrorKind::InvalidOperand(operands[0].into())),
+            };
+            Instruction::Load { binding: binding, location: try!(parse_mem(operands[1])) }
+        },
+        "interupt" => {
+            try!(expect(1));
+            Instruction::Interupt { code: try!(parse_number(operands[0])) }
+        },
+        "iter.start" => {
+            try!(expect(1));
+            Instruction::IterStart { location: try!(parse_mem(operands[0])) }
+        },
+        "iter.next" => {
+            try!(expect(2));
+            let (pc, target_label) = try!(parse_target(operands[0]));
+            label = target_label;
+            let binding = match try!(parse_mem(operands[1])) {
+                Mem::Binding(binding) => binding,
+                _ => return Err(AsmErrorKind::InvalidOperand(operands[1].into())),
+            };
+            Instruction::IterNext { binding: binding, pc: pc }
+        },
+        "call.local" => {
+            try!(expect(1));
+            let (pc, target_label) = try!(parse_target(operands[0]));
+            label = target_label;
+            Instruction::CallLocal { pc: pc }
+        },
+        "return" => {
+            try!(expect(0));
+            Instruction::Return
+        },
+        "include" => {
+            try!(expect(2));
+            Instruction::Include { name: try!(parse_mem(operands[0])), location: try!(parse_mem(operands[1])) }
+        },
+        "neg" => {
+            try!(expect(0));
+            Instruction::Negate
+        },
+        "not" => {
+            try!(expect(0));
+            Instruction::Not
+        },
+        _ if word.starts_with("op.") => {
+            let op = match Operator::from_name(&word["op.".len() ..]) {
+                Some(op) => op,
+                None => return Err(AsmErrorKind::UnknownInstruction(word.into())),
+            };
+            try!(expect(1));
+            Instruction::Operate { op: op, location: try!(parse_mem(operands[0])) }
+        },
+        _ if word.starts_with("output.") => {
+            let escape = match Escape::from_name(&word["output.".len() ..]) {
+                Some(Escape::Inherit) | None => return Err(AsmErrorKind::UnknownInstruction(word.into())),
+                Some(escape) => escape,
+            };
+            try!(expect(1));
+            Instruction::Output { location: try!(parse_mem(operands[0])), escape: escape }
+        },
+        _ if word.starts_with("cmp.") => {
+            let test = match parse_cond(&word["cmp.".len() ..]) {
+                Some(test) => test,
+                None => return Err(AsmErrorKind::UnknownInstruction(word.into())),
+            };
+            try!(expect(1));
+            Instruction::Compare { location: try!(parse_mem(operands[0])), test: test }
+        },
+        _ if word.starts_with("cjump.") => {
+            let test = match parse_cond(&word["cjump.".len() ..]) {
+                Some(test) => test,
+                None => return Err(AsmErrorKind::UnknownInstruction(word.into())),
+            };
+            try!(expect(2));
+            let (pc, target_label) = try!(parse_target(operands[0]));
+            label = target_label;
+            Instruction::CondJump { pc: pc, location: try!(parse_mem(operands[1])), test: test }
+        },
+        _ => return Err(AsmErrorKind::UnknownInstruction(word.into())),
+    };
+
+    Ok((instruction, label))
+}
+
+fn parse_cond(name: &str) -> Option<Cond> {
+    Some(match name {
+        "eq" => Cond::Eq,
+        "ne" => Cond::Ne,
+        "gt" => Cond::Gt,
+        "lt" => Cond::Lt,
+        "gte" => Cond::Gte,
+        "lte" => Cond::Lte,
+        _ => return None,
+    })
+}
+
+fn parse_target(operand: &str) -> Result<(u16, Option<&str>), AsmErrorKind> {
+    if operand.starts_with('@') {
+        Ok((0, Some(&operand[1..])))
+    } else {
+        Ok((try!(parse_number(operand)), None))
+    }
+}
+
+fn parse_mem(operand: &str) -> Result<Mem, AsmErrorKind> {
+    let (word, rest) = split_word(operand);
+    Ok(match (word, rest.is_empty()) {
+        ("const", false) => Mem::Const(Constant(try!(parse_number(rest)))),
+        ("binding", false) => Mem::Binding(Binding(try!(parse_number(rest)))),
+        ("param", false) => Mem::Parameter { name: Constant(try!(parse_number(rest))) },
+        ("params", true) => Mem::Parameters,
+        ("top1", true) => Mem::StackTop1,
+        ("top2", true) => Mem::StackTop2,
+        _ => return Err(AsmErrorKind::InvalidOperand(operand.into())),
+    })
+}
+
+fn parse_number<N: ::std::str::FromStr>(operand: &str) -> Result<N, AsmErrorKind> {
+    operand.parse().map_err(|_| AsmErrorKind::InvalidOperand(operand.into()))
+}
+
+fn parse_hex(operand: &str) -> Option<Vec<u8>> {
+    if !operand.starts_with("0x") || operand.len() % 2 != 0 {
+        return None;
+    }
+    let digits = operand[2..].as_bytes();
+    let mut bytes = Vec::with_capacity(digits.len() / 2);
+    for pair in digits.chunks(2) {
+        let hi = (pair[0] as char).to_digit(16);
+        let lo = (pair[1] as char).to_digit(16);
+        match (hi, lo) {
+            (Some(hi), Some(lo)) => bytes.push((hi * 16 + lo) as u8),
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+/// Parses string literal in the format written by `{:?}`.
+fn parse_string(operand: &str) -> Option<String> {
+    if operand.len() < 2 || !operand.starts_with('"') || !operand.ends_with('"') {
+        return None;
+    }
+    let mut result = String::new();
+    let mut chars = operand[1 .. operand.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.push(match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('\'') => '\'',
+                Some('u') => {
+                    if chars.next() != Some('{') {
+                        return None;
+                    }
+                    let hex: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    match u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32) {
+                        Some(c) => c,
+                        None => return None,
+                    }
+                },
+                _ => return None,
+            }),
+            '"' => return None,
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// Removes `;` comment, ignoring `;` inside string literals.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    }
+}
@@ -0,0 +1,119 @@
+//! Output buffer of streams.
+
+use std::io::{ self, Write };
+
+/// Bytes written by instructions but not yet read.
+///
+/// Reading advances `start` instead of moving remaining bytes to the front.
+/// Read bytes are reclaimed when the buffer is empty, or on write when they
+/// take at least half of the buffer, so every byte is moved at most once
+/// and memory is bounded by the largest single write plus unread bytes.
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    data: Vec<u8>,
+    start: usize,
+}
+
+impl OutputBuffer {
+    pub fn new() -> OutputBuffer {
+        OutputBuffer {
+            data: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Number of unread bytes.
+    pub fn len(&self) -> usize {
+        self.data.len() - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Unread bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.start..]
+    }
+
+    /// Copies unread bytes to `buf`, returns the number of copied bytes.
+    pub fn read_into(&mut self, buf: &mut [u8]) -> usize {
+        let len = if self.len() < buf.len() { self.len() } else { buf.len() };
+        buf[..len].copy_from_slice(&self.data[self.start .. self.start + len]);
+        self.consume(len);
+        len
+    }
+
+    /// Marks `len` bytes as read.
+    pub fn consume(&mut self, len: usize) {
+        self.start += len;
+        if self.start >= self.data.len() {
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.start = 0;
+    }
+
+    /// Moves unread bytes to the front if read bytes take at least half of the buffer.
+    fn reclaim(&mut self) {
+        if self.start > 0 && self.start * 2 >= self.data.len() {
+            let len = self.len();
+            let start = self.start;
+            self.data.copy_within(start.., 0);
+            self.data.truncate(len);
+            self.start = 0;
+        }
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.reclaim();
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use super::*;
+
+    #[test]
+    fn test_reads_in_order() {
+        let mut buffer = OutputBuffer::new();
+        buffer.write_all(b"hello ").unwrap();
+        buffer.write_all(b"world").unwrap();
+
+        let mut buf = [0; 4];
+        let mut output = Vec::new();
+        loop {
+            let len = buffer.read_into(&mut buf);
+            if len == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..len]);
+        }
+
+        assert_eq!(b"hello world", &output[..]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_reclaims_read_bytes() {
+        let mut buffer = OutputBuffer::new();
+        buffer.write_all(b"abcdef").unwrap();
+        buffer.read_into(&mut [0; 4]);
+        buffer.write_all(b"gh").unwrap();
+
+        assert_eq!(0, buffer.start);
+        assert_eq!(b"efgh", buffer.as_slice());
+    }
+}
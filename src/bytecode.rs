@@ -1,14 +1,82 @@
 /*!
 Bytecode `io` helpers.
+
+Templates can be written to `.little` files at deploy time and later
+read back without parsing the template source:
+
+```ignore
+use little::bytecode;
+
+bytecode::write_file("page.little", &template).unwrap();
+let template: Template<Value> = bytecode::read_file("page.little").unwrap();
+```
+
+Bytecode starts with a `Header` that contains `FORMAT_VERSION`, files written
+in other versions fail to load with `Error::UnsupportedVersion`.
 */
 
+use std::fs;
 use std::io;
-use std::mem;
+use std::io::Read;
+use std::path::Path;
+use std::fmt;
+use std::error;
 use byteorder::{ self, LittleEndian, ReadBytesExt, WriteBytesExt };
 
+use {
+    Binding,
+    Block,
+    Call,
+    Constant,
+    Cond,
+    Escape,
+    Fingerprint,
+    Instruction,
+    Mem,
+    Operator,
+    SourceMap,
+    SourceSpan,
+    Template,
+};
+
 /// Bytecode representation.
-pub trait Bytecode {
-    
+///
+/// Structure written together with bytecode `Header`.
+pub trait Bytecode: Serializer {
+    /// Write header and contents to `writer`, returns bytes written.
+    fn write_bytecode<O: io::Write>(&self, writer: &mut O) -> Result<u64, Error> {
+        let header_len = try!(Header::new().serialize(writer));
+        Ok(header_len + try!(self.serialize(writer)))
+    }
+
+    /// Read header and contents from `reader`, fails if header is not valid
+    /// or bytecode was written in other format version.
+    fn read_bytecode<I: io::Read>(reader: &mut I) -> Result<Self, Error> where Self: Sized {
+        let (_, header) = try!(Header::deserialize(reader));
+        try!(header.check());
+        let (_, contents) = try!(Self::deserialize(reader));
+        Ok(contents)
+    }
+}
+
+/// Returns true if `bytes` start with bytecode `Header`.
+pub fn has_header(bytes: &[u8]) -> bool {
+    match Header::deserialize(&mut io::Cursor::new(bytes)) {
+        Ok((_, header)) => header.is_magical(),
+        Err(_) => false,
+    }
+}
+
+/// Write bytecode to file at `path`.
+pub fn write_file<B: Bytecode, P: AsRef<Path>>(path: P, bytecode: &B) -> Result<u64, Error> {
+    let mut file = io::BufWriter::new(try!(fs::File::create(path)));
+    bytecode.write_bytecode(&mut file)
+}
+
+/// Read bytecode from file at `path`.
+pub fn read_file<B: Bytecode, P: AsRef<Path>>(path: P) -> Result<B, Error> {
+    let mut file = io::BufReader::new(try!(fs::File::open(path)));
+    B::read_bytecode(&mut file)
 }
 
 /// Serialize and deserialize a structure from `io`.
@@ -19,15 +87,51 @@ pub trait Serializer {
     fn deserialize<I: io::Read>(reader: &mut I) -> Result<(u64, Self), Error> where Self: Sized;
 }
 
+/// Serialize and deserialize a template value from `io`.
+///
+/// Required to store template constants in bytecode.
+pub trait ValueSerializer {
+    /// Write value to io `writer`, returns bytes written.
+    fn serialize_value<O: io::Write>(&self, writer: &mut O) -> Result<u64, Error>;
+    /// Read value from `reader`, return tuple of bytes read and new value.
+    fn deserialize_value<I: io::Read>(reader: &mut I) -> Result<(u64, Self), Error> where Self: Sized;
+}
+
 /// Bytecode read/write error.
 #[derive(Debug)]
 pub enum Error {
     /// Failed to read cache header, assume this is not valid cache file.
     InvalidBinaryFormat,
+    /// Bytecode was written in format version other than `FORMAT_VERSION`.
+    ///
+    /// Files written before format was versioned report version `0`.
+    UnsupportedVersion { found: u32, expected: u32 },
     UnexpectedEOF,
     Io(io::Error),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidBinaryFormat => write!(f, "Invalid binary format"),
+            Error::UnsupportedVersion { found, expected } => write!(f, "Unsupported bytecode version {}, expected {}", found, expected),
+            Error::UnexpectedEOF => write!(f, "Unexpected end of file"),
+            Error::Io(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidBinaryFormat => "invalid binary format",
+            Error::UnsupportedVersion { .. } => "unsupported bytecode version",
+            Error::UnexpectedEOF => "unexpected end of file",
+            Error::Io(_) => "io error",
+        }
+    }
+}
+
 impl From<byteorder::Error> for Error {
     fn from(other: byteorder::Error) -> Error {
         match other {
@@ -43,43 +147,561 @@ impl From<io::Error> for Error {
     }
 }
 
+/// Version of bytecode layout, written in `Header`.
+///
+/// Increment it whenever serialized layout of any structure changes,
+/// so that files written before the change are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Magic number of files written before bytecode had a format version.
+const UNVERSIONED_MAGIC: u32 = 52231103;
+
 /// Bytecode file header.
 #[derive(Eq, PartialEq, Debug)]
 pub struct Header {
     magic: u32,
+    version: u32,
 }
 
 impl Header {
     pub fn new() -> Header {
         Header {
             magic: Header::magic(),
+            version: FORMAT_VERSION,
         }
     }
 
     /// Check if header is valid.
+    ///
+    /// Headers of files written before format was versioned are also valid.
     pub fn is_magical(&self) -> bool {
-        self.magic == Header::magic()
+        self.magic == Header::magic() || self.magic == UNVERSIONED_MAGIC
+    }
+
+    /// Format version of bytecode that follows this header.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Fails if header is not valid or its version is not `FORMAT_VERSION`.
+    pub fn check(&self) -> Result<(), Error> {
+        if !self.is_magical() {
+            return Err(Error::InvalidBinaryFormat);
+        }
+        if self.version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion { found: self.version, expected: FORMAT_VERSION });
+        }
+        Ok(())
     }
 
     /// Return magic header number.
     fn magic() -> u32 {
-        52231103
+        52231104
     }
 }
 
+/// Unversioned header is only the magic number, it is read as version `0`.
 impl Serializer for Header {
     fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
         try!(output.write_u32::<LittleEndian>(self.magic));
-        Ok(mem::size_of::<Header>() as u64)
+        try!(output.write_u32::<LittleEndian>(self.version));
+        Ok(8)
     }
 
     fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Header), Error> {
-        Ok((mem::size_of::<Header>() as u64, Header {
-            magic: try!(input.read_u32::<LittleEndian>())
+        let magic = try!(input.read_u32::<LittleEndian>());
+        if magic != Header::magic() {
+            return Ok((4, Header { magic: magic, version: 0 }));
+        }
+        Ok((8, Header {
+            magic: magic,
+            version: try!(input.read_u32::<LittleEndian>()),
         }))
     }
 }
 
+impl Serializer for String {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        try!(output.write_u32::<LittleEndian>(self.len() as u32));
+        try!(output.write_all(self.as_bytes()));
+        Ok(4 + self.len() as u64)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, String), Error> {
+        let len = try!(input.read_u32::<LittleEndian>()) as u64;
+        let mut bytes = Vec::new();
+        if try!(input.take(len).read_to_end(&mut bytes)) as u64 != len {
+            return Err(Error::UnexpectedEOF);
+        }
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok((4 + len, value)),
+            Err(_) => Err(Error::InvalidBinaryFormat),
+        }
+    }
+}
+
+impl Serializer for Fingerprint {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        try!(output.write_all(&self.0));
+        Ok(20)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Fingerprint), Error> {
+        let mut inner = [0; 20];
+        let mut read = 0;
+        while read < inner.len() {
+            match try!(input.read(&mut inner[read..])) {
+                0 => return Err(Error::UnexpectedEOF),
+                len => read += len,
+            }
+        }
+        Ok((20, Fingerprint::new(inner)))
+    }
+}
+
+impl Serializer for Mem {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        Ok(match *self {
+            Mem::Const(Constant(c)) => {
+                try!(output.write_u8(0));
+                try!(output.write_u32::<LittleEndian>(c));
+                5
+            },
+            Mem::Binding(Binding(b)) => {
+                try!(output.write_u8(1));
+                try!(output.write_u32::<LittleEndian>(b));
+                5
+            },
+            Mem::Parameter { name: Constant(c) } => {
+                try!(output.write_u8(2));
+                try!(output.write_u32::<LittleEndian>(c));
+                5
+            },
+            Mem::Parameters => { try!(output.write_u8(3)); 1 },
+            Mem::StackTop1 => { try!(output.write_u8(4)); 1 },
+            Mem::StackTop2 => { try!(output.write_u8(5)); 1 },
+        })
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Mem), Error> {
+        Ok(match try!(input.read_u8()) {
+            0 => (5, Mem::Const(Constant(try!(input.read_u32::<LittleEndian>())))),
+            1 => (5, Mem::Binding(Binding(try!(input.read_u32::<LittleEndian>())))),
+            2 => (5, Mem::Parameter { name: Constant(try!(input.read_u32::<LittleEndian>())) }),
+            3 => (1, Mem::Parameters),
+            4 => (1, Mem::StackTop1),
+            5 => (1, Mem::StackTop2),
+            _ => return Err(Error::InvalidBinaryFormat),
+        })
+    }
+}
+
+impl Serializer for Cond {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        try!(output.write_u8(match *self {
+            Cond::Eq => 0,
+            Cond::Ne => 1,
+            Cond::Gt => 2,
+            Cond::Lt => 3,
+            Cond::Gte => 4,
+            Cond::Lte => 5,
+        }));
+        Ok(1)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Cond), Error> {
+        Ok((1, match try!(input.read_u8()) {
+            0 => Cond::Eq,
+            1 => Cond::Ne,
+            2 => Cond::Gt,
+            3 => Cond::Lt,
+            4 => Cond::Gte,
+            5 => Cond::Lte,
+            _ => return Err(Error::InvalidBinaryFormat),
+        }))
+    }
+}
+
+impl Serializer for Operator {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        try!(output.write_u8(match *self {
+            Operator::Add => 0,
+            Operator::Sub => 1,
+            Operator::Mul => 2,
+            Operator::Div => 3,
+            Operator::Rem => 4,
+        }));
+        Ok(1)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Operator), Error> {
+        Ok((1, match try!(input.read_u8()) {
+            0 => Operator::Add,
+            1 => Operator::Sub,
+            2 => Operator::Mul,
+            3 => Operator::Div,
+            4 => Operator::Rem,
+            _ => return Err(Error::InvalidBinaryFormat),
+        }))
+    }
+}
+
+impl Serializer for Escape {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        try!(output.write_u8(match *self {
+            Escape::Inherit => 0,
+            Escape::None => 1,
+            Escape::Html => 2,
+            Escape::HtmlAttribute => 3,
+            Escape::Json => 4,
+            Escape::Url => 5,
+        }));
+        Ok(1)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Escape), Error> {
+        Ok((1, match try!(input.read_u8()) {
+            0 => Escape::Inherit,
+            1 => Escape::None,
+            2 => Escape::Html,
+            3 => Escape::HtmlAttribute,
+            4 => Escape::Json,
+            5 => Escape::Url,
+            _ => return Err(Error::InvalidBinaryFormat),
+        }))
+    }
+}
+
+impl Serializer for Instruction {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        Ok(1 + match *self {
+            Instruction::Output { ref location, ref escape } => {
+                try!(output.write_u8(0));
+                try!(location.serialize(output)) + try!(escape.serialize(output))
+            },
+            Instruction::Property { ref name } => {
+                try!(output.write_u8(1));
+                try!(name.serialize(output))
+            },
+            Instruction::Push { ref location } => {
+                try!(output.write_u8(2));
+                try!(location.serialize(output))
+            },
+            Instruction::Pop { times } => {
+                try!(output.write_u8(3));
+                try!(output.write_u16::<LittleEndian>(times));
+                2
+            },
+            Instruction::Jump { pc } => {
+                try!(output.write_u8(4));
+                try!(output.write_u16::<LittleEndian>(pc));
+                2
+            },
+            Instruction::CondJump { pc, ref location, ref test } => {
+                try!(output.write_u8(5));
+                try!(output.write_u16::<LittleEndian>(pc));
+                2 + try!(location.serialize(output)) + try!(test.serialize(output))
+            },
+            Instruction::Call { call: Call(call), argc, push_result_to_stack } => {
+                try!(output.write_u8(6));
+                try!(output.write_u32::<LittleEndian>(call));
+                try!(output.write_u8(argc));
+                try!(output.write_u8(push_result_to_stack as u8));
+                6
+            },
+            Instruction::Load { binding: Binding(binding), ref location } => {
+                try!(output.write_u8(7));
+                try!(output.write_u32::<LittleEndian>(binding));
+                4 + try!(location.serialize(output))
+            },
+            Instruction::Interupt { code } => {
+                try!(output.write_u8(8));
+                try!(output.write_u16::<LittleEndian>(code));
+                2
+            },
+            Instruction::IterStart { ref location } => {
+                try!(output.write_u8(9));
+                try!(location.serialize(output))
+            },
+            Instruction::IterNext { binding: Binding(binding), pc } => {
+                try!(output.write_u8(10));
+                try!(output.write_u32::<LittleEndian>(binding));
+                try!(output.write_u16::<LittleEndian>(pc));
+                6
+            },
+            Instruction::Operate { ref op, ref location } => {
+                try!(output.write_u8(11));
+                try!(op.serialize(output)) + try!(location.serialize(output))
+            },
+            Instruction::Negate => {
+                try!(output.write_u8(12));
+                0
+            },
+            Instruction::Not => {
+                try!(output.write_u8(13));
+                0
+            },
+            Instruction::Compare { ref location, ref test } => {
+                try!(output.write_u8(14));
+                try!(location.serialize(output)) + try!(test.serialize(output))
+            },
+            Instruction::Include { ref name, ref location } => {
+                try!(output.write_u8(15));
+                try!(name.serialize(output)) + try!(location.serialize(output))
+            },
+            Instruction::CallLocal { pc } => {
+                try!(output.write_u8(16));
+                try!(output.write_u16::<LittleEndian>(pc));
+                2
+            },
+            Instruction::Return => {
+                try!(output.write_u8(17));
+                0
+            },
+        })
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Instruction), Error> {
+        let (len, instruction) = match try!(input.read_u8()) {
+            0 => {
+                let (len, location) = try!(Mem::deserialize(input));
+                let (escape_len, escape) = try!(Escape::deserialize(input));
+                (len + escape_len, Instruction::Output { location: location, escape: escape })
+            },
+            1 => {
+                let (len, name) = try!(Mem::deserialize(input));
+                (len, Instruction::Property { name: name })
+            },
+            2 => {
+                let (len, location) = try!(Mem::deserialize(input));
+                (len, Instruction::Push { location: location })
+            },
+            3 => (2, Instruction::Pop { times: try!(input.read_u16::<LittleEndian>()) }),
+            4 => (2, Instruction::Jump { pc: try!(input.read_u16::<LittleEndian>()) }),
+            5 => {
+                let pc = try!(input.read_u16::<LittleEndian>());
+                let (location_len, location) = try!(Mem::deserialize(input));
+                let (test_len, test) = try!(Cond::deserialize(input));
+                (2 + location_len + test_len, Instruction::CondJump { pc: pc, location: location, test: test })
+            },
+            6 => {
+                let call = Call(try!(input.read_u32::<LittleEndian>()));
+                let argc = try!(input.read_u8());
+                let push_result_to_stack = match try!(input.read_u8()) {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(Error::InvalidBinaryFormat),
+                };
+                (6, Instruction::Call { call: call, argc: argc, push_result_to_stack: push_result_to_stack })
+            },
+            7 => {
+                let binding = Binding(try!(input.read_u32::<LittleEndian>()));
+                let (len, location) = try!(Mem::deserialize(input));
+                (4 + len, Instruction::Load { binding: binding, location: location })
+            },
+            8 => (2, Instruction::Interupt { code: try!(input.read_u16::<LittleEndian>()) }),
+            9 => {
+                let (len, location) = try!(Mem::deserialize(input));
+                (len, Instruction::IterStart { location: location })
+            },
+            10 => {
+                let binding = Binding(try!(input.read_u32::<LittleEndian>()));
+                (6, Instruction::IterNext { binding: binding, pc: try!(input.read_u16::<LittleEndian>()) })
+            },
+            11 => {
+                let (op_len, op) = try!(Operator::deserialize(input));
+                let (len, location) = try!(Mem::deserialize(input));
+                (op_len + len, Instruction::Operate { op: op, location: location })
+            },
+            12 => (0, Instruction::Negate),
+            13 => (0, Instruction::Not),
+            14 => {
+                let (len, location) = try!(Mem::deserialize(input));
+                let (test_len, test) = try!(Cond::deserialize(input));
+                (len + test_len, Instruction::Compare { location: location, test: test })
+            },
+            15 => {
+                let (name_len, name) = try!(Mem::deserialize(input));
+                let (len, location) = try!(Mem::deserialize(input));
+                (name_len + len, Instruction::Include { name: name, location: location })
+            },
+            16 => (2, Instruction::CallLocal { pc: try!(input.read_u16::<LittleEndian>()) }),
+            17 => (0, Instruction::Return),
+            _ => return Err(Error::InvalidBinaryFormat),
+        };
+        Ok((1 + len, instruction))
+    }
+}
+
+/// Constants and calls are written ordered by index, so that the same
+/// template always produces the same bytes.
+impl<V: ValueSerializer> Serializer for Template<V> {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        let mut len = 4;
+        try!(output.write_u32::<LittleEndian>(self.bindings_capacity));
+
+        let mut calls: Vec<_> = self.calls_template.iter().collect();
+        calls.sort_by_key(|&(_, &Call(call))| call);
+        len += 4;
+        try!(output.write_u32::<LittleEndian>(calls.len() as u32));
+        for (name, &Call(call)) in calls {
+            len += try!(name.serialize(output)) + 4;
+            try!(output.write_u32::<LittleEndian>(call));
+        }
+
+        let mut constants: Vec<_> = self.constants.iter().collect();
+        constants.sort_by_key(|&(&Constant(constant), _)| constant);
+        len += 4;
+        try!(output.write_u32::<LittleEndian>(constants.len() as u32));
+        for (&Constant(constant), value) in constants {
+            len += 4;
+            try!(output.write_u32::<LittleEndian>(constant));
+            len += try!(value.serialize_value(output));
+        }
+
+        len += 4;
+        try!(output.write_u32::<LittleEndian>(self.instructions.len() as u32));
+        for instruction in &self.instructions {
+            len += try!(instruction.serialize(output));
+        }
+
+        len += 1;
+        match self.source_map {
+            Some(ref source_map) => {
+                try!(output.write_u8(1));
+                len += try!(source_map.serialize(output));
+            },
+            None => try!(output.write_u8(0)),
+        }
+
+        len += 4;
+        try!(output.write_u32::<LittleEndian>(self.blocks.len() as u32));
+        for block in &self.blocks {
+            len += try!(block.name.serialize(output)) + 2 + 4;
+            try!(output.write_u16::<LittleEndian>(block.pc));
+            try!(output.write_u32::<LittleEndian>(block.super_calls.len() as u32));
+            for &pc in &block.super_calls {
+                len += 2;
+                try!(output.write_u16::<LittleEndian>(pc));
+            }
+        }
+
+        Ok(len)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, Template<V>), Error> {
+        let mut template = Template::empty();
+        let mut len = 4;
+        template.bindings_capacity = try!(input.read_u32::<LittleEndian>());
+
+        len += 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            let (name_len, name) = try!(String::deserialize(input));
+            len += name_len + 4;
+            template.calls_template.push(name, Call(try!(input.read_u32::<LittleEndian>())));
+        }
+
+        len += 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            let constant = Constant(try!(input.read_u32::<LittleEndian>()));
+            let (value_len, value) = try!(V::deserialize_value(input));
+            len += 4 + value_len;
+            template.push_constant(constant, value);
+        }
+
+        len += 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            let (instruction_len, instruction) = try!(Instruction::deserialize(input));
+            len += instruction_len;
+            template.push_instruction(instruction);
+        }
+
+        len += 1;
+        template.source_map = match try!(input.read_u8()) {
+            0 => None,
+            1 => {
+                let (source_map_len, source_map) = try!(SourceMap::deserialize(input));
+                len += source_map_len;
+                Some(source_map)
+            },
+            _ => return Err(Error::InvalidBinaryFormat),
+        };
+
+        len += 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            let (name_len, name) = try!(String::deserialize(input));
+            let mut block = Block::new(name, try!(input.read_u16::<LittleEndian>()));
+            len += name_len + 2 + 4;
+            for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+                len += 2;
+                block.super_calls.push(try!(input.read_u16::<LittleEndian>()));
+            }
+            template.blocks.push(block);
+        }
+
+        Ok((len, template))
+    }
+}
+
+impl Serializer for SourceMap {
+    fn serialize<O: io::Write>(&self, output: &mut O) -> Result<u64, Error> {
+        let mut len = 4;
+        try!(output.write_u32::<LittleEndian>(self.files.len() as u32));
+        for file in &self.files {
+            len += try!(file.serialize(output));
+        }
+
+        len += 4;
+        try!(output.write_u32::<LittleEndian>(self.spans.len() as u32));
+        for span in &self.spans {
+            len += 1;
+            match *span {
+                Some(span) => {
+                    try!(output.write_u8(1));
+                    try!(output.write_u32::<LittleEndian>(span.file));
+                    try!(output.write_u32::<LittleEndian>(span.line));
+                    try!(output.write_u32::<LittleEndian>(span.column));
+                    len += 12;
+                },
+                None => try!(output.write_u8(0)),
+            }
+        }
+
+        Ok(len)
+    }
+
+    fn deserialize<I: io::Read>(input: &mut I) -> Result<(u64, SourceMap), Error> {
+        let mut source_map = SourceMap::new();
+        let mut len = 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            let (file_len, file) = try!(String::deserialize(input));
+            len += file_len;
+            source_map.files.push(file);
+        }
+
+        len += 4;
+        for _ in 0..try!(input.read_u32::<LittleEndian>()) {
+            len += 1;
+            source_map.push(match try!(input.read_u8()) {
+                0 => None,
+                1 => {
+                    len += 12;
+                    Some(SourceSpan::new(
+                        try!(input.read_u32::<LittleEndian>()),
+                        try!(input.read_u32::<LittleEndian>()),
+                        try!(input.read_u32::<LittleEndian>())
+                    ))
+                },
+                _ => return Err(Error::InvalidBinaryFormat),
+            });
+        }
+
+        Ok((len, source_map))
+    }
+}
+
+impl<V: ValueSerializer> Bytecode for Template<V> {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -97,4 +719,46 @@ mod test {
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn instructions() {
+        let instructions = vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::Html },
+            Instruction::Property { name: Mem::Parameter { name: Constant(7) } },
+            Instruction::Push { location: Mem::StackTop2 },
+            Instruction::Pop { times: 3 },
+            Instruction::Jump { pc: 300 },
+            Instruction::CondJump { pc: 2, location: Mem::Binding(Binding(4)), test: Cond::Lte },
+            Instruction::Call { call: Call(5), argc: 2, push_result_to_stack: true },
+            Instruction::Load { binding: Binding(1), location: Mem::Parameters },
+            Instruction::Interupt { code: 7 },
+            Instruction::IterStart { location: Mem::StackTop1 },
+            Instruction::IterNext { binding: Binding(2), pc: 9 },
+            Instruction::Operate { op: Operator::Rem, location: Mem::Const(Constant(1)) },
+            Instruction::Negate,
+            Instruction::Not,
+            Instruction::Compare { location: Mem::StackTop2, test: Cond::Gte },
+            Instruction::Include { name: Mem::Const(Constant(2)), location: Mem::Parameters },
+            Instruction::CallLocal { pc: 3 },
+            Instruction::Return,
+        ];
+
+        for instruction in instructions {
+            let mut output: Vec<u8> = vec![];
+            let written = instruction.serialize(&mut output).unwrap();
+            assert_eq!(output.len() as u64, written);
+
+            let (read, result) = Instruction::deserialize(&mut Cursor::new(&output[..])).unwrap();
+            assert_eq!(written, read);
+            assert_eq!(format!("{:?}", instruction), format!("{:?}", result));
+        }
+    }
+
+    #[test]
+    fn invalid_instruction() {
+        let input: &[u8] = &[200];
+        match Instruction::deserialize(&mut Cursor::new(input)) {
+            Err(Error::InvalidBinaryFormat) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 }
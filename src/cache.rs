@@ -0,0 +1,218 @@
+/*!
+Storage for built executables.
+
+Backends that have a `Store` persist every built template, so it
+can be later restored with `Build::load` without building the template again.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{ Read, Write, Cursor };
+use std::path::{ Path, PathBuf };
+use std::process;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+use bytecode::{ Header, Serializer, ValueSerializer };
+use verifier;
+use {
+    BuildError,
+    Call,
+    Fingerprint,
+    Function,
+    Options,
+    OptionsTemplate,
+    Template,
+};
+
+/// Persistent storage of executable bytes by id.
+pub trait Store {
+    /// Save data under `id`, replacing existing data.
+    fn put(&mut self, id: &str, data: Vec<u8>) -> io::Result<()>;
+    /// Get data saved under `id`, `None` if this id is unknown.
+    fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// Store that keeps executables in memory.
+pub struct MemoryStore {
+    items: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            items: HashMap::new(),
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn put(&mut self, id: &str, data: Vec<u8>) -> io::Result<()> {
+        self.items.insert(id.into(), data);
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.items.get(id).cloned())
+    }
+}
+
+/// Store that keeps every executable in a separate file in a directory.
+pub struct DirectoryStore {
+    path: PathBuf,
+}
+
+impl DirectoryStore {
+    /// Use directory at `path`, it is created on first write if missing.
+    pub fn new<P: AsRef<Path>>(path: P) -> DirectoryStore {
+        DirectoryStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns file path for specified id.
+    ///
+    /// Characters other than ASCII letters, digits and `-` are hex-escaped,
+    /// so any id maps to a single file inside the directory.
+    pub fn file_path(&self, id: &str) -> PathBuf {
+        let mut name = String::new();
+        for b in id.bytes() {
+            match b {
+                b'a' ..= b'z' | b'A' ..= b'Z' | b'0' ..= b'9' | b'-' => name.push(b as char),
+                b => name.push_str(&format!("_{:02x}", b)),
+            }
+        }
+        name.push_str(".little");
+        self.path.join(name)
+    }
+
+    /// Returns unique path of temporary file for `path`, in the same directory.
+    fn temp_path(&self, path: &Path) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        self.path.join(format!(".{}.{}.{}.tmp", name, process::id(), NEXT.fetch_add(1, Ordering::SeqCst)))
+    }
+}
+
+impl Store for DirectoryStore {
+    /// Data is written to a temporary file that is then renamed, so readers
+    /// never see a partially written file.
+    fn put(&mut self, id: &str, data: Vec<u8>) -> io::Result<()> {
+        try!(fs::create_dir_all(&self.path));
+        let path = self.file_path(id);
+        let temp = self.temp_path(&path);
+        let result = fs::File::create(&temp)
+            .and_then(|mut file| file.write_all(&data).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&temp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut file = match fs::File::open(self.file_path(id)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        Ok(Some(data))
+    }
+}
+
+/// Backend store that keeps nothing, this is the default.
+///
+/// Executables can not be loaded, and template values do not have to
+/// implement `ValueSerializer`.
+pub struct NoStore;
+
+/// Saves built templates and restores them for `Build::load`.
+///
+/// Implemented for every `Store` when values implement `ValueSerializer`.
+pub trait Persist<V> {
+    /// Save template built for `env` under `id`.
+    fn save(&mut self, id: &str, env: Fingerprint, template: &Template<V>) -> Result<(), BuildError>;
+    /// Restore template saved under `id` for `env`.
+    fn restore(&self, id: &str, env: Fingerprint) -> Result<Template<V>, BuildError>;
+}
+
+impl<V> Persist<V> for NoStore {
+    fn save(&mut self, _id: &str, _env: Fingerprint, _template: &Template<V>) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn restore(&self, id: &str, _env: Fingerprint) -> Result<Template<V>, BuildError> {
+        Err(BuildError::ExecutableNotFound { id: id.into() })
+    }
+}
+
+impl<V: ValueSerializer, S: Store> Persist<V> for S {
+    fn save(&mut self, id: &str, env: Fingerprint, template: &Template<V>) -> Result<(), BuildError> {
+        save(self, id, env, template)
+    }
+
+    fn restore(&self, id: &str, env: Fingerprint) -> Result<Template<V>, BuildError> {
+        restore(self, id, env)
+    }
+}
+
+/// Write template to store under `id`, built for `env`.
+pub fn save<V: ValueSerializer>(store: &mut Store, id: &str, env: Fingerprint, template: &Template<V>)
+    -> Result<(), BuildError>
+{
+    let mut data = Vec::new();
+    try!(Header::new().serialize(&mut data));
+    try!(id.to_string().serialize(&mut data));
+    try!(env.serialize(&mut data));
+    try!(template.serialize(&mut data));
+
+    debug!("save executable {:?} ({} bytes)", id, data.len());
+
+    try!(store.put(id, data));
+    Ok(())
+}
+
+/// Read template from store, fails if it is missing, was built for different `env`
+/// or its instructions do not pass verification.
+pub fn restore<V: ValueSerializer>(store: &Store, id: &str, env: Fingerprint)
+    -> Result<Template<V>, BuildError>
+{
+    let data = match try!(store.get(id)) {
+        Some(data) => data,
+        None => return Err(BuildError::ExecutableNotFound { id: id.into() }),
+    };
+
+    debug!("restore executable {:?} ({} bytes)", id, data.len());
+
+    let mut input = Cursor::new(&data[..]);
+    let (_, header) = try!(Header::deserialize(&mut input));
+    try!(header.check());
+    let (_, stored_id) = try!(String::deserialize(&mut input));
+    if stored_id != id {
+        return Err(BuildError::ExecutableNotFound { id: id.into() });
+    }
+    let (_, stored_env) = try!(Fingerprint::deserialize(&mut input));
+    if stored_env != env {
+        return Err(BuildError::EnvironmentMismatch { id: id.into() });
+    }
+
+    let (_, template) = try!(Template::deserialize(&mut input));
+    try!(verifier::verify(&template));
+    Ok(template)
+}
+
+/// Map template calls to functions, where `calls[n]` is the function for `Call(n)`.
+pub fn map_calls<'a, V>(calls_template: &OptionsTemplate<Call>, calls: &'a Vec<&'a (Function<V> + 'a)>)
+    -> Result<Options<Call, &'a Function<V>>, BuildError>
+{
+    let mut map = HashMap::new();
+    for (name, &call) in calls_template.iter() {
+        match calls.get(call.0 as usize) {
+            Some(function) => { map.insert(call, *function); },
+            None => return Err(BuildError::FunctionNotFound { required: name.clone() }),
+        };
+    }
+    Ok(Options::new(map))
+}
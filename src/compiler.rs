@@ -1,27 +1,104 @@
-use std::collections::HashMap;
-use std::io;
+//! Template compiler.
+//!
+//! Static output is rendered at build time into a single byte blob,
+//! the rest of instructions are kept as ops with jump targets remapped to op indices.
+
+use std::collections::{ HashMap, HashSet };
 use std::fmt;
+use std::sync::Arc;
+use std::io;
+use std::io::Write;
+
+use options;
+use machine::{ self, CallRef, Configure, Host, Machine, Progress, Run, Runtime, Step };
+use cache::{ self, NoStore, Persist, Store };
+use verifier;
+use optimizer::Optimizer;
+use registry::{ IncludeContext, Render };
+use escape::Escaper;
+
 use {
+    Call,
+    Options,
+    Escape,
+    Limits,
+    Constant,
+    Instruction,
+    Mem,
     Execute,
     Fingerprint,
+    LittleValue,
     Template,
     Function,
     BuildError,
     Build,
+    LittleError,
     LittleResult,
 };
 
-pub struct Compiler;
+pub struct Compiler<S = NoStore> {
+    store: S,
+    optimizer: Optimizer,
+}
 
 impl Compiler {
     pub fn new() -> Compiler {
         trace!("create new compiler");
-        Compiler
+        Compiler {
+            store: NoStore,
+            optimizer: Optimizer::for_build(),
+        }
+    }
+
+    /// Create compiler that saves built executables to `store`,
+    /// so that they can be restored later with `load`.
+    ///
+    /// Store keeps templates, they are compiled again when loaded.
+    pub fn with_store<S: Store>(store: S) -> Compiler<S> {
+        trace!("create new compiler with store");
+        Compiler {
+            store: store,
+            optimizer: Optimizer::for_build(),
+        }
     }
 }
 
-impl<'a, V: fmt::Debug> Build<'a, V> for Compiler {
-    type Output = Executable;
+impl<S> Compiler<S> {
+    /// Replace optimizer that is run on templates before they are built,
+    /// the default is `Optimizer::for_build()`.
+    pub fn set_optimizer(&mut self, optimizer: Optimizer) {
+        self.optimizer = optimizer;
+    }
+
+    /// Builds executable that shares `functions` instead of borrowing them,
+    /// so that it can be kept after functions map is gone.
+    pub fn build_shared<'e, V: LittleValue + 'e>(
+        &mut self,
+        id: &str,
+        template: Template<V>,
+        functions: &HashMap<String, Arc<Function<V> + 'e>>
+    ) -> Result<Executable<'e, V>, BuildError> where S: Persist<V> {
+        trace!("build shared Executable for compiler with template {:?}", id);
+        let template = try!(self.prepare(id, template));
+        let calls = try!(machine::shared_calls(&template.calls_template, functions));
+        executable(id, template.env_fingerprint(), template, calls)
+    }
+
+    /// Verifies and optimizes template, then saves it to store.
+    fn prepare<V: LittleValue>(&mut self, id: &str, mut template: Template<V>) -> Result<Template<V>, BuildError>
+        where S: Persist<V>
+    {
+        try!(verifier::verify(&template));
+        let report = self.optimizer.optimize(&mut template);
+        debug!("optimize {:?}: {}", id, report);
+
+        try!(self.store.save(id, template.env_fingerprint(), &template));
+        Ok(template)
+    }
+}
+
+impl<'a, V: LittleValue + 'a, S: Persist<V> + 'a> Build<'a, V> for Compiler<S> {
+    type Output = Executable<'a, V>;
 
     fn build(
         &'a mut self,
@@ -29,28 +106,164 @@ impl<'a, V: fmt::Debug> Build<'a, V> for Compiler {
         template: Template<V>,
         calls: &'a HashMap<&'a str, &'a (Function<V> + 'a)>
     ) -> LittleResult<Self::Output> {
-        trace!("build Executable for compiler with template {:#?} and calls {:#?}", template, calls.keys().collect::<Vec<_>>());
-        Ok(Executable { id: id.into() })
+        trace!("build Executable for compiler with template {:?} and calls {:?}", id, calls.keys().collect::<Vec<_>>());
+        let template = try!(self.prepare(id, template));
+
+        let calls = match template.calls_template.build(calls) {
+            Ok(built) => built,
+            Err(options::Error::ParameterMissing(s)) => return Err(BuildError::FunctionNotFound { required: s }.into()),
+        };
+        Ok(try!(executable(id, template.env_fingerprint(), template, calls.map(CallRef::Borrowed))))
     }
 
     fn load(&'a mut self, id: &str, env: Fingerprint, calls: &'a Vec<&'a (Function<V> + 'a)>)
         -> LittleResult<Self::Output>
     {
-        unreachable!("compiler load not implemented");
+        trace!("load Executable for compiler {:?}", id);
+        let template: Template<V> = try!(self.store.restore(id, env));
+
+        let calls = try!(cache::map_calls(&template.calls_template, calls));
+        Ok(try!(executable(id, env, template, calls.map(CallRef::Borrowed))))
     }
 }
 
-pub struct Executable
+/// Compiles template into executable.
+fn executable<'a, V: LittleValue>(id: &str, env: Fingerprint, template: Template<V>, calls: Options<Call, CallRef<'a, V>>)
+    -> Result<Executable<'a, V>, BuildError>
+{
+    let (blob, ops) = try!(compile(&template.instructions, &template.constants));
+    Ok(Executable {
+        id: id.into(),
+        env: env,
+        blob: blob,
+        ops: ops,
+        runtime: Runtime::new(template.constants, calls, &template.calls_template, template.source_map, template.bindings_capacity),
+    })
+}
+
+/// Compiled operation.
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    /// Output `len` bytes from blob starting at `offset`, replaces `instructions` output instructions.
+    Text { offset: usize, len: usize, instructions: usize },
+    /// Execute instruction at template `pc`, its jump target is op index.
+    Exec(Instruction, usize),
+}
+
+/// Renders constant output into blob and converts instructions to ops.
+fn compile<V: LittleValue>(instructions: &[Instruction], constants: &Options<Constant, V>)
+    -> Result<(Vec<u8>, Vec<Op>), BuildError>
 {
+    let mut targets = HashSet::new();
+    for instruction in instructions {
+        match *instruction {
+            Instruction::Jump { pc }
+            | Instruction::CondJump { pc, .. }
+            | Instruction::IterNext { pc, .. }
+            | Instruction::CallLocal { pc } => { targets.insert(pc as usize); },
+            _ => (),
+        }
+    }
+
+    let mut blob = Vec::new();
+    let mut ops: Vec<Op> = Vec::new();
+    let mut op_indices = Vec::with_capacity(instructions.len());
+
+    for (pc, instruction) in instructions.iter().enumerate() {
+        // Inherited escaping is known only when executable runs.
+        if let Instruction::Output { location: Mem::Const(c), escape } = *instruction {
+            if let (Some(value), false) = (constants.get(c), escape == Escape::Inherit) {
+                let escape = if value.is_safe() { Escape::None } else { escape };
+                let offset = blob.len();
+                // Formatting into `io::Write` panics if `Display` fails, format into text first.
+                let mut text = String::new();
+                try!(fmt::Write::write_fmt(&mut text, format_args!("{}", value))
+                    .map_err(|_| BuildError::ConstantFormat { constant: c }));
+                try!(Escaper::new(&mut blob, escape).write_all(text.as_bytes())
+                    .map_err(|_| BuildError::ConstantFormat { constant: c }));
+                let len = blob.len() - offset;
+
+                // Text can be merged into previous op only if nothing jumps between them.
+                let index = ops.len();
+                if !targets.contains(&pc) {
+                    if let Some(&mut Op::Text { len: ref mut prev_len, ref mut instructions, .. }) = ops.last_mut() {
+                        *prev_len += len;
+                        *instructions += 1;
+                        op_indices.push(index - 1);
+                        continue;
+                    }
+                }
+
+                op_indices.push(index);
+                ops.push(Op::Text { offset: offset, len: len, instructions: 1 });
+                continue;
+            }
+        }
+
+        op_indices.push(ops.len());
+        ops.push(Op::Exec(*instruction, pc));
+    }
+
+    let end = ops.len();
+    let remap = |pc: u16| -> u16 {
+        *op_indices.get(pc as usize).unwrap_or(&end) as u16
+    };
+
+    for op in &mut ops {
+        match *op {
+            Op::Exec(Instruction::Jump { ref mut pc }, _) => *pc = remap(*pc),
+            Op::Exec(Instruction::CondJump { ref mut pc, .. }, _) => *pc = remap(*pc),
+            Op::Exec(Instruction::IterNext { ref mut pc, .. }, _) => *pc = remap(*pc),
+            Op::Exec(Instruction::CallLocal { ref mut pc }, _) => *pc = remap(*pc),
+            _ => (),
+        }
+    }
+
+    trace!("compiled {} instructions into {} ops and {} bytes of text", instructions.len(), ops.len(), blob.len());
+
+    Ok((blob, ops))
+}
+
+pub struct Executable<'a, V: 'a> {
     id: String,
+    env: Fingerprint,
+    blob: Vec<u8>,
+    ops: Vec<Op>,
+    runtime: Runtime<'a, V>,
 }
 
-impl<'a, V: fmt::Debug> Execute<'a, V> for Executable {
-    type Stream = CompilerStream;
+impl<'a, V: 'a> Configure<'a, V> for Executable<'a, V> {
+    fn runtime(&self) -> &Runtime<'a, V> {
+        &self.runtime
+    }
+
+    fn runtime_mut(&mut self) -> &mut Runtime<'a, V> {
+        &mut self.runtime
+    }
+}
+
+impl<'a, V: LittleValue + 'a> Executable<'a, V> {
+    /// Run this executable with limits other than configured for executable.
+    pub fn execute_with_limits(&'a self, data: V, limits: Limits) -> CompilerStream<'a, V> {
+        CompilerStream {
+            run: Run::new(Machine::new(&self.runtime, data, limits)),
+            executable: self,
+        }
+    }
+}
+
+impl<'a, V: LittleValue + 'a> Execute<'a, V> for Executable<'a, V> {
+    type Stream = CompilerStream<'a, V>;
 
     fn execute(&'a self, data: V) -> Self::Stream {
-        trace!("run Executable with data {:#?}", data);
-        CompilerStream
+        CompilerStream {
+            run: Run::new(Machine::new(&self.runtime, data, self.runtime.limits)),
+            executable: self,
+        }
+    }
+
+    fn execute_into<W: Write>(&'a self, data: V, out: &mut W) -> Result<(), LittleError> {
+        self.execute(data).write_to(out)
     }
 
     fn get_id<'r>(&'r self) -> &'r str {
@@ -58,32 +271,69 @@ impl<'a, V: fmt::Debug> Execute<'a, V> for Executable {
     }
 
     fn identify_env(&self) -> Fingerprint {
-        Fingerprint::empty()
+        self.env
     }
 }
 
-pub struct CompilerStream;
-
-impl io::Read for CompilerStream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        Ok(0)
+impl<'a, V: LittleValue + 'a> Render<V> for Executable<'a, V> {
+    fn render(&self, data: V, mut out: &mut io::Write, context: &mut IncludeContext<V>) -> Result<(), LittleError> {
+        let mut stream = self.execute_with_limits(data, context.limits);
+        stream.run.machine.include_from(context);
+        let result = stream.write_to(&mut out);
+        context.executed = stream.run.machine.executed();
+        context.output_bytes = stream.run.machine.output_bytes();
+        result
     }
 }
 
-mod ooo {
-    use std::io;
+pub struct CompilerStream<'a, V: 'a> {
+    run: Run<'a, V>,
+    executable: &'a Executable<'a, V>,
+}
 
-    #[allow(non_camel_case_types)]
-    pub struct template_ooo;
+impl<'a, V: LittleValue> Host<'a, V> for CompilerStream<'a, V> {
+    fn run(&self) -> &Run<'a, V> {
+        &self.run
+    }
 
-    impl template_ooo {
-        pub fn output<I, O>(input: &mut I, output: &mut O)
-            -> Result<usize, io::Error>
-        where
-            I: io::Read + io::Seek, O: io::Write
-        {
+    fn run_mut(&mut self) -> &mut Run<'a, V> {
+        &mut self.run
+    }
 
-            Ok(0)
+    fn execute_next<W: Write>(&mut self, out: &mut W) -> Result<Progress, LittleError> {
+        let executable = self.executable;
+        let run = &mut self.run;
+        match executable.ops.get(run.pc) {
+            Some(&Op::Text { offset, len, instructions }) => {
+                try!(run.machine.count_instructions(instructions as u64));
+                let mut counter = run.machine.output_writer(out);
+                let result = counter.write_all(&executable.blob[offset .. offset + len]);
+                try!(run.machine.count_output(counter, result));
+                run.pc += 1;
+                Ok(Progress::Continue)
+            },
+            Some(&Op::Exec(ref i, pc)) => {
+                match try!(run.machine.execute(pc, i, out)) {
+                    Step::Next => run.pc += 1,
+                    Step::Jump(pc) => run.pc = pc,
+                    Step::Call(pc) => {
+                        run.machine.enter_frame(run.pc + 1);
+                        run.pc = pc;
+                    },
+                    Step::Interupt(code) => {
+                        run.pc += 1;
+                        return Ok(Progress::Interupt(code));
+                    },
+                };
+                Ok(Progress::Continue)
+            },
+            None => Ok(Progress::Done),
         }
     }
 }
+
+impl<'a, V: LittleValue> io::Read for CompilerStream<'a, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_output(buf)
+    }
+}
@@ -0,0 +1,312 @@
+/*!
+Functions, templates and settings that are used together.
+
+`Environment` owns everything needed to render templates by name:
+
+```ignore
+let mut env = Environment::new();
+env.add_function("upper", |args: &[Value]| -> LittleResult<Value> { ... });
+env.add_global("site", Value::from("Little".to_string()));
+env.add_template("nav", "<nav>{{ site }}</nav>").unwrap();
+env.add_template("page", "{% include \"nav\" %}{{ title }}").unwrap();
+
+let html = env.render("page", params).unwrap();
+```
+
+Templates can include other templates of the environment by name.
+Parameters that are not found in render parameters are looked up in globals.
+
+Templates that were not added are loaded from `loader::TemplateLoader`
+if it is set with `set_loader`. They are loaded again when the modification
+stamp of the template changes.
+
+Templates are built with the configured `Backend` when they are first rendered.
+Executables share environment functions, so they are kept in a cache until
+the template, functions, backend or escaping changes.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use compiler::{ self, Compiler };
+use interpreter::{ self, Interpreter };
+use bytecode::{ self, ValueSerializer };
+use machine::Configure;
+use loader::{ LoadedTemplate, TemplateSource, TemplateLoader };
+use optimizer::Optimizer;
+use registry::{ IncludeContext, Registry, Render };
+use stream::FmtWriter;
+use verifier;
+
+use {
+    BuildError,
+    Escape,
+    Function,
+    GetProperty,
+    Limits,
+    LittleError,
+    LittleValue,
+    Template,
+    parser,
+};
+
+/// Backend that builds executables of environment templates.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Backend {
+    /// `interpreter::Interpreter`.
+    Interpreter,
+    /// `compiler::Compiler`.
+    Compiler,
+}
+
+/// Value that can be added to environment as a template.
+pub trait IntoTemplate<V> {
+    /// Convert into template, `name` is used as source file name.
+    fn into_template(self, name: &str) -> Result<Template<V>, BuildError>;
+}
+
+impl<V> IntoTemplate<V> for Template<V> {
+    fn into_template(self, _name: &str) -> Result<Template<V>, BuildError> {
+        Ok(self)
+    }
+}
+
+impl<'s, V: Default + From<String> + From<i64>> IntoTemplate<V> for &'s str {
+    fn into_template(self, name: &str) -> Result<Template<V>, BuildError> {
+        parser::parse_file(name, self).map_err(BuildError::from)
+    }
+}
+
+impl<V: Default + From<String> + From<i64>> IntoTemplate<V> for String {
+    fn into_template(self, name: &str) -> Result<Template<V>, BuildError> {
+        parser::parse_file(name, &self).map_err(BuildError::from)
+    }
+}
+
+/// Global values by name.
+struct Globals<V> {
+    values: Vec<(V, V)>,
+}
+
+impl<V: PartialEq + Clone> GetProperty<V> for Globals<V> {
+    fn get_property(&self, name: V) -> Option<V> {
+        self.values.iter()
+            .find(|&&(ref n, _)| *n == name)
+            .map(|&(_, ref value)| value.clone())
+    }
+}
+
+/// Owns functions, global values and templates, renders templates by name.
+pub struct Environment<V: 'static> {
+    functions: HashMap<String, Arc<Function<V>>>,
+    globals: Globals<V>,
+    templates: HashMap<String, Template<V>>,
+    loader: Option<Box<TemplateLoader>>,
+    /// Reads loaded source or bytecode, set together with `loader`.
+    read_loaded: fn(LoadedTemplate, &str) -> Result<Template<V>, BuildError>,
+    /// Built executables with modification stamps of loaded templates,
+    /// the stamp is `None` for added templates.
+    executables: RefCell<HashMap<String, (Option<u64>, Rc<Built<V>>)>>,
+    backend: Backend,
+    optimizer: Optimizer,
+    limits: Limits,
+    escape: Escape,
+}
+
+impl<V: LittleValue + From<String> + From<i64> + 'static> Environment<V> {
+    pub fn new() -> Environment<V> {
+        Environment {
+            functions: HashMap::new(),
+            globals: Globals { values: Vec::new() },
+            templates: HashMap::new(),
+            loader: None,
+            read_loaded: read_source,
+            executables: RefCell::new(HashMap::new()),
+            backend: Backend::Interpreter,
+            optimizer: Optimizer::for_build(),
+            limits: Limits::default(),
+            escape: Escape::None,
+        }
+    }
+
+    /// Add function, replaces existing one with the same name.
+    pub fn add_function<F: Function<V> + 'static>(&mut self, name: &str, function: F) {
+        self.functions.insert(name.into(), Arc::new(function));
+        self.executables.get_mut().clear();
+    }
+
+    /// Add function that is shared with other environments.
+    pub fn add_shared_function(&mut self, name: &str, function: Arc<Function<V>>) {
+        self.functions.insert(name.into(), function);
+        self.executables.get_mut().clear();
+    }
+
+    /// Add global value, replaces existing one with the same name.
+    pub fn add_global(&mut self, name: &str, value: V) {
+        let name = V::from(name.to_string());
+        self.globals.values.retain(|&(ref n, _)| *n != name);
+        self.globals.values.push((name, value));
+    }
+
+    /// Add template from source or `Template`, replaces existing one with the same name.
+    ///
+    /// Template is verified and optimized before it is added.
+    pub fn add_template<T: IntoTemplate<V>>(&mut self, name: &str, template: T) -> Result<(), BuildError> {
+        let template = try!(self.prepare(name, try!(template.into_template(name))));
+        self.templates.insert(name.into(), template);
+        self.executables.get_mut().remove(name);
+        Ok(())
+    }
+
+    /// Load templates that were not added from `loader`.
+    ///
+    /// Loader may return precompiled bytecode, so values must implement `ValueSerializer`.
+    pub fn set_loader<L: TemplateLoader + 'static>(&mut self, loader: L) where V: ValueSerializer {
+        self.loader = Some(Box::new(loader));
+        self.read_loaded = LoadedTemplate::into_template::<V>;
+        self.executables.get_mut().clear();
+    }
+
+    /// Returns false if there was no template with this name.
+    pub fn remove_template(&mut self, name: &str) -> bool {
+        self.executables.get_mut().remove(name);
+        self.templates.remove(name).is_some()
+    }
+
+    pub fn contains_template(&self, name: &str) -> bool {
+        self.templates.contains_key(name)
+    }
+
+    /// Set backend used to build executables, the default is `Backend::Interpreter`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+        self.executables.get_mut().clear();
+    }
+
+    /// Set optimizer used for templates added or loaded later, the default is `Optimizer::for_build()`.
+    pub fn set_optimizer(&mut self, optimizer: Optimizer) {
+        self.optimizer = optimizer;
+    }
+
+    /// Set limits of every render, the default is `Limits::default()`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Set escaping of `Output` instructions with `Escape::Inherit`, the default is `Escape::None`.
+    pub fn set_escape(&mut self, escape: Escape) {
+        self.escape = escape;
+        self.executables.get_mut().clear();
+    }
+
+    /// Render template named `name` into a string.
+    pub fn render(&self, name: &str, params: V) -> Result<String, LittleError> {
+        let mut out = String::new();
+        {
+            let mut writer = FmtWriter::new(&mut out);
+            try!(self.render_into(name, params, &mut writer));
+            try!(writer.finish());
+        }
+        Ok(out)
+    }
+
+    /// Render template named `name` into `out`.
+    pub fn render_into<W: io::Write>(&self, name: &str, params: V, out: &mut W) -> Result<(), LittleError> {
+        let mut registry = Registry::new();
+        registry.set_limits(self.limits);
+        registry.set_globals(&self.globals);
+        registry.set_fallback(Cached { env: self });
+        registry.render(name, params, out)
+    }
+
+    /// Returns executable of added template or template from loader,
+    /// it is built if it is not in the cache yet.
+    fn executable(&self, name: &str) -> Result<Rc<Built<V>>, BuildError> {
+        if let Some(template) = self.templates.get(name) {
+            if let Some(&(None, ref built)) = self.executables.borrow().get(name) {
+                return Ok(built.clone());
+            }
+            // Added templates are already verified and optimized.
+            let built = Rc::new(try!(self.build(name, template.clone(), Optimizer::empty())));
+            self.executables.borrow_mut().insert(name.into(), (None, built.clone()));
+            return Ok(built);
+        }
+        let loader = match self.loader {
+            Some(ref loader) => loader,
+            None => return Err(BuildError::ExecutableNotFound { id: name.into() }),
+        };
+
+        let modified = try!(loader.modified(name));
+        if let Some(&(Some(stamp), ref built)) = self.executables.borrow().get(name) {
+            if stamp == modified {
+                return Ok(built.clone());
+            }
+        }
+
+        let loaded = try!(loader.load(name));
+        let stamp = loaded.modified;
+        let template = try!((self.read_loaded)(loaded, name));
+        let built = Rc::new(try!(self.build(name, template, self.optimizer.clone())));
+        self.executables.borrow_mut().insert(name.into(), (Some(stamp), built.clone()));
+        Ok(built)
+    }
+
+    /// Builds executable with configured backend, `optimizer` is run on the template first.
+    fn build(&self, name: &str, template: Template<V>, optimizer: Optimizer) -> Result<Built<V>, BuildError> {
+        Ok(match self.backend {
+            Backend::Interpreter => {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_optimizer(optimizer);
+                let mut executable = try!(interpreter.build_shared(name, template, &self.functions));
+                executable.set_escape(self.escape);
+                Built::Interpreter(executable)
+            },
+            Backend::Compiler => {
+                let mut compiler = Compiler::new();
+                compiler.set_optimizer(optimizer);
+                let mut executable = try!(compiler.build_shared(name, template, &self.functions));
+                executable.set_escape(self.escape);
+                Built::Compiler(executable)
+            },
+        })
+    }
+
+    fn prepare(&self, name: &str, mut template: Template<V>) -> Result<Template<V>, BuildError> {
+        try!(verifier::verify_with_limits(&template, &self.limits));
+        let report = self.optimizer.optimize(&mut template);
+        debug!("optimize {:?}: {}", name, report);
+        Ok(template)
+    }
+}
+
+/// Executable built by environment `Backend`.
+enum Built<V: 'static> {
+    Interpreter(interpreter::Executable<'static, V>),
+    Compiler(compiler::Executable<'static, V>),
+}
+
+/// Renders environment template named by `IncludeContext::name`.
+struct Cached<'e, V: 'static> {
+    env: &'e Environment<V>,
+}
+
+impl<'e, V: LittleValue + From<String> + From<i64> + 'static> Render<V> for Cached<'e, V> {
+    fn render(&self, data: V, out: &mut io::Write, context: &mut IncludeContext<V>) -> Result<(), LittleError> {
+        let built = try!(self.env.executable(&context.name));
+        match *built {
+            Built::Interpreter(ref executable) => executable.render(data, out, context),
+            Built::Compiler(ref executable) => executable.render(data, out, context),
+        }
+    }
+}
+
+/// Reads template source, used until a loader is set.
+fn read_source<V: Default + From<String> + From<i64>>(loaded: LoadedTemplate, name: &str) -> Result<Template<V>, BuildError> {
+    match loaded.source {
+        TemplateSource::Text(text) => text.into_template(name),
+        TemplateSource::Bytecode(_) => Err(bytecode::Error::InvalidBinaryFormat.into()),
+    }
+}
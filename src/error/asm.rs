@@ -0,0 +1,80 @@
+use std::error;
+use std::fmt;
+
+/// Kind of assembler source error.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    /// Unknown directive, like `.foo`.
+    UnknownDirective(String),
+    /// Unknown instruction mnemonic.
+    UnknownInstruction(String),
+    /// Operand is missing or can not be parsed.
+    InvalidOperand(String),
+    /// Wrong number of operands for instruction or directive.
+    OperandCount { expected: usize, found: usize },
+    /// Jump to a label that is not defined.
+    UndefinedLabel(String),
+    /// Label is defined more than once.
+    DuplicateLabel(String),
+    /// Constant value bytes could not be decoded.
+    InvalidValue,
+    /// Template does not fit into addressable instruction range.
+    TooManyInstructions,
+    /// Source span is given for instruction past the last one.
+    SpanOutOfBounds { pc: usize, len: usize },
+}
+
+/// Error while assembling template from text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub kind: AsmErrorKind,
+    /// Source line, starting from 1.
+    pub line: usize,
+}
+
+impl AsmError {
+    pub fn new(kind: AsmErrorKind, line: usize) -> AsmError {
+        AsmError {
+            kind: kind,
+            line: line,
+        }
+    }
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsmErrorKind::UnknownDirective(ref d) => write!(f, "Unknown directive {:?}", d),
+            AsmErrorKind::UnknownInstruction(ref i) => write!(f, "Unknown instruction {:?}", i),
+            AsmErrorKind::InvalidOperand(ref o) => write!(f, "Invalid operand {:?}", o),
+            AsmErrorKind::OperandCount { expected, found } => write!(f, "Expected {} operands, found {}", expected, found),
+            AsmErrorKind::UndefinedLabel(ref l) => write!(f, "Label {:?} is not defined", l),
+            AsmErrorKind::DuplicateLabel(ref l) => write!(f, "Label {:?} is already defined", l),
+            AsmErrorKind::InvalidValue => write!(f, "Invalid constant value"),
+            AsmErrorKind::TooManyInstructions => write!(f, "Template is too large"),
+            AsmErrorKind::SpanOutOfBounds { pc, len } => write!(f, "Span of instruction {} is outside of {} instructions", pc, len),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}", self.kind, self.line)
+    }
+}
+
+impl error::Error for AsmError {
+    fn description(&self) -> &str {
+        match self.kind {
+            AsmErrorKind::UnknownDirective(_) => "unknown directive",
+            AsmErrorKind::UnknownInstruction(_) => "unknown instruction",
+            AsmErrorKind::InvalidOperand(_) => "invalid operand",
+            AsmErrorKind::OperandCount { .. } => "wrong number of operands",
+            AsmErrorKind::UndefinedLabel(_) => "undefined label",
+            AsmErrorKind::DuplicateLabel(_) => "duplicate label",
+            AsmErrorKind::InvalidValue => "invalid value",
+            AsmErrorKind::TooManyInstructions => "too many instructions",
+            AsmErrorKind::SpanOutOfBounds { .. } => "span out of bounds",
+        }
+    }
+}
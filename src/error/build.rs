@@ -1,17 +1,70 @@
+use std::io;
 use std::error;
 use std::fmt;
+use bytecode;
+use Constant;
+use ParseError;
+use VerifyError;
 
 /// Error while performing seek.
 #[derive(Debug)]
 pub enum BuildError {
     /// Out of bound operation on container.
     FunctionNotFound { required: String },
+    /// Executable with this id was not found in the store.
+    ExecutableNotFound { id: String },
+    /// Template name can not be resolved, for example it leads outside of loader root.
+    InvalidName { name: String },
+    /// Stored executable was built for different environment.
+    EnvironmentMismatch { id: String },
+    /// Constant output could not be formatted when compiling template.
+    ConstantFormat { constant: Constant },
+    /// Template instructions are malformed.
+    Verify(VerifyError),
+    /// Template source could not be parsed.
+    Parse(ParseError),
+    /// Stored executable could not be read or written.
+    Bytecode(bytecode::Error),
+    /// I/O error in executable store.
+    Io(io::Error),
+}
+
+impl From<bytecode::Error> for BuildError {
+    fn from(other: bytecode::Error) -> BuildError {
+        BuildError::Bytecode(other)
+    }
+}
+
+impl From<VerifyError> for BuildError {
+    fn from(other: VerifyError) -> BuildError {
+        BuildError::Verify(other)
+    }
+}
+
+impl From<ParseError> for BuildError {
+    fn from(other: ParseError) -> BuildError {
+        BuildError::Parse(other)
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(other: io::Error) -> BuildError {
+        BuildError::Io(other)
+    }
 }
 
 impl fmt::Display for BuildError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             BuildError::FunctionNotFound { ref required } => write!(f, "Function {:?} not found", required),
+            BuildError::ExecutableNotFound { ref id } => write!(f, "Executable {:?} not found", id),
+            BuildError::InvalidName { ref name } => write!(f, "Invalid template name {:?}", name),
+            BuildError::EnvironmentMismatch { ref id } => write!(f, "Executable {:?} was built for different environment", id),
+            BuildError::ConstantFormat { constant } => write!(f, "Constant {:?} could not be formatted", constant),
+            BuildError::Verify(ref e) => write!(f, "Invalid template: {}", e),
+            BuildError::Parse(ref e) => write!(f, "Template source error: {}", e),
+            BuildError::Bytecode(ref e) => write!(f, "Bytecode error: {}", e),
+            BuildError::Io(ref e) => write!(f, "Store error: {}", e),
         }
     }
 }
@@ -20,6 +73,14 @@ impl error::Error for BuildError {
     fn description(&self) -> &str {
         match *self {
             BuildError::FunctionNotFound { .. } => "function not found",
+            BuildError::ExecutableNotFound { .. } => "executable not found",
+            BuildError::InvalidName { .. } => "invalid template name",
+            BuildError::EnvironmentMismatch { .. } => "environment mismatch",
+            BuildError::ConstantFormat { .. } => "constant could not be formatted",
+            BuildError::Verify(_) => "invalid template",
+            BuildError::Parse(_) => "template source error",
+            BuildError::Bytecode(_) => "bytecode error",
+            BuildError::Io(_) => "store error",
         }
     }
 }
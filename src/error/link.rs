@@ -0,0 +1,25 @@
+use std::error;
+use std::fmt;
+
+/// Error while linking child template into parent template.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkError {
+    /// Linked template does not fit into addressable instruction range.
+    TooManyInstructions(usize),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LinkError::TooManyInstructions(count) => write!(f, "Linked template is too large ({} instructions)", count),
+        }
+    }
+}
+
+impl error::Error for LinkError {
+    fn description(&self) -> &str {
+        match *self {
+            LinkError::TooManyInstructions(_) => "too many instructions",
+        }
+    }
+}
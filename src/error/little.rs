@@ -5,6 +5,7 @@ use {
     Constant,
     Call,
     BuildError,
+    SourceLocation,
 };
 
 /// Runtime error.
@@ -12,20 +13,103 @@ use {
 pub enum LittleError {
     /// A parameter was required for an instruction, but it was not found.
     ParameterMissing(Constant),
+    /// Object has no property with this name.
+    PropertyMissing { name: String, pc: usize },
     /// A constant was required for an instruction, but it was not found.
     ConstantMissing(Constant),
     /// A call was required for an instruction, but it was not found.
     CallMissing(Call),
     /// A call has returned an error.
-    CallError(Box<error::Error + Sync + Send>),
+    CallError { name: String, pc: usize, argc: u8, error: Box<error::Error + Sync + Send> },
     /// I/O error writing template result to output.
     OutputError(io::Error),
     /// Error building the template.
     BuildError(BuildError),
     /// Attempt to pop values on empty stack.
     StackUnderflow,
-    /// Instruction has caused an interupt, it is up to user to know how to handle it.
-    Interupt,
+    /// Executed more instructions than allowed by `Limits`.
+    InstructionLimitExceeded(u64),
+    /// Pushed more values to stack than allowed by `Limits`.
+    StackLimitExceeded(usize),
+    /// Used more bindings than allowed by `Limits`.
+    BindingLimitExceeded(usize),
+    /// Produced more output than allowed by `Limits`.
+    OutputLimitExceeded(u64),
+    /// Value at instruction is not iterable.
+    NotIterable { pc: usize },
+    /// `IterNext` was executed without started iteration.
+    IterationMissing,
+    /// Executable named by `Include` instruction is not in the registry.
+    IncludeMissing { name: String, pc: usize },
+    /// Included more nested executables than allowed by `Limits`.
+    IncludeDepthExceeded(usize),
+    /// Error inside of executable included with `name`.
+    Included { name: String, error: Box<LittleError> },
+    /// `Return` was executed outside of subroutine.
+    ReturnWithoutCall { pc: usize },
+    /// Values do not support operation at instruction.
+    InvalidOperation { operation: &'static str, pc: usize },
+    /// Instruction has caused an interupt with specified code, it is up to user to know how to handle it.
+    ///
+    /// The stream can be read again to resume execution.
+    Interupt(u16),
+    /// Error at instruction with known source location.
+    Located { location: SourceLocation, error: Box<LittleError> },
+}
+
+impl LittleError {
+    /// Returns `LittleError` carried by an I/O error returned from stream `read`.
+    pub fn from_io_error(error: &io::Error) -> Option<&LittleError> {
+        error.get_ref().and_then(|e| e.downcast_ref::<LittleError>())
+    }
+
+    /// Returns source location if error happened at instruction with known location.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match *self {
+            LittleError::Located { ref location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without source location.
+    pub fn unlocated(&self) -> &LittleError {
+        match *self {
+            LittleError::Located { ref error, .. } => error.unlocated(),
+            ref other => other,
+        }
+    }
+
+    /// Returns names of included executables that lead to the error, the outermost first.
+    pub fn include_chain(&self) -> Vec<&str> {
+        let mut chain = Vec::new();
+        let mut error = self;
+        loop {
+            error = match *error {
+                LittleError::Located { ref error, .. } => error,
+                LittleError::Included { ref name, ref error } => {
+                    chain.push(&name[..]);
+                    error
+                },
+                _ => return chain,
+            };
+        }
+    }
+
+    /// Returns the error without source locations and include chain.
+    pub fn original(&self) -> &LittleError {
+        match *self {
+            LittleError::Located { ref error, .. } | LittleError::Included { ref error, .. } => error.original(),
+            ref other => other,
+        }
+    }
+
+    /// Returns interupt code if this is `LittleError::Interupt`.
+    pub fn interupt_code(&self) -> Option<u16> {
+        match *self {
+            LittleError::Interupt(code) => Some(code),
+            _ => None,
+        }
+    }
 }
 
 impl From<io::Error> for LittleError {
@@ -44,13 +128,26 @@ impl fmt::Display for LittleError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             LittleError::ParameterMissing(ref p) => write!(f, "Parameter {:?} is missing.", p),
+            LittleError::PropertyMissing { ref name, pc } => write!(f, "Property {:?} is missing at instruction {}.", name, pc),
             LittleError::ConstantMissing(c) => write!(f, "Constant {:?} is missing.", c),
             LittleError::CallMissing(c) => write!(f, "Call {:?} is missing.", c),
-            LittleError::CallError(ref e) => e.fmt(f),
+            LittleError::CallError { ref name, pc, argc, ref error } => write!(f, "Call {:?} with {} arguments at instruction {} failed: {}", name, argc, pc, error),
             LittleError::BuildError(ref e) => e.fmt(f),
             LittleError::OutputError(ref e) => write!(f, "Output error: {:?}", e),
             LittleError::StackUnderflow => write!(f, "Attempt to pop empty stack."),
-            LittleError::Interupt => write!(f, "Interupt."),
+            LittleError::InstructionLimitExceeded(max) => write!(f, "Executed more than {} instructions.", max),
+            LittleError::StackLimitExceeded(max) => write!(f, "Stack is deeper than {} values.", max),
+            LittleError::BindingLimitExceeded(max) => write!(f, "Used more than {} bindings.", max),
+            LittleError::OutputLimitExceeded(max) => write!(f, "Output is longer than {} bytes.", max),
+            LittleError::NotIterable { pc } => write!(f, "Value at instruction {} is not iterable.", pc),
+            LittleError::IterationMissing => write!(f, "There is no iteration to continue."),
+            LittleError::IncludeMissing { ref name, pc } => write!(f, "Executable {:?} included at instruction {} is not in the registry.", name, pc),
+            LittleError::IncludeDepthExceeded(max) => write!(f, "Included executables are nested deeper than {}.", max),
+            LittleError::Included { ref name, ref error } => write!(f, "{} In included {:?}.", error, name),
+            LittleError::ReturnWithoutCall { pc } => write!(f, "Return at instruction {} is not in a subroutine.", pc),
+            LittleError::InvalidOperation { operation, pc } => write!(f, "Operation {:?} is not supported for values at instruction {}.", operation, pc),
+            LittleError::Interupt(code) => write!(f, "Interupt {}.", code),
+            LittleError::Located { ref location, ref error } => write!(f, "{} At {}.", error, location),
         }
     }
 }
@@ -59,16 +156,29 @@ impl error::Error for LittleError {
     fn description(&self) -> &str {
         match *self {
             LittleError::ParameterMissing(_) => "parameter is missing",
+            LittleError::PropertyMissing { .. } => "property is missing",
             LittleError::ConstantMissing(_) => "constant is missing",
             LittleError::CallMissing(_) => "call is missing",
-            LittleError::CallError(ref e) => e.description(),
+            LittleError::CallError { .. } => "call error",
             LittleError::BuildError(ref e) => e.description(),
             LittleError::OutputError(_) => "output error",
             LittleError::StackUnderflow => "stack underflow",
-            LittleError::Interupt => "interupt",
+            LittleError::InstructionLimitExceeded(_) => "instruction limit exceeded",
+            LittleError::StackLimitExceeded(_) => "stack limit exceeded",
+            LittleError::BindingLimitExceeded(_) => "binding limit exceeded",
+            LittleError::OutputLimitExceeded(_) => "output limit exceeded",
+            LittleError::NotIterable { .. } => "value is not iterable",
+            LittleError::IterationMissing => "iteration is missing",
+            LittleError::IncludeMissing { .. } => "include is missing",
+            LittleError::IncludeDepthExceeded(_) => "include depth exceeded",
+            LittleError::Included { ref error, .. } => error.description(),
+            LittleError::ReturnWithoutCall { .. } => "return without call",
+            LittleError::InvalidOperation { .. } => "invalid operation",
+            LittleError::Interupt(_) => "interupt",
+            LittleError::Located { ref error, .. } => error.description(),
         }
     }
 }
 
 /// Runtime result.
-pub type LittleResult<V> = Result<V, Box<error::Error>>;
+pub type LittleResult<V> = Result<V, Box<error::Error + Sync + Send>>;
@@ -1,3 +1,7 @@
 pub mod little;
 pub mod build;
 pub mod seek;
+pub mod parse;
+pub mod verify;
+pub mod asm;
+pub mod link;
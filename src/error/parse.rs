@@ -0,0 +1,96 @@
+use std::error;
+use std::fmt;
+use parser::Span;
+
+/// Kind of template source error.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// Source ended while more input was expected.
+    UnexpectedEof { expected: &'static str },
+    /// Found a token that does not fit here.
+    UnexpectedToken { found: String, expected: &'static str },
+    /// Unknown character inside `{{ }}` or `{% %}`.
+    UnexpectedChar(char),
+    /// String literal is not closed.
+    UnterminatedString,
+    /// Integer literal does not fit into `i64`.
+    InvalidNumber(String),
+    /// Unknown tag name in `{% %}`.
+    UnknownTag(String),
+    /// Block was opened but never closed.
+    UnclosedBlock { tag: &'static str },
+    /// Function was called with more arguments than `Call` instruction supports.
+    TooManyArguments(usize),
+    /// Block with this name is already defined in template.
+    DuplicateBlock(String),
+    /// `super()` is used outside of a block.
+    SuperOutsideBlock,
+    /// `super` is called with arguments or inside of an expression, only `{{ super() }}` is allowed.
+    InvalidSuperCall,
+    /// Expressions or blocks are nested deeper than `parser::MAX_DEPTH`.
+    TooDeeplyNested,
+    /// Generated template does not fit into addressable instruction range.
+    TooManyInstructions,
+}
+
+/// Error while parsing template source.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError {
+            kind: kind,
+            span: span,
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorKind::UnexpectedEof { expected } => write!(f, "Unexpected end of template, expected {}", expected),
+            ParseErrorKind::UnexpectedToken { ref found, expected } => write!(f, "Unexpected {}, expected {}", found, expected),
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "Unexpected character {:?}", c),
+            ParseErrorKind::UnterminatedString => write!(f, "Unterminated string literal"),
+            ParseErrorKind::InvalidNumber(ref n) => write!(f, "Invalid number {:?}", n),
+            ParseErrorKind::UnknownTag(ref t) => write!(f, "Unknown tag {:?}", t),
+            ParseErrorKind::UnclosedBlock { tag } => write!(f, "Block {:?} is not closed", tag),
+            ParseErrorKind::TooManyArguments(n) => write!(f, "Too many arguments ({}) in function call", n),
+            ParseErrorKind::DuplicateBlock(ref name) => write!(f, "Block {:?} is already defined", name),
+            ParseErrorKind::SuperOutsideBlock => write!(f, "Function \"super\" can only be called in a block"),
+            ParseErrorKind::InvalidSuperCall => write!(f, "Function \"super\" can only be called as {{{{ super() }}}}"),
+            ParseErrorKind::TooDeeplyNested => write!(f, "Template is nested too deeply"),
+            ParseErrorKind::TooManyInstructions => write!(f, "Template is too large"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.kind, self.span.start.line, self.span.start.column)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match self.kind {
+            ParseErrorKind::UnexpectedEof { .. } => "unexpected end of template",
+            ParseErrorKind::UnexpectedToken { .. } => "unexpected token",
+            ParseErrorKind::UnexpectedChar(_) => "unexpected character",
+            ParseErrorKind::UnterminatedString => "unterminated string",
+            ParseErrorKind::InvalidNumber(_) => "invalid number",
+            ParseErrorKind::UnknownTag(_) => "unknown tag",
+            ParseErrorKind::UnclosedBlock { .. } => "unclosed block",
+            ParseErrorKind::TooManyArguments(_) => "too many arguments",
+            ParseErrorKind::DuplicateBlock(_) => "duplicate block",
+            ParseErrorKind::SuperOutsideBlock => "super outside of block",
+            ParseErrorKind::InvalidSuperCall => "invalid super call",
+            ParseErrorKind::TooDeeplyNested => "nested too deeply",
+            ParseErrorKind::TooManyInstructions => "too many instructions",
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::error;
+use std::fmt;
+use {
+    Binding,
+    Call,
+    Constant,
+};
+
+/// Kind of malformed instruction stream error.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VerifyErrorKind {
+    /// Jump target is past the end of instructions.
+    JumpOutOfBounds { target: usize },
+    /// Instruction requires more stack items than available.
+    StackUnderflow { required: usize, depth: usize },
+    /// Instruction can be reached with different stack depths.
+    StackMismatch { expected: usize, found: usize },
+    /// `IterNext` can be reached without started iteration.
+    IterationMissing,
+    /// Instruction can be reached with different number of started iterations.
+    IterationMismatch { expected: usize, found: usize },
+    /// `Return` can be reached outside of subroutine.
+    ReturnWithoutCall,
+    /// Referenced constant does not exist in template.
+    ConstantMissing(Constant),
+    /// Referenced call does not exist in template.
+    CallMissing(Call),
+    /// Binding is outside of bindings that frame can use.
+    BindingOutOfBounds { binding: Binding, capacity: u32 },
+    /// Binding is not below `Limits::max_bindings`.
+    BindingLimitExceeded { binding: Binding, max: usize },
+    /// Subroutine can reach the end of instructions without `Return`.
+    MissingReturn,
+}
+
+/// Error in template instructions, found before execution.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    /// Index of the offending instruction.
+    pub pc: usize,
+}
+
+impl VerifyError {
+    pub fn new(kind: VerifyErrorKind, pc: usize) -> VerifyError {
+        VerifyError {
+            kind: kind,
+            pc: pc,
+        }
+    }
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyErrorKind::JumpOutOfBounds { target } => write!(f, "Jump target {} is out of bounds", target),
+            VerifyErrorKind::StackUnderflow { required, depth } => write!(f, "Instruction requires {} stack items, but stack has {}", required, depth),
+            VerifyErrorKind::StackMismatch { expected, found } => write!(f, "Instruction is reached with stack depth {} and {}", expected, found),
+            VerifyErrorKind::IterationMissing => write!(f, "Instruction continues iteration that was not started"),
+            VerifyErrorKind::IterationMismatch { expected, found } => write!(f, "Instruction is reached with {} and {} started iterations", expected, found),
+            VerifyErrorKind::ReturnWithoutCall => write!(f, "Return is reached outside of subroutine"),
+            VerifyErrorKind::ConstantMissing(c) => write!(f, "Constant {:?} is missing", c),
+            VerifyErrorKind::CallMissing(c) => write!(f, "Call {:?} is missing", c),
+            VerifyErrorKind::BindingOutOfBounds { binding, capacity } => write!(f, "{:?} is out of frame bindings capacity {}", binding, capacity),
+            VerifyErrorKind::BindingLimitExceeded { binding, max } => write!(f, "{:?} exceeds binding limit {}", binding, max),
+            VerifyErrorKind::MissingReturn => write!(f, "Subroutine continues past the last instruction without Return"),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at instruction {}", self.kind, self.pc)
+    }
+}
+
+impl error::Error for VerifyError {
+    fn description(&self) -> &str {
+        match self.kind {
+            VerifyErrorKind::JumpOutOfBounds { .. } => "jump out of bounds",
+            VerifyErrorKind::StackUnderflow { .. } => "stack underflow",
+            VerifyErrorKind::StackMismatch { .. } => "stack mismatch",
+            VerifyErrorKind::IterationMissing => "iteration is missing",
+            VerifyErrorKind::IterationMismatch { .. } => "iteration mismatch",
+            VerifyErrorKind::ReturnWithoutCall => "return without call",
+            VerifyErrorKind::ConstantMissing(_) => "constant is missing",
+            VerifyErrorKind::CallMissing(_) => "call is missing",
+            VerifyErrorKind::BindingOutOfBounds { .. } => "binding out of bounds",
+            VerifyErrorKind::BindingLimitExceeded { .. } => "binding limit exceeded",
+            VerifyErrorKind::MissingReturn => "missing return",
+        }
+    }
+}
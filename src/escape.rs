@@ -0,0 +1,125 @@
+use std::io;
+use std::io::Write;
+
+/// Escaping of `Output` instruction.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Escape {
+    /// Use escaping configured for executable.
+    Inherit,
+    /// Output value as is.
+    None,
+    /// Escape `&`, `<`, `>`, `"` and `'` for HTML text.
+    Html,
+    /// Escape all ASCII characters except letters and digits, safe for unquoted HTML attribute values.
+    HtmlAttribute,
+    /// Escape for inside of JSON string, quotes are not added.
+    Json,
+    /// Percent-encode all bytes except unreserved characters, for URL path segment or query component.
+    Url,
+}
+
+impl Escape {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Escape::Inherit => "inherit",
+            Escape::None => "none",
+            Escape::Html => "html",
+            Escape::HtmlAttribute => "attr",
+            Escape::Json => "json",
+            Escape::Url => "url",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Escape> {
+        Some(match name {
+            "inherit" => Escape::Inherit,
+            "none" => Escape::None,
+            "html" => Escape::Html,
+            "attr" => Escape::HtmlAttribute,
+            "json" => Escape::Json,
+            "url" => Escape::Url,
+            _ => return None,
+        })
+    }
+
+    /// Returns `default` if this is `Escape::Inherit`.
+    pub fn or(self, default: Escape) -> Escape {
+        match self {
+            Escape::Inherit => default,
+            other => other,
+        }
+    }
+}
+
+impl Default for Escape {
+    fn default() -> Escape {
+        Escape::Inherit
+    }
+}
+
+/// Writer that escapes everything written to it.
+///
+/// Escaped characters are ASCII, so escaping bytes of UTF-8 text one by one
+/// gives the same result as escaping the whole text.
+pub struct Escaper<'w, W: 'w> {
+    inner: &'w mut W,
+    escape: Escape,
+}
+
+impl<'w, W: Write> Escaper<'w, W> {
+    pub fn new(inner: &'w mut W, escape: Escape) -> Escaper<'w, W> {
+        Escaper {
+            inner: inner,
+            escape: escape,
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        match self.escape {
+            Escape::Inherit | Escape::None => self.inner.write_all(&[byte]),
+            Escape::Html => match byte {
+                b'&' => self.inner.write_all(b"&amp;"),
+                b'<' => self.inner.write_all(b"&lt;"),
+                b'>' => self.inner.write_all(b"&gt;"),
+                b'"' => self.inner.write_all(b"&quot;"),
+                b'\'' => self.inner.write_all(b"&#39;"),
+                _ => self.inner.write_all(&[byte]),
+            },
+            Escape::HtmlAttribute => match byte {
+                b'a' ..= b'z' | b'A' ..= b'Z' | b'0' ..= b'9' => self.inner.write_all(&[byte]),
+                _ if byte < 0x80 => write!(self.inner, "&#x{:02X};", byte),
+                _ => self.inner.write_all(&[byte]),
+            },
+            Escape::Json => match byte {
+                b'"' => self.inner.write_all(b"\\\""),
+                b'\\' => self.inner.write_all(b"\\\\"),
+                b'\n' => self.inner.write_all(b"\\n"),
+                b'\r' => self.inner.write_all(b"\\r"),
+                b'\t' => self.inner.write_all(b"\\t"),
+                b'<' | b'>' | b'&' => write!(self.inner, "\\u{:04x}", byte),
+                _ if byte < 0x20 || byte == 0x7f => write!(self.inner, "\\u{:04x}", byte),
+                _ => self.inner.write_all(&[byte]),
+            },
+            Escape::Url => match byte {
+                b'a' ..= b'z' | b'A' ..= b'Z' | b'0' ..= b'9' | b'-' | b'_' | b'.' | b'~' => self.inner.write_all(&[byte]),
+                _ => write!(self.inner, "%{:02X}", byte),
+            },
+        }
+    }
+}
+
+impl<'w, W: Write> Write for Escaper<'w, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Escape::Inherit | Escape::None = self.escape {
+            return self.inner.write(buf);
+        }
+        for &byte in buf {
+            try!(self.write_byte(byte));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -1,23 +1,29 @@
 //! Template interpreter.
 
 use std::io;
-use std::io::{ Read, Write };
-use std::collections::HashMap;
-use std::borrow::Cow;
+use std::io::Write;
+use std::mem;
+use std::collections::{ HashMap, HashSet };
+use std::sync::Arc;
+use std::time::Instant;
 
 use options;
+use machine::{ self, CallRef, Configure, Host, Machine, Progress, Run, Runtime, Step };
+use cache::{ self, NoStore, Persist, Store };
+use verifier;
+use optimizer::Optimizer;
+use buffer::OutputBuffer;
+use registry::{ IncludeContext, Render };
+use profiler::Profile;
 
 use {
-    Options,
     Call,
-    Constant,
-    Binding,
+    Limits,
     Instruction,
-    Cond,
-    Mem,
     Execute,
     Fingerprint,
     LittleValue,
+    Options,
     Template,
     Build,
     Function,
@@ -26,18 +32,64 @@ use {
     LittleResult,
 };
 
-const MAX_VALUES: usize = 500000;
-
 /// Executes template without compilation.
-pub struct Interpreter;
+pub struct Interpreter<S = NoStore> {
+    store: S,
+    optimizer: Optimizer,
+}
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        Interpreter
+        Interpreter {
+            store: NoStore,
+            optimizer: Optimizer::for_build(),
+        }
+    }
+
+    /// Create interpreter that saves built executables to `store`,
+    /// so that they can be restored later with `load`.
+    pub fn with_store<S: Store>(store: S) -> Interpreter<S> {
+        Interpreter {
+            store: store,
+            optimizer: Optimizer::for_build(),
+        }
     }
 }
 
-impl<'a, V: LittleValue + 'a> Build<'a, V> for Interpreter {
+impl<S> Interpreter<S> {
+    /// Replace optimizer that is run on templates before they are built,
+    /// the default is `Optimizer::for_build()`.
+    pub fn set_optimizer(&mut self, optimizer: Optimizer) {
+        self.optimizer = optimizer;
+    }
+
+    /// Builds executable that shares `functions` instead of borrowing them,
+    /// so that it can be kept after functions map is gone.
+    pub fn build_shared<'e, V: LittleValue + 'e>(
+        &mut self,
+        id: &str,
+        template: Template<V>,
+        functions: &HashMap<String, Arc<Function<V> + 'e>>
+    ) -> Result<Executable<'e, V>, BuildError> where S: Persist<V> {
+        let template = try!(self.prepare(id, template));
+        let calls = try!(machine::shared_calls(&template.calls_template, functions));
+        Ok(executable(id, template.env_fingerprint(), template, calls))
+    }
+
+    /// Verifies and optimizes template, then saves it to store.
+    fn prepare<V: LittleValue>(&mut self, id: &str, mut template: Template<V>) -> Result<Template<V>, BuildError>
+        where S: Persist<V>
+    {
+        try!(verifier::verify(&template));
+        let report = self.optimizer.optimize(&mut template);
+        debug!("optimize {:?}: {}", id, report);
+
+        try!(self.store.save(id, template.env_fingerprint(), &template));
+        Ok(template)
+    }
+}
+
+impl<'a, V: LittleValue + 'a, S: Persist<V> + 'a> Build<'a, V> for Interpreter<S> {
     type Output = Executable<'a, V>;
 
     /// Loads the interpreter's executable.
@@ -49,30 +101,65 @@ impl<'a, V: LittleValue + 'a> Build<'a, V> for Interpreter {
         template: Template<V>,
         calls: &'a HashMap<&'a str, &'a (Function<V> + 'a)>
     ) -> LittleResult<Executable<V>> {
-        Ok(Executable::<V> {
-            id: id.into(),
-            instructions: template.instructions,
-            constants: template.constants,
-            calls: match template.calls_template.build(calls) {
-                Ok(built) => built,
-                Err(options::Error::ParameterMissing(s)) => return Err(BuildError::FunctionNotFound { required: s }.into()),
-            },
-        })
+        let template = try!(self.prepare(id, template));
+
+        let calls = match template.calls_template.build(calls) {
+            Ok(built) => built,
+            Err(options::Error::ParameterMissing(s)) => return Err(BuildError::FunctionNotFound { required: s }.into()),
+        };
+
+        Ok(executable(id, template.env_fingerprint(), template, calls.map(CallRef::Borrowed)))
     }
 
     /// Loads existing executable by unique fingerprint and env fingerprint.
     fn load(&'a mut self, id: &str, env: Fingerprint, calls: &'a Vec<&'a (Function<V> + 'a)>)
         -> LittleResult<Self::Output>
     {
-        unreachable!("interpreter load is not implemented");
+        let template: Template<V> = try!(self.store.restore(id, env));
+
+        let calls = try!(cache::map_calls(&template.calls_template, calls));
+
+        Ok(executable(id, env, template, calls.map(CallRef::Borrowed)))
+    }
+}
+
+fn executable<'a, V>(id: &str, env: Fingerprint, template: Template<V>, calls: Options<Call, CallRef<'a, V>>) -> Executable<'a, V> {
+    Executable {
+        id: id.into(),
+        env: env,
+        runtime: Runtime::new(template.constants, calls, &template.calls_template, template.source_map, template.bindings_capacity),
+        instructions: template.instructions,
     }
 }
 
 pub struct Executable<'a, V: 'a> {
     id: String,
+    env: Fingerprint,
     instructions: Vec<Instruction>,
-    constants: Options<Constant, V>,
-    calls: Options<Call, &'a Function<V>>,
+    runtime: Runtime<'a, V>,
+}
+
+impl<'a, V: 'a> Configure<'a, V> for Executable<'a, V> {
+    fn runtime(&self) -> &Runtime<'a, V> {
+        &self.runtime
+    }
+
+    fn runtime_mut(&mut self) -> &mut Runtime<'a, V> {
+        &mut self.runtime
+    }
+}
+
+impl<'a, V: LittleValue + 'a> Executable<'a, V> {
+    /// Run this executable with limits other than configured for executable.
+    pub fn execute_with_limits(&'a self, data: V, limits: Limits) -> InterpreterStream<'a, V> {
+        InterpreterStream {
+            run: Run::new(Machine::new(&self.runtime, data, limits)),
+            executable: self,
+            breakpoints: HashSet::new(),
+            reported_breakpoint: None,
+            profile: None,
+        }
+    }
 }
 
 impl<'a, V: LittleValue + 'a> Execute<'a, V> for Executable<'a, V> {
@@ -80,238 +167,221 @@ impl<'a, V: LittleValue + 'a> Execute<'a, V> for Executable<'a, V> {
 
     fn execute(&'a self, data: V) -> InterpreterStream<'a, V> {
         InterpreterStream {
-            pc: 0,
-            buf: Vec::new(),
-            values: Values {
-                stack: Vec::new(),
-                values: Vec::new(),
-                executable: self,
-                parameters: data,
-            }
+            run: Run::new(Machine::new(&self.runtime, data, self.runtime.limits)),
+            executable: self,
+            breakpoints: HashSet::new(),
+            reported_breakpoint: None,
+            profile: None,
         }
     }
 
+    fn execute_into<W: Write>(&'a self, data: V, out: &mut W) -> Result<(), LittleError> {
+        self.execute(data).write_to(out)
+    }
+
     fn get_id<'r>(&'r self) -> &'r str {
         &self.id
     }
 
     fn identify_env(&self) -> Fingerprint {
-        Fingerprint::empty()
+        self.env
+    }
+}
+
+impl<'a, V: LittleValue + 'a> Render<V> for Executable<'a, V> {
+    fn render(&self, data: V, mut out: &mut io::Write, context: &mut IncludeContext<V>) -> Result<(), LittleError> {
+        let mut stream = self.execute_with_limits(data, context.limits);
+        stream.run.machine.include_from(context);
+        let result = stream.write_to(&mut out);
+        context.executed = stream.run.machine.executed();
+        context.output_bytes = stream.run.machine.output_bytes();
+        result
     }
 }
 
 pub struct InterpreterStream<'a, V: 'a> {
-    pc: usize,
-    buf: Vec<u8>,
-    values: Values<'a, V>,
+    run: Run<'a, V>,
+    executable: &'a Executable<'a, V>,
+    breakpoints: HashSet<usize>,
+    /// Breakpoint returned by the last debugger call, execution continues past it.
+    reported_breakpoint: Option<usize>,
+    profile: Option<Profile>,
 }
 
-enum ExecutionResult {
+/// Reason why debugger stopped execution.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DebugEvent {
+    /// Single instruction was executed.
+    Step,
+    /// Execution stopped before instruction with breakpoint at `pc`.
+    Breakpoint(usize),
+    /// `Output` instruction was executed.
+    Output,
+    /// `Interupt` instruction was executed.
+    Interupt(u16),
+    /// There are no more instructions.
     Done,
-    Continue,
-    Interupt,
 }
 
 impl<'a, V: LittleValue> InterpreterStream<'a, V> {
-    /// Returns specified number of stack items.
-    ///
-    /// If stack is smaller, returns None.
-    pub fn peek_stack<'r>(&'r self, slice_size: usize) -> Option<&'r [V]> {
-        let stack_len = self.values.stack.len();
+    /// Start collecting execution profile, existing profile is reset.
+    pub fn enable_profiler(&mut self) {
+        self.profile = Some(Profile::new(self.executable.instructions.len()));
+    }
 
-        if stack_len < slice_size {
-            return None;
-        }
+    /// Returns profile collected since `enable_profiler`.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
 
-        Some(&self.values.stack[stack_len - slice_size as usize .. stack_len])
+    /// Index of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.run.pc
     }
 
-    fn execute(&mut self) -> Result<ExecutionResult, LittleError>  {
-        match self.values.executable.instructions.get(self.pc) {
-            Some(i) => {
-                match *i {
-                    Instruction::Output { ref location } => {
-                        debug!("Output (location: {:?})", location);
-                        try!(write!(self.buf, "{}", try!(self.values.get_mem_value(location))))
-                    },
-                    Instruction::Property { ref name } => {
-                        debug!("Property (name: {:?})", name);
-                        let name = try!(self.values.get_mem_value(name)).into_owned();
-                        trace!("property name {}", name);
-                        let obj = match self.values.stack.pop() {
-                            None => return Err(LittleError::StackUnderflow),
-                            Some(v) => v,
-                        };
-                        self.values.stack.push(obj.get_property(name).unwrap());
-                    },
-                    Instruction::Pop { mut times } => while times > 0 {
-                        debug!("Pop (times: {:?})", times);
-                        if let None = self.values.stack.pop() {
-                            return Err(LittleError::StackUnderflow);
-                        }
-                        times -= 1;
-                    },
-                    Instruction::Push { ref location } => {
-                        debug!("Push (location: {:?})", location);
-                        let value = try!(self.values.get_mem_value(location)).into_owned();
-                        self.values.stack.push(value);
-                    },
-                    Instruction::Load { binding, ref location } => {
-                        debug!("Load (binding: {:?}, location: {:?})", binding, location);
-                        let value = try!(self.values.get_mem_value(location)).into_owned();
-                        self.values.set(binding, value);
-                    },
-                    Instruction::Jump { pc } => {
-                        debug!("Jump (pc: {:?})", pc);
-                        self.pc = pc as usize;
-                        return Ok(ExecutionResult::Continue);
-                    },
-                    Instruction::CondJump { pc, ref location, test } => {
-                        debug!("CondJump (pc: {:?}, location: {:?}, test: {:?})", pc, location, test);
-                        let value = try!(self.values.get_mem_value(location));
-                        let value_ref = value.as_ref();
-                        let stack = match self.values.stack.last() {
-                            Some(value) => value,
-                            None => return Err(LittleError::StackUnderflow),
-                        };
-                        let should_jump = match test {
-                            Cond::Eq => stack == value_ref,
-                            Cond::Gt => stack > value_ref,
-                            Cond::Gte => stack >= value_ref,
-                            Cond::Lt => stack < value_ref,
-                            Cond::Lte => stack <= value_ref,
-                            Cond::Ne => stack != value_ref,
-                        };
-                        if should_jump {
-                            self.pc = pc as usize;
-                            return Ok(ExecutionResult::Continue);
-                        }
-                    },
-                    Instruction::Call { call, argc, push_result_to_stack } => {
-                        debug!("Call (call: {:?}, argc: {:?}, push_result_to_stack: {:?})", call, argc, push_result_to_stack);
-                        let fun = match self.values.executable.calls.get(call) {
-                            Some(f) => f,
-                            None => return Err(LittleError::CallMissing(call)),
-                        };
-
-                        let stack_len = self.values.stack.len();
-                        let result = fun.invoke(&self.values.stack[stack_len - argc as usize .. stack_len]);
-
-                        if push_result_to_stack {
-                            self.values.stack.push(result.unwrap());
-                        }
-                    },
-                    Instruction::Interupt => {
-                        debug!("Interupt");
-                        self.pc += 1;
-                        return Ok(ExecutionResult::Interupt);
-                    }
-                };
-                self.pc += 1;
-                Ok(ExecutionResult::Continue)
-            },
-            None => Ok(ExecutionResult::Done),
-        }
+    /// Returns the next instruction, or None if execution is done.
+    pub fn instruction(&self) -> Option<&'a Instruction> {
+        self.executable.instructions.get(self.run.pc)
     }
 
-    fn consume_buf(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let self_buf_len = self.buf.len();
-        if self_buf_len >= buf.len() {
-            for (i, o) in self.buf.drain(..buf.len()).zip(buf.iter_mut()) {
-                *o = i
-            }
-            Ok(buf.len())
-        } else {
-            for (i, o) in self.buf.drain(..).zip(&mut buf[..self_buf_len]) {
-                *o = i
-            }
-            Ok(self_buf_len)
-        }
+    /// Returns the whole stack, the last value is on top.
+    pub fn stack<'r>(&'r self) -> &'r [V] {
+        &self.run.machine.stack
     }
-}
 
-impl<'a, V: LittleValue> io::Read for InterpreterStream<'a, V> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        loop {
-            if self.buf.len() >= buf.len() {
-                break;
-            }
+    /// Returns bindings of the current subroutine frame set so far, bindings past the end have default value.
+    pub fn bindings<'r>(&'r self) -> &'r [V] {
+        let machine = &self.run.machine;
+        if machine.base < machine.values.len() { &machine.values[machine.base..] } else { &[] }
+    }
 
-            match self.execute() {
-                Ok(res) => match res {
-                    ExecutionResult::Done => return self.consume_buf(buf),
-                    ExecutionResult::Continue => (),
-                    ExecutionResult::Interupt => return Err(io::Error::new(io::ErrorKind::Other, LittleError::Interupt)),
-                },
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
-            }
-        }
+    /// Returns output that was produced but not yet read.
+    pub fn pending_output<'r>(&'r self) -> &'r [u8] {
+        self.run.buf.as_slice()
+    }
 
-        self.consume_buf(buf)
+    /// Make `step`, `step_output` and `resume` stop before executing instruction at `pc`.
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
     }
-}
 
-struct Values<'a, V: 'a> {
-    stack: Vec<V>,
-    values: Vec<V>,
-    parameters: V,
-    executable: &'a Executable<'a, V>,
-}
+    /// Returns false if there was no breakpoint at `pc`.
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
 
-impl<'a, V: LittleValue> Values<'a, V> {
-    fn get_const(&self, i: Constant) -> Result<Cow<V>, LittleError> {
-        match self.executable.constants.get(i) {
-            Some(value) => Ok(Cow::Borrowed(value)),
-            None => return Err(LittleError::ConstantMissing(i)),
-        }
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
     }
 
-    fn get_mem_value(&self, mem: &Mem) -> Result<Cow<V>, LittleError> {
-        Ok(match *mem {
-            Mem::Binding(i) => self.get(i),
-            Mem::Parameter { name: name_constant } => {
-                let name = try!(self.get_const(name_constant));
-                let value = match self.parameters.get_property(name.into_owned()) {
-                    Some(value) => value,
-                    None => return Err(LittleError::ParameterMissing(name_constant)),
-                };
-                Cow::Owned(value)
-            },
-            Mem::Parameters => { Cow::Borrowed(&self.parameters) },
-            Mem::Const(i) => try!(self.get_const(i)),
-            Mem::StackTop1 => match self.stack.last() {
-                Some(value) => Cow::Borrowed(value),
-                None => return Err(LittleError::StackUnderflow),
-            },
-            Mem::StackTop2 => match self.stack.get(self.stack.len() - 2) {
-                Some(value) => Cow::Borrowed(value),
-                None => return Err(LittleError::StackUnderflow),
-            },
+    /// Execute a single instruction.
+    pub fn step(&mut self) -> Result<DebugEvent, LittleError> {
+        self.debug_run(|_| Some(DebugEvent::Step))
+    }
+
+    /// Execute instructions until `Output` instruction is executed or breakpoint is reached.
+    pub fn step_output(&mut self) -> Result<DebugEvent, LittleError> {
+        self.debug_run(|i| match *i {
+            Instruction::Output { .. } => Some(DebugEvent::Output),
+            _ => None,
         })
     }
 
-    fn set(&mut self, Binding(index): Binding, value: V) {
-        let i = index as usize;
-        self.ensure_capacity_for_index(i);
-        * unsafe { self.values.get_unchecked_mut(i) } = value;
+    /// Execute instructions until breakpoint is reached.
+    ///
+    /// Output is kept in the pending buffer, it can be read from the stream later.
+    pub fn resume(&mut self) -> Result<DebugEvent, LittleError> {
+        self.debug_run(|_| None)
     }
 
-    fn get<'r>(&'r self, Binding(index): Binding) -> Cow<'r, V> {
-        let i = index as usize;
-        if i >= self.values.len() {
-            Cow::Owned(V::default())
-        } else {
-            Cow::Borrowed(self.values.get(i).unwrap())
-        }
+    /// Executes instructions until `stop` returns an event, breakpoint is reached
+    /// or execution is interupted.
+    ///
+    /// Breakpoint that was reported by the previous call is passed.
+    fn debug_run<F: Fn(&Instruction) -> Option<DebugEvent>>(&mut self, stop: F) -> Result<DebugEvent, LittleError> {
+        let mut pending = mem::replace(&mut self.run.buf, OutputBuffer::new());
+        let result = self.debug_run_into(&mut pending, stop);
+        self.run.buf = pending;
+        result
     }
 
-    fn ensure_capacity_for_index(&mut self, index: usize) {
-        let required_len = index + 1;
-        if required_len > MAX_VALUES {
-            panic!("maximum number of values {} reached!", MAX_VALUES);
+    fn debug_run_into<F: Fn(&Instruction) -> Option<DebugEvent>>(&mut self, out: &mut OutputBuffer, stop: F) -> Result<DebugEvent, LittleError> {
+        self.run.interupt = None;
+        let mut reported = self.reported_breakpoint.take();
+        loop {
+            let pc = self.run.pc;
+            if self.breakpoints.contains(&pc) && reported != Some(pc) {
+                self.reported_breakpoint = Some(pc);
+                return Ok(DebugEvent::Breakpoint(pc));
+            }
+            reported = None;
+
+            let instruction = match self.instruction() {
+                Some(i) => i,
+                None => return Ok(DebugEvent::Done),
+            };
+            match try!(self.execute_next(out)) {
+                Progress::Done => return Ok(DebugEvent::Done),
+                Progress::Interupt(code) => {
+                    self.run.interupt = Some(code);
+                    return Ok(DebugEvent::Interupt(code));
+                },
+                Progress::Continue => if let Some(event) = stop(instruction) {
+                    return Ok(event);
+                },
+            }
         }
-        if required_len > self.values.len() {
-            self.values.resize(required_len, V::default());
+    }
+}
+
+impl<'a, V: LittleValue> Host<'a, V> for InterpreterStream<'a, V> {
+    fn run(&self) -> &Run<'a, V> {
+        &self.run
+    }
+
+    fn run_mut(&mut self) -> &mut Run<'a, V> {
+        &mut self.run
+    }
+
+    fn execute_next<W: Write>(&mut self, out: &mut W) -> Result<Progress, LittleError> {
+        let executable = self.executable;
+        let run = &mut self.run;
+        match executable.instructions.get(run.pc) {
+            Some(i) => {
+                let output_bytes = run.machine.output_bytes();
+                let started = match (&self.profile, i) {
+                    (&Some(_), &Instruction::Call { .. }) => Some(Instant::now()),
+                    _ => None,
+                };
+                let step = try!(run.machine.execute(run.pc, i, out));
+                if let Some(ref mut profile) = self.profile {
+                    profile.count_instruction(run.pc, run.machine.output_bytes() - output_bytes);
+                    if let (Some(started), &Instruction::Call { call, .. }) = (started, i) {
+                        let name = executable.runtime.call_names.get(&call).map(|n| &n[..]).unwrap_or("");
+                        profile.count_call(name, started.elapsed());
+                    }
+                }
+                match step {
+                    Step::Next => run.pc += 1,
+                    Step::Jump(pc) => run.pc = pc,
+                    Step::Call(pc) => {
+                        run.machine.enter_frame(run.pc + 1);
+                        run.pc = pc;
+                    },
+                    Step::Interupt(code) => {
+                        run.pc += 1;
+                        return Ok(Progress::Interupt(code));
+                    },
+                };
+                Ok(Progress::Continue)
+            },
+            None => Ok(Progress::Done),
         }
     }
 }
+
+impl<'a, V: LittleValue> io::Read for InterpreterStream<'a, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_output(buf)
+    }
+}
@@ -16,17 +16,42 @@ use byteorder::{ WriteBytesExt, LittleEndian };
 mod options;
 mod template;
 mod error;
+mod machine;
+mod limits;
+mod source_map;
+mod escape;
+mod buffer;
 
 pub mod interpreter;
 pub mod compiler;
 pub mod stream;
 pub mod bytecode;
+pub mod parser;
+pub mod cache;
+pub mod verifier;
+pub mod asm;
+pub mod optimizer;
+pub mod profiler;
+pub mod registry;
+pub mod link;
+pub mod environment;
+pub mod loader;
+pub mod sha1;
 
 pub use options::{ OptionsTemplate, Options };
-pub use template::{ Template };
+pub use template::{ Template, Block };
+pub use limits::Limits;
+pub use source_map::{ SourceMap, SourceSpan, SourceLocation };
+pub use escape::Escape;
 pub use error::seek::SeekError;
 pub use error::little::{ LittleError, LittleResult };
 pub use error::build::{ BuildError };
+pub use error::parse::{ ParseError, ParseErrorKind };
+pub use error::verify::{ VerifyError, VerifyErrorKind };
+pub use error::asm::{ AsmError, AsmErrorKind };
+pub use error::link::LinkError;
+pub use environment::Environment;
+pub use machine::{ Configure, Host };
 
 /// Mutable internal machine binding.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -59,8 +84,8 @@ pub enum Mem {
 
 /// Jump condition.
 ///
-/// Used by `CondJump` instruction.
-#[derive(Copy, Clone, Debug)]
+/// Used by `CondJump` and `Compare` instructions.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Cond {
     /// Jump if stack value equals `Mem`.
     Eq,
@@ -76,11 +101,51 @@ pub enum Cond {
     Lte,
 }
 
+/// Arithmetic operator.
+///
+/// Used by `Operate` instruction, stack value is the left operand.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operator {
+    /// Add `Mem` to stack value.
+    Add,
+    /// Subtract `Mem` from stack value.
+    Sub,
+    /// Multiply stack value by `Mem`.
+    Mul,
+    /// Divide stack value by `Mem`.
+    Div,
+    /// Remainder of dividing stack value by `Mem`.
+    Rem,
+}
+
+impl Operator {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Operator::Add => "add",
+            Operator::Sub => "sub",
+            Operator::Mul => "mul",
+            Operator::Div => "div",
+            Operator::Rem => "rem",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Operator> {
+        Some(match name {
+            "add" => Operator::Add,
+            "sub" => Operator::Sub,
+            "mul" => Operator::Mul,
+            "div" => Operator::Div,
+            "rem" => Operator::Rem,
+            _ => return None,
+        })
+    }
+}
+
 /// Executable template instruction.
 #[derive(Copy, Clone, Debug)]
 pub enum Instruction {
-    /// Output specified `Mem`.
-    Output { location: Mem },
+    /// Output specified `Mem` escaped with `Escape`.
+    Output { location: Mem, escape: Escape },
     /// Replace a value in `StackTop1` with its property named `Mem`.
     Property { name: Mem },
     /// Push data from `Mem` to stack.
@@ -95,8 +160,32 @@ pub enum Instruction {
     Call { call: Call, argc: u8, push_result_to_stack: bool },
     /// Copy value from `Mem` to `Binding`.
     Load { binding: Binding, location: Mem },
-    /// Interupt execution, it is up to the user to know what to do with the stack at current state.
-    Interupt,
+    /// Interupt execution with code, it is up to the user to know what to do with the stack at current state.
+    ///
+    /// Execution resumes at the next instruction when the stream is read again.
+    Interupt { code: u16 },
+    /// Start iteration over items of `Mem`, see `IterateValue`.
+    IterStart { location: Mem },
+    /// Load the next item of the last started iteration to `Binding`,
+    /// or end the iteration and jump to instruction if there are no more items.
+    IterNext { binding: Binding, pc: u16 },
+    /// Replace a value in `StackTop1` with the result of `Operator` applied to it and `Mem`.
+    Operate { op: Operator, location: Mem },
+    /// Replace a value in `StackTop1` with its negation.
+    Negate,
+    /// Replace a value in `StackTop1` with boolean that is true if the value equals `V::default()`.
+    Not,
+    /// Replace a value in `StackTop1` with boolean result of `Cond` test against `Mem`.
+    Compare { location: Mem, test: Cond },
+    /// Render executable named by `name` from the registry with `location` as its parameters.
+    Include { name: Mem, location: Mem },
+    /// Call subroutine at instruction, execution continues at the next instruction after `Return`.
+    ///
+    /// Bindings of subroutine start after bindings of the caller, so `Binding(bindings_capacity + n)`
+    /// of the caller is `Binding(n)` of the subroutine.
+    CallLocal { pc: u16 },
+    /// Return from subroutine.
+    Return,
 }
 
 /// External template function.
@@ -112,8 +201,46 @@ impl<V, F: for<'z> Fn(&'z [V]) -> LittleResult<V>> Function<V> for F {
     }
 }
 
+/// Handler of errors returned by functions.
+///
+/// Receives `LittleError::CallError` and call arguments. Returned value is
+/// used instead of the call result, `None` fails execution with the error.
+pub trait CallErrorHandler<V> {
+    fn handle<'r>(&self, &LittleError, &'r [V]) -> Option<V>;
+}
+
+impl<V, F: for<'z> Fn(&LittleError, &'z [V]) -> Option<V>> CallErrorHandler<V> for F {
+    fn handle<'r>(&self, error: &LittleError, args: &'r [V]) -> Option<V> {
+        self(error, args)
+    }
+}
+
+/// Handler of properties and parameters that were not found.
+///
+/// Receives the object and the name of property. Returned value is
+/// used instead of the property, `None` fails execution.
+pub trait MissingHandler<V> {
+    fn handle(&self, &V, &V) -> Option<V>;
+}
+
+impl<V, F: Fn(&V, &V) -> Option<V>> MissingHandler<V> for F {
+    fn handle(&self, object: &V, name: &V) -> Option<V> {
+        self(object, name)
+    }
+}
+
+/// What to do when `Instruction::Property` or `Mem::Parameter` can not find a value.
+pub enum MissingPolicy<'a, V> {
+    /// Fail with `LittleError::PropertyMissing` or `LittleError::ParameterMissing`.
+    Error,
+    /// Use `V::default()`.
+    Default,
+    /// Ask handler for the value.
+    Hook(Box<MissingHandler<V> + 'a>),
+}
+
 /// Structure used to uniquely identify executable blobs.
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub struct Fingerprint([u8;20]);
 
 impl Fingerprint {
@@ -126,6 +253,16 @@ impl Fingerprint {
     }
 }
 
+/// Lowercase hex representation.
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in &self.0 {
+            try!(write!(f, "{:02x}", b));
+        }
+        Ok(())
+    }
+}
+
 /// Converts template into a runable version.
 ///
 /// Consumes `Template` and produces object that has `Run` trait,
@@ -155,6 +292,18 @@ pub trait Execute<'a, V> {
     /// Run this executable.
     fn execute(&'a self, V) -> Self::Stream;
 
+    /// Run this executable writing output directly to `out` as instructions run.
+    ///
+    /// Unlike reading the stream, interupt stops execution with `LittleError::Interupt`.
+    fn execute_into<W: io::Write>(&'a self, V, &mut W) -> Result<(), LittleError>;
+
+    /// Run this executable appending output to `out`.
+    fn execute_fmt<W: fmt::Write>(&'a self, data: V, out: &mut W) -> Result<(), LittleError> {
+        let mut writer = stream::FmtWriter::new(out);
+        try!(self.execute_into(data, &mut writer));
+        writer.finish().map_err(LittleError::from)
+    }
+
     /// Get executable's id.
     fn get_id<'r>(&'r self) -> &'r str;
 
@@ -183,57 +332,57 @@ pub trait Sha1Hasher {
     /// Write a single `u16` into this hasher.
     #[inline]
     fn write_u16(&mut self, i: u16) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;2];
-        buf_ref.write_u16::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 2];
+        (&mut buf[..]).write_u16::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `u32` into this hasher.
     #[inline]
     fn write_u32(&mut self, i: u32) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;4];
-        buf_ref.write_u32::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 4];
+        (&mut buf[..]).write_u32::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `u64` into this hasher.
     #[inline]
     fn write_u64(&mut self, i: u64) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;8];
-        buf_ref.write_u64::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 8];
+        (&mut buf[..]).write_u64::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `i8` into this hasher.
     #[inline]
     fn write_i8(&mut self, i: i8) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;1];
-        buf_ref.write_i8(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 1];
+        (&mut buf[..]).write_i8(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `i16` into this hasher.
     #[inline]
     fn write_i16(&mut self, i: i16) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;2];
-        buf_ref.write_i16::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 2];
+        (&mut buf[..]).write_i16::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `i32` into this hasher.
     #[inline]
     fn write_i32(&mut self, i: i32) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;4];
-        buf_ref.write_i32::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 4];
+        (&mut buf[..]).write_i32::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 
     /// Write a single `i64` into this hasher.
     #[inline]
     fn write_i64(&mut self, i: i64) {
-        let mut buf_ref: &mut [u8] = &mut [0u8;8];
-        buf_ref.write_i64::<LittleEndian>(i).unwrap();
-        self.write(buf_ref);
+        let mut buf = [0u8; 8];
+        (&mut buf[..]).write_i64::<LittleEndian>(i).unwrap();
+        self.write(&buf);
     }
 }
 
@@ -241,16 +390,68 @@ pub trait GetProperty<V> {
     fn get_property(&self, name: V) -> Option<V>;
 }
 
+/// Access to items of a list or map value, used by `IterStart` and `IterNext` instructions.
+///
+/// Values are not iterable by default.
+pub trait IterateValue<V> {
+    /// Number of items, `None` if value can not be iterated.
+    fn iterate_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Item at `index`, returning `None` ends the iteration.
+    fn iterate_item(&self, _index: usize) -> Option<V> {
+        None
+    }
+}
+
+/// Operators used by `Operate`, `Negate`, `Not` and `Compare` instructions.
+///
+/// Values support no operators by default, unsupported operations fail
+/// with `LittleError::InvalidOperation`.
+pub trait OperateValue<V> {
+    /// Result of `self op other`, `None` if operator is not defined for these values.
+    fn operate(&self, _op: Operator, _other: &V) -> Option<V> {
+        None
+    }
+
+    /// Result of `-self`.
+    fn negate(&self) -> Option<V> {
+        None
+    }
+
+    /// Boolean value pushed by `Compare` and `Not`.
+    ///
+    /// `false` should equal `V::default()`, so that template `if` treats it as false.
+    fn from_bool(_value: bool) -> Option<V> {
+        None
+    }
+}
+
 /// Little Value abstraction, used by runtime.
 pub trait LittleValue :
     Default +
     GetProperty<Self> +
+    IterateValue<Self> +
+    OperateValue<Self> +
     PartialEq +
     PartialOrd +
     Clone +
     IdentifyValue +
     fmt::Display
-{ }
+{
+    /// Create a value that displays as `self` followed by `other`.
+    ///
+    /// Used by optimizer to merge constant output, values are not merged if `None`.
+    fn concat_output(&self, _other: &Self) -> Option<Self> {
+        None
+    }
+
+    /// Value is already escaped and should be output as is by any `Escape`.
+    fn is_safe(&self) -> bool {
+        false
+    }
+}
 
 /// Seek to an offset.
 pub trait PositionSeek {
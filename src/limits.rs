@@ -0,0 +1,40 @@
+/// Resource limits of a single execution.
+///
+/// `None` means there is no limit.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Maximum number of executed instructions.
+    pub max_instructions: Option<u64>,
+    /// Maximum number of values on stack.
+    pub max_stack_depth: Option<usize>,
+    /// Maximum number of bindings.
+    pub max_bindings: Option<usize>,
+    /// Maximum number of output bytes.
+    pub max_output_bytes: Option<u64>,
+    /// Maximum number of nested includes.
+    pub max_include_depth: Option<usize>,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub fn unlimited() -> Limits {
+        Limits {
+            max_instructions: None,
+            max_stack_depth: None,
+            max_bindings: None,
+            max_output_bytes: None,
+            max_include_depth: None,
+        }
+    }
+}
+
+/// Bindings are limited to 500000 and include depth to 64 by default.
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_bindings: Some(500000),
+            max_include_depth: Some(64),
+            .. Limits::unlimited()
+        }
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+Template inheritance.
+
+A parent template renders named blocks with `CallLocal`, a child template
+defines blocks with the same names. `link` produces a single template that
+runs the parent code with child blocks in place of parent ones:
+
+```ignore
+let layout = parser::parse_file("layout", "<title>{% block title %}Site{% endblock %}</title>").unwrap();
+let page = parser::parse_file("page", "{% block title %}Page - {{ super() }}{% endblock %}").unwrap();
+
+let template = link::link(layout, page).unwrap();
+```
+
+Child code outside of blocks is not rendered. Blocks that only the child
+defines are kept, so that other child blocks can call them.
+
+`super` calls of child blocks (`Block::super_calls`) are linked to the parent
+version of the block. The linked template keeps all blocks and `super` calls
+that are still unresolved, so it can be linked again in either direction:
+`link(base, link(layout, page))` renders the same as `link(link(base, layout), page)`.
+
+Child constants are renumbered after parent constants, child calls reuse
+parent call indices of functions with the same name.
+*/
+
+use std::collections::HashMap;
+
+use {
+    Block,
+    Call,
+    Constant,
+    Instruction,
+    LinkError,
+    Mem,
+    SourceMap,
+    Template,
+};
+
+/// Link `child` blocks into `parent` template.
+pub fn link<V>(parent: Template<V>, mut child: Template<V>) -> Result<Template<V>, LinkError> {
+    // Parent code is followed by a jump over child code.
+    let len = parent.instructions.len() + 1 + child.instructions.len();
+    if len > u16::MAX as usize {
+        return Err(LinkError::TooManyInstructions(len));
+    }
+    let offset = parent.instructions.len() as u16 + 1;
+
+    let source_map = link_source_maps(&parent, &child);
+    let mut template = Template::new(
+        parent.constants,
+        parent.calls_template,
+        Vec::with_capacity(len),
+        parent.bindings_capacity.max(child.bindings_capacity)
+    );
+    template.source_map = source_map;
+
+    let constant_offset = template.constants.iter()
+        .map(|(&Constant(c), _)| c + 1)
+        .max()
+        .unwrap_or(0);
+    let child_constants: Vec<Constant> = child.constants.iter().map(|(&c, _)| c).collect();
+    for Constant(c) in child_constants {
+        if let Some(value) = child.constants.remove(Constant(c)) {
+            template.constants.push(Constant(c + constant_offset), value);
+        }
+    }
+
+    let mut next_call = template.calls_template.iter()
+        .map(|(_, &Call(c))| c + 1)
+        .max()
+        .unwrap_or(0);
+    let mut calls = HashMap::new();
+    for (name, &call) in child.calls_template.iter() {
+        let linked = match template.calls_template.index_of(name) {
+            Some(linked) => linked,
+            None => {
+                next_call += 1;
+                Call(next_call - 1)
+            },
+        };
+        template.calls_template.push(name.as_str(), linked);
+        calls.insert(call, linked);
+    }
+
+    let child_blocks: HashMap<String, u16> = child.blocks.iter()
+        .map(|block| (block.name.clone(), block.pc + offset))
+        .collect();
+
+    // Parent calls of overridden blocks render child blocks instead.
+    let mut overrides = HashMap::new();
+    for block in &parent.blocks {
+        if let Some(&pc) = child_blocks.get(&block.name) {
+            overrides.insert(block.pc, pc);
+        }
+    }
+    for instruction in parent.instructions {
+        template.instructions.push(match instruction {
+            Instruction::CallLocal { pc } => Instruction::CallLocal { pc: *overrides.get(&pc).unwrap_or(&pc) },
+            other => other,
+        });
+    }
+    template.instructions.push(Instruction::Jump { pc: len as u16 });
+
+    // Child `super` calls render parent blocks.
+    let mut supers = HashMap::new();
+    for block in &child.blocks {
+        if let Some(parent_block) = parent.blocks.iter().find(|b| b.name == block.name) {
+            for &pc in &block.super_calls {
+                supers.insert(pc, parent_block.pc);
+            }
+        }
+    }
+    let map_mem = |location: Mem| match location {
+        Mem::Const(Constant(c)) => Mem::Const(Constant(c + constant_offset)),
+        Mem::Parameter { name: Constant(c) } => Mem::Parameter { name: Constant(c + constant_offset) },
+        other => other,
+    };
+    for (pc, instruction) in child.instructions.into_iter().enumerate() {
+        template.instructions.push(match instruction {
+            Instruction::Output { location, escape } => Instruction::Output { location: map_mem(location), escape: escape },
+            Instruction::Property { name } => Instruction::Property { name: map_mem(name) },
+            Instruction::Push { location } => Instruction::Push { location: map_mem(location) },
+            Instruction::Jump { pc } => Instruction::Jump { pc: pc + offset },
+            Instruction::CondJump { pc, location, test } => Instruction::CondJump { pc: pc + offset, location: map_mem(location), test: test },
+            Instruction::Call { call, argc, push_result_to_stack } => Instruction::Call {
+                call: *calls.get(&call).unwrap_or(&call),
+                argc: argc,
+                push_result_to_stack: push_result_to_stack,
+            },
+            Instruction::Load { binding, location } => Instruction::Load { binding: binding, location: map_mem(location) },
+            Instruction::IterStart { location } => Instruction::IterStart { location: map_mem(location) },
+            Instruction::IterNext { binding, pc } => Instruction::IterNext { binding: binding, pc: pc + offset },
+            Instruction::Operate { op, location } => Instruction::Operate { op: op, location: map_mem(location) },
+            Instruction::Compare { location, test } => Instruction::Compare { location: map_mem(location), test: test },
+            Instruction::Include { name, location } => Instruction::Include { name: map_mem(name), location: map_mem(location) },
+            Instruction::CallLocal { pc: target } => Instruction::CallLocal {
+                pc: match supers.get(&(pc as u16)) {
+                    Some(&parent_pc) => parent_pc,
+                    None => target + offset,
+                },
+            },
+            other @ Instruction::Pop { .. }
+            | other @ Instruction::Interupt { .. }
+            | other @ Instruction::Negate
+            | other @ Instruction::Not
+            | other @ Instruction::Return => other,
+        });
+    }
+
+    // The most derived version of every block, with `super` calls left to link.
+    for block in parent.blocks {
+        let mut linked = Block::new(block.name.as_str(), *child_blocks.get(&block.name).unwrap_or(&block.pc));
+        linked.super_calls = block.super_calls;
+        template.blocks.push(linked);
+    }
+    for block in child.blocks {
+        if template.block(&block.name).is_some() {
+            continue;
+        }
+        let mut linked = Block::new(block.name, block.pc + offset);
+        linked.super_calls = block.super_calls.iter().map(|&pc| pc + offset).collect();
+        template.blocks.push(linked);
+    }
+
+    Ok(template)
+}
+
+/// Source map of linked template, `None` if neither template has one.
+fn link_source_maps<V>(parent: &Template<V>, child: &Template<V>) -> Option<SourceMap> {
+    if parent.source_map.is_none() && child.source_map.is_none() {
+        return None;
+    }
+
+    let mut source_map = SourceMap::new();
+    push_spans(&mut source_map, parent);
+    // Jump over child code.
+    source_map.push(None);
+    push_spans(&mut source_map, child);
+    Some(source_map)
+}
+
+/// Appends spans of all template instructions, with file indices of `source_map`.
+fn push_spans<V>(source_map: &mut SourceMap, template: &Template<V>) {
+    let files: Vec<u32> = match template.source_map {
+        Some(ref map) => map.files.iter().map(|file| source_map.add_file(file.as_str())).collect(),
+        None => Vec::new(),
+    };
+    for pc in 0..template.instructions.len() {
+        let span = template.source_map.as_ref().and_then(|map| map.get(pc));
+        source_map.push(span.map(|mut span| {
+            span.file = files.get(span.file as usize).cloned().unwrap_or(span.file);
+            span
+        }));
+    }
+}
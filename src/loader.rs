@@ -0,0 +1,312 @@
+/*!
+Templates loaded by name.
+
+`TemplateLoader` finds template source or bytecode by name, together with a
+modification stamp that changes when the template changes:
+
+```
+use little::loader::{ ChainLoader, DirectoryLoader, MemoryLoader, TemplateLoader };
+
+let mut overrides = MemoryLoader::new();
+overrides.insert_source("partials/nav", "<nav></nav>");
+
+let loader = ChainLoader::new()
+    .with(overrides)
+    .with(DirectoryLoader::new("templates").with_extension("html"));
+
+assert!(loader.load("partials/nav").is_ok());
+```
+
+Names are paths separated by `/`. Names that start with `./` or `../` are
+relative to the template that uses them, so that `pages/home` can include
+`../partials/nav`, see `resolve_name`.
+
+`environment::Environment` loads templates that were not added to it from a loader.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{ Component, Path, PathBuf };
+use std::time::UNIX_EPOCH;
+
+use bytecode::{ self, Bytecode, ValueSerializer };
+use {
+    BuildError,
+    Template,
+    parser,
+};
+
+/// Template source or precompiled template.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TemplateSource {
+    /// Template language source.
+    Text(String),
+    /// Template written with `Bytecode::write_bytecode`.
+    Bytecode(Vec<u8>),
+}
+
+/// Template found by loader.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoadedTemplate {
+    pub source: TemplateSource,
+    /// Modification stamp, the same as returned by `TemplateLoader::modified`.
+    pub modified: u64,
+}
+
+impl LoadedTemplate {
+    /// Parse or read template, `name` is used as source file name.
+    pub fn into_template<V>(self, name: &str) -> Result<Template<V>, BuildError>
+        where V: Default + From<String> + From<i64> + ValueSerializer
+    {
+        match self.source {
+            TemplateSource::Text(text) => Ok(try!(parser::parse_file(name, &text))),
+            TemplateSource::Bytecode(bytes) => Ok(try!(Template::read_bytecode(&mut io::Cursor::new(&bytes[..])))),
+        }
+    }
+}
+
+/// Finds templates by name.
+pub trait TemplateLoader {
+    /// Load template, fails with `BuildError::ExecutableNotFound` if there is no such template.
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError>;
+
+    /// Modification stamp of template, it changes when the template changes.
+    fn modified(&self, name: &str) -> Result<u64, BuildError>;
+}
+
+impl<L: TemplateLoader + ?Sized> TemplateLoader for Box<L> {
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError> {
+        (**self).load(name)
+    }
+
+    fn modified(&self, name: &str) -> Result<u64, BuildError> {
+        (**self).modified(name)
+    }
+}
+
+/// Resolve template `name` used by template `from`.
+///
+/// Names that start with `./` or `../` are relative to the directory of `from`,
+/// other names are relative to loader root. `.` and `..` parts are removed,
+/// fails with `BuildError::InvalidName` if the name leads outside of root.
+pub fn resolve_name(from: &str, name: &str) -> Result<String, BuildError> {
+    let mut parts: Vec<&str> = Vec::new();
+    if is_relative(name) {
+        parts.extend(from.split('/').filter(|part| !part.is_empty()));
+        parts.pop();
+    }
+    for part in name.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => if parts.pop().is_none() {
+                return Err(BuildError::InvalidName { name: name.into() });
+            },
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(BuildError::InvalidName { name: name.into() });
+    }
+    Ok(parts.join("/"))
+}
+
+/// Returns true if `name` is relative to the template that uses it.
+pub fn is_relative(name: &str) -> bool {
+    name.starts_with("./") || name.starts_with("../")
+}
+
+/// Loads templates from files in a directory.
+///
+/// Files that start with bytecode header are loaded as bytecode, other files
+/// as UTF-8 source. Modification stamp is file modification time in nanoseconds.
+pub struct DirectoryLoader {
+    root: PathBuf,
+    extension: Option<String>,
+}
+
+impl DirectoryLoader {
+    pub fn new<P: Into<PathBuf>>(root: P) -> DirectoryLoader {
+        DirectoryLoader {
+            root: root.into(),
+            extension: None,
+        }
+    }
+
+    /// Append `.extension` to template names to get file names.
+    pub fn with_extension(mut self, extension: &str) -> DirectoryLoader {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    /// Path of template file.
+    ///
+    /// Fails with `BuildError::InvalidName` if a part of the name is not a plain
+    /// file name (it contains `\` or `:`, or is absolute), so the path stays inside root.
+    pub fn path(&self, name: &str) -> Result<PathBuf, BuildError> {
+        let invalid = || BuildError::InvalidName { name: name.into() };
+        let resolved = try!(resolve_name("", name));
+        let mut path = self.root.clone();
+        for part in resolved.split('/') {
+            if part.contains('\\') || part.contains(':') || !is_file_name(part) {
+                return Err(invalid());
+            }
+            path.push(part);
+        }
+        if !path.starts_with(&self.root) {
+            return Err(invalid());
+        }
+        if let Some(ref extension) = self.extension {
+            let mut file = path.file_name().unwrap().to_os_string();
+            file.push(".");
+            file.push(extension);
+            path.set_file_name(file);
+        }
+        Ok(path)
+    }
+}
+
+/// Returns true if `part` is a single normal path component.
+fn is_file_name(part: &str) -> bool {
+    let mut components = Path::new(part).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => true,
+        _ => false,
+    }
+}
+
+impl TemplateLoader for DirectoryLoader {
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError> {
+        let modified = try!(self.modified(name));
+        let bytes = try!(fs::read(try!(self.path(name))).map_err(|e| not_found(e, name)));
+        let source = if bytecode::has_header(&bytes) {
+            TemplateSource::Bytecode(bytes)
+        } else {
+            match String::from_utf8(bytes) {
+                Ok(text) => TemplateSource::Text(text),
+                Err(e) => return Err(BuildError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            }
+        };
+        Ok(LoadedTemplate {
+            source: source,
+            modified: modified,
+        })
+    }
+
+    fn modified(&self, name: &str) -> Result<u64, BuildError> {
+        let metadata = try!(fs::metadata(try!(self.path(name))).map_err(|e| not_found(e, name)));
+        if !metadata.is_file() {
+            return Err(BuildError::ExecutableNotFound { id: name.into() });
+        }
+        let since_epoch = try!(metadata.modified()).duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(since_epoch.as_secs() * 1_000_000_000 + since_epoch.subsec_nanos() as u64)
+    }
+}
+
+fn not_found(error: io::Error, name: &str) -> BuildError {
+    match error.kind() {
+        io::ErrorKind::NotFound => BuildError::ExecutableNotFound { id: name.into() },
+        _ => BuildError::Io(error),
+    }
+}
+
+/// Loads templates from memory.
+///
+/// Modification stamp changes every time a template is inserted.
+pub struct MemoryLoader {
+    templates: HashMap<String, LoadedTemplate>,
+    version: u64,
+}
+
+impl MemoryLoader {
+    pub fn new() -> MemoryLoader {
+        MemoryLoader {
+            templates: HashMap::new(),
+            version: 0,
+        }
+    }
+
+    /// Add template source, replaces existing template with the same name.
+    pub fn insert_source(&mut self, name: &str, source: &str) {
+        self.insert(name, TemplateSource::Text(source.into()));
+    }
+
+    /// Add template bytecode, replaces existing template with the same name.
+    pub fn insert_bytecode(&mut self, name: &str, bytecode: Vec<u8>) {
+        self.insert(name, TemplateSource::Bytecode(bytecode));
+    }
+
+    pub fn insert(&mut self, name: &str, source: TemplateSource) {
+        self.version += 1;
+        self.templates.insert(name.into(), LoadedTemplate {
+            source: source,
+            modified: self.version,
+        });
+    }
+
+    /// Returns false if there was no template with this name.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.templates.remove(name).is_some()
+    }
+}
+
+impl TemplateLoader for MemoryLoader {
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError> {
+        match self.templates.get(name) {
+            Some(template) => Ok(template.clone()),
+            None => Err(BuildError::ExecutableNotFound { id: name.into() }),
+        }
+    }
+
+    fn modified(&self, name: &str) -> Result<u64, BuildError> {
+        match self.templates.get(name) {
+            Some(template) => Ok(template.modified),
+            None => Err(BuildError::ExecutableNotFound { id: name.into() }),
+        }
+    }
+}
+
+/// Loads template from the first loader that has it.
+pub struct ChainLoader {
+    loaders: Vec<Box<TemplateLoader>>,
+}
+
+impl ChainLoader {
+    pub fn new() -> ChainLoader {
+        ChainLoader {
+            loaders: Vec::new(),
+        }
+    }
+
+    /// Add loader that is tried after already added ones.
+    pub fn with<L: TemplateLoader + 'static>(mut self, loader: L) -> ChainLoader {
+        self.push(loader);
+        self
+    }
+
+    pub fn push<L: TemplateLoader + 'static>(&mut self, loader: L) {
+        self.loaders.push(Box::new(loader));
+    }
+}
+
+impl TemplateLoader for ChainLoader {
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError> {
+        for loader in &self.loaders {
+            match loader.load(name) {
+                Err(BuildError::ExecutableNotFound { .. }) => continue,
+                result => return result,
+            }
+        }
+        Err(BuildError::ExecutableNotFound { id: name.into() })
+    }
+
+    fn modified(&self, name: &str) -> Result<u64, BuildError> {
+        for loader in &self.loaders {
+            match loader.modified(name) {
+                Err(BuildError::ExecutableNotFound { .. }) => continue,
+                result => return result,
+            }
+        }
+        Err(BuildError::ExecutableNotFound { id: name.into() })
+    }
+}
@@ -0,0 +1,787 @@
+//! Machine state and instruction semantics shared by backends.
+
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use buffer::OutputBuffer;
+use escape::Escaper;
+use registry::{ IncludeContext, Registry };
+use loader;
+use options;
+
+use {
+    Options,
+    OptionsTemplate,
+    Call,
+    CallErrorHandler,
+    Constant,
+    Binding,
+    BuildError,
+    Instruction,
+    Cond,
+    Escape,
+    Mem,
+    Function,
+    GetProperty,
+    LittleValue,
+    LittleError,
+    Limits,
+    MissingPolicy,
+    SourceMap,
+};
+
+/// Result of executed instruction.
+pub enum Step {
+    /// Continue with the next instruction.
+    Next,
+    /// Continue at specified instruction.
+    Jump(usize),
+    /// Enter subroutine at specified instruction, the caller should `enter_frame` with return address.
+    Call(usize),
+    /// Stop and return control to the user with interupt code.
+    Interupt(u16),
+}
+
+/// Function called by executable, borrowed from the caller of `build` or shared with it.
+pub enum CallRef<'a, V: 'a> {
+    Borrowed(&'a (Function<V> + 'a)),
+    Shared(Arc<Function<V> + 'a>),
+}
+
+impl<'a, V: 'a> Clone for CallRef<'a, V> {
+    fn clone(&self) -> CallRef<'a, V> {
+        match *self {
+            CallRef::Borrowed(function) => CallRef::Borrowed(function),
+            CallRef::Shared(ref function) => CallRef::Shared(function.clone()),
+        }
+    }
+}
+
+impl<'a, V: 'a> Deref for CallRef<'a, V> {
+    type Target = Function<V> + 'a;
+
+    fn deref(&self) -> &(Function<V> + 'a) {
+        match *self {
+            CallRef::Borrowed(function) => function,
+            CallRef::Shared(ref function) => &**function,
+        }
+    }
+}
+
+/// Functions for template calls, shared with the owner of `functions`.
+pub fn shared_calls<'a, V>(calls_template: &OptionsTemplate<Call>, functions: &HashMap<String, Arc<Function<V> + 'a>>)
+    -> Result<Options<Call, CallRef<'a, V>>, BuildError>
+{
+    let functions: HashMap<&str, CallRef<'a, V>> = functions.iter()
+        .map(|(name, function)| (name.as_str(), CallRef::Shared(function.clone())))
+        .collect();
+    match calls_template.build(&functions) {
+        Ok(calls) => Ok(calls),
+        Err(options::Error::ParameterMissing(s)) => Err(BuildError::FunctionNotFound { required: s }),
+    }
+}
+
+/// Executable data shared by all its runs.
+pub struct Runtime<'a, V: 'a> {
+    pub constants: Options<Constant, V>,
+    pub calls: Options<Call, CallRef<'a, V>>,
+    pub call_names: HashMap<Call, String>,
+    pub call_error_handler: Option<Box<CallErrorHandler<V> + 'a>>,
+    pub missing_policy: MissingPolicy<'a, V>,
+    pub limits: Limits,
+    pub source_map: Option<SourceMap>,
+    /// Escaping of `Output` instructions with `Escape::Inherit`.
+    pub escape: Escape,
+    /// Number of bindings in a frame of subroutine call.
+    pub bindings_capacity: u32,
+    /// Provides parameters that are not found in execution parameters.
+    pub globals: Option<&'a GetProperty<V>>,
+}
+
+impl<'a, V: 'a> Runtime<'a, V> {
+    pub fn new(
+        constants: Options<Constant, V>,
+        calls: Options<Call, CallRef<'a, V>>,
+        calls_template: &OptionsTemplate<Call>,
+        source_map: Option<SourceMap>,
+        bindings_capacity: u32
+    ) -> Runtime<'a, V> {
+        Runtime {
+            constants: constants,
+            calls: calls,
+            call_names: calls_template.iter().map(|(name, &call)| (call, name.clone())).collect(),
+            call_error_handler: None,
+            missing_policy: MissingPolicy::Error,
+            limits: Limits::default(),
+            source_map: source_map,
+            escape: Escape::None,
+            bindings_capacity: bindings_capacity,
+            globals: None,
+        }
+    }
+}
+
+/// Settings of executables, implemented by executables of every backend.
+pub trait Configure<'a, V: 'a> {
+    /// Executable data shared by all its runs.
+    fn runtime(&self) -> &Runtime<'a, V>;
+
+    fn runtime_mut(&mut self) -> &mut Runtime<'a, V>;
+
+    /// Install handler for errors returned by functions, it can provide a fallback value.
+    fn set_call_error_handler<H: CallErrorHandler<V> + 'a>(&mut self, handler: H) where Self: Sized {
+        self.runtime_mut().call_error_handler = Some(Box::new(handler));
+    }
+
+    /// Set what to do when property or parameter is not found, the default is `MissingPolicy::Error`.
+    fn set_missing_policy(&mut self, policy: MissingPolicy<'a, V>) {
+        self.runtime_mut().missing_policy = policy;
+    }
+
+    /// Set limits used by `execute`, the default is `Limits::default()`.
+    fn set_limits(&mut self, limits: Limits) {
+        self.runtime_mut().limits = limits;
+    }
+
+    /// Set escaping of `Output` instructions with `Escape::Inherit`, the default is `Escape::None`.
+    fn set_escape(&mut self, escape: Escape) {
+        self.runtime_mut().escape = escape;
+    }
+
+    /// Set object that provides parameters not found in execution parameters.
+    fn set_globals(&mut self, globals: &'a GetProperty<V>) {
+        self.runtime_mut().globals = Some(globals);
+    }
+
+    /// Source locations of template instructions, if template had them.
+    fn source_map<'r>(&'r self) -> Option<&'r SourceMap> where 'a: 'r {
+        self.runtime().source_map.as_ref()
+    }
+}
+
+/// Result of executing the next instruction of a stream.
+pub enum Progress {
+    /// There are no more instructions.
+    Done,
+    Continue,
+    Interupt(u16),
+}
+
+/// State of a single run, kept by streams of every backend.
+pub struct Run<'a, V: 'a> {
+    /// Index of the next instruction or op.
+    pub pc: usize,
+    /// Output that was produced but not yet read.
+    pub buf: OutputBuffer,
+    pub machine: Machine<'a, V>,
+    /// Interupt that stopped execution, cleared when execution resumes.
+    pub interupt: Option<u16>,
+}
+
+impl<'a, V: 'a> Run<'a, V> {
+    pub fn new(machine: Machine<'a, V>) -> Run<'a, V> {
+        Run {
+            pc: 0,
+            buf: OutputBuffer::new(),
+            machine: machine,
+            interupt: None,
+        }
+    }
+}
+
+/// Host access to running streams, implemented by streams of every backend.
+pub trait Host<'a, V: LittleValue + 'a> {
+    /// State of the run.
+    fn run(&self) -> &Run<'a, V>;
+
+    fn run_mut(&mut self) -> &mut Run<'a, V>;
+
+    /// Executes the next instruction, writing its output to `out`.
+    fn execute_next<W: Write>(&mut self, out: &mut W) -> Result<Progress, LittleError> where Self: Sized;
+
+    /// Returns specified number of stack items.
+    ///
+    /// If stack is smaller, returns None.
+    fn peek_stack<'r>(&'r self, slice_size: usize) -> Option<&'r [V]> where 'a: 'r {
+        let stack = &self.run().machine.stack;
+        if stack.len() < slice_size {
+            return None;
+        }
+        Some(&stack[stack.len() - slice_size ..])
+    }
+
+    /// Returns code of the interupt that stopped execution, if the stream was not read since.
+    ///
+    /// Reading the stream again resumes execution after the `Interupt` instruction.
+    fn interupted(&self) -> Option<u16> {
+        self.run().interupt
+    }
+
+    /// Push value to stack, for example a result of data fetched on interupt.
+    fn push_stack(&mut self, value: V) -> Result<(), LittleError> {
+        self.run_mut().machine.push(value)
+    }
+
+    /// Pop value from stack, returns None if stack is empty.
+    fn pop_stack(&mut self) -> Option<V> {
+        self.run_mut().machine.stack.pop()
+    }
+
+    /// Returns value of binding, unset bindings have default value.
+    fn get_binding<'r>(&'r self, binding: Binding) -> Cow<'r, V> where 'a: 'r {
+        self.run().machine.get(binding)
+    }
+
+    /// Set value of binding.
+    fn set_binding(&mut self, binding: Binding, value: V) -> Result<(), LittleError> {
+        self.run_mut().machine.set(binding, value)
+    }
+
+    /// Run to the end writing output directly to `out`, output that was not read yet is written first.
+    ///
+    /// Returns `LittleError::Interupt` on interupt, calling it again resumes execution.
+    fn write_to<W: Write>(&mut self, out: &mut W) -> Result<(), LittleError> where Self: Sized {
+        {
+            let run = self.run_mut();
+            run.interupt = None;
+            if !run.buf.is_empty() {
+                try!(out.write_all(run.buf.as_slice()));
+                run.buf.clear();
+            }
+        }
+        loop {
+            match try!(self.execute_next(out)) {
+                Progress::Done => return Ok(()),
+                Progress::Continue => (),
+                Progress::Interupt(code) => {
+                    self.run_mut().interupt = Some(code);
+                    return Err(LittleError::Interupt(code));
+                },
+            }
+        }
+    }
+
+    /// Reads output into `buf`, executing instructions until there is enough of it,
+    /// streams implement `io::Read` with it.
+    fn read_output(&mut self, buf: &mut [u8]) -> io::Result<usize> where Self: Sized {
+        self.run_mut().interupt = None;
+        let mut pending = mem::replace(&mut self.run_mut().buf, OutputBuffer::new());
+        let result = fill(self, &mut pending, buf.len());
+        self.run_mut().buf = pending;
+        try!(result);
+
+        Ok(self.run_mut().buf.read_into(buf))
+    }
+}
+
+/// Executes instructions of `stream` until there are `len` bytes of output or execution is done.
+fn fill<'a, V: LittleValue + 'a, H: Host<'a, V>>(stream: &mut H, pending: &mut OutputBuffer, len: usize) -> io::Result<()> {
+    while pending.len() < len {
+        match stream.execute_next(pending) {
+            Ok(Progress::Done) => break,
+            Ok(Progress::Continue) => (),
+            Ok(Progress::Interupt(code)) => {
+                stream.run_mut().interupt = Some(code);
+                return Err(io::Error::new(io::ErrorKind::Other, LittleError::Interupt(code)));
+            },
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Started iteration over items of `value`.
+pub struct Iteration<V> {
+    pub value: V,
+    pub index: usize,
+    pub len: usize,
+}
+
+/// Subroutine call made by `CallLocal`.
+pub struct Frame {
+    /// Where execution continues after `Return`.
+    pub return_pc: usize,
+    /// Binding base of the caller.
+    pub base: usize,
+}
+
+pub struct Machine<'a, V: 'a> {
+    pub stack: Vec<V>,
+    pub iterations: Vec<Iteration<V>>,
+    pub frames: Vec<Frame>,
+    pub values: Vec<V>,
+    /// Index of `Binding(0)` in `values`.
+    pub base: usize,
+    pub parameters: V,
+    runtime: &'a Runtime<'a, V>,
+    limits: Limits,
+    executed: u64,
+    output_bytes: u64,
+    registry: Option<&'a Registry<'a, V>>,
+    include_depth: usize,
+    /// Name of executable in registry, relative include names are resolved from it.
+    include_name: String,
+}
+
+impl<'a, V: LittleValue> Machine<'a, V> {
+    pub fn new(runtime: &'a Runtime<'a, V>, parameters: V, limits: Limits) -> Machine<'a, V> {
+        Machine {
+            stack: Vec::new(),
+            iterations: Vec::new(),
+            frames: Vec::new(),
+            values: Vec::new(),
+            base: 0,
+            parameters: parameters,
+            runtime: runtime,
+            limits: limits,
+            executed: 0,
+            output_bytes: 0,
+            registry: None,
+            include_depth: 0,
+            include_name: String::new(),
+        }
+    }
+
+    /// Continues execution described by `context`, `Include` instructions
+    /// are resolved from its registry.
+    pub fn include_from(&mut self, context: &IncludeContext<'a, V>) {
+        self.registry = Some(context.registry);
+        self.include_depth = context.depth;
+        self.include_name = context.name.clone();
+        self.limits = context.limits;
+        self.executed = context.executed;
+        self.output_bytes = context.output_bytes;
+    }
+
+    /// Starts subroutine frame, `Return` continues at `return_pc`.
+    pub fn enter_frame(&mut self, return_pc: usize) {
+        self.frames.push(Frame { return_pc: return_pc, base: self.base });
+        self.base += self.runtime.bindings_capacity as usize;
+    }
+
+    /// Number of instructions executed so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Counts executed instructions, fails if there are more than allowed.
+    pub fn count_instructions(&mut self, count: u64) -> Result<(), LittleError> {
+        self.executed += count;
+        match self.limits.max_instructions {
+            Some(max) if self.executed > max => Err(LittleError::InstructionLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of output bytes written so far.
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    /// Writer of the next output, it writes only as many bytes as the output limit allows.
+    pub fn output_writer<'w, W: Write>(&self, out: &'w mut W) -> Counter<'w, W> {
+        Counter {
+            inner: out,
+            count: 0,
+            limit: self.limits.max_output_bytes.map(|max| max.saturating_sub(self.output_bytes)),
+            exceeded: false,
+        }
+    }
+
+    /// Counts bytes written by `counter`, fails if output did not fit into the limit.
+    pub fn count_output<W>(&mut self, counter: Counter<W>, result: io::Result<()>) -> Result<(), LittleError> {
+        self.output_bytes += counter.count;
+        match self.limits.max_output_bytes {
+            Some(max) if counter.exceeded => Err(LittleError::OutputLimitExceeded(max)),
+            _ => result.map_err(From::from),
+        }
+    }
+
+    /// Executes a single instruction at `pc`, writing any output to `out`.
+    ///
+    /// Errors include source location of the instruction if it is known.
+    pub fn execute<W: Write>(&mut self, pc: usize, instruction: &Instruction, out: &mut W) -> Result<Step, LittleError> {
+        self.execute_instruction(pc, instruction, out).map_err(|e| self.locate(pc, e))
+    }
+
+    /// Adds source location of instruction at `pc` to error.
+    pub fn locate(&self, pc: usize, error: LittleError) -> LittleError {
+        match self.runtime.source_map.as_ref().and_then(|map| map.resolve(pc)) {
+            Some(location) => LittleError::Located { location: location, error: Box::new(error) },
+            None => error,
+        }
+    }
+
+    fn execute_instruction<W: Write>(&mut self, pc: usize, instruction: &Instruction, out: &mut W) -> Result<Step, LittleError> {
+        try!(self.count_instructions(1));
+        match *instruction {
+            Instruction::Output { ref location, escape } => {
+                debug!("Output (location: {:?}, escape: {:?})", location, escape);
+                let mut counter = self.output_writer(out);
+                let result = {
+                    let value = try!(self.get_mem_value(location));
+                    let escape = if value.is_safe() { Escape::None } else { escape.or(self.runtime.escape) };
+                    write!(Escaper::new(&mut counter, escape), "{}", value)
+                };
+                try!(self.count_output(counter, result));
+            },
+            Instruction::Property { ref name } => {
+                debug!("Property (name: {:?})", name);
+                let name = try!(self.get_mem_value(name)).into_owned();
+                trace!("property name {}", name);
+                let obj = match self.stack.pop() {
+                    None => return Err(LittleError::StackUnderflow),
+                    Some(v) => v,
+                };
+                let value = match obj.get_property(name.clone()) {
+                    Some(value) => value,
+                    None => try!(self.resolve_missing(&obj, &name, || LittleError::PropertyMissing { name: name.to_string(), pc: pc })),
+                };
+                self.stack.push(value);
+            },
+            Instruction::Pop { mut times } => while times > 0 {
+                debug!("Pop (times: {:?})", times);
+                if let None = self.stack.pop() {
+                    return Err(LittleError::StackUnderflow);
+                }
+                times -= 1;
+            },
+            Instruction::Push { ref location } => {
+                debug!("Push (location: {:?})", location);
+                let value = try!(self.get_mem_value(location)).into_owned();
+                try!(self.push(value));
+            },
+            Instruction::Load { binding, ref location } => {
+                debug!("Load (binding: {:?}, location: {:?})", binding, location);
+                let value = try!(self.get_mem_value(location)).into_owned();
+                try!(self.set(binding, value));
+            },
+            Instruction::Jump { pc } => {
+                debug!("Jump (pc: {:?})", pc);
+                return Ok(Step::Jump(pc as usize));
+            },
+            Instruction::CondJump { pc, ref location, test } => {
+                debug!("CondJump (pc: {:?}, location: {:?}, test: {:?})", pc, location, test);
+                let value = try!(self.get_mem_value(location));
+                let value_ref = value.as_ref();
+                let stack = match self.stack.last() {
+                    Some(value) => value,
+                    None => return Err(LittleError::StackUnderflow),
+                };
+                if compare(stack, value_ref, test) {
+                    return Ok(Step::Jump(pc as usize));
+                }
+            },
+            Instruction::Call { call, argc, push_result_to_stack } => {
+                debug!("Call (call: {:?}, argc: {:?}, push_result_to_stack: {:?})", call, argc, push_result_to_stack);
+                let fun = match self.runtime.calls.get(call) {
+                    Some(f) => f,
+                    None => return Err(LittleError::CallMissing(call)),
+                };
+
+                let stack_len = self.stack.len();
+                if stack_len < argc as usize {
+                    return Err(LittleError::StackUnderflow);
+                }
+                let value = {
+                    let args = &self.stack[stack_len - argc as usize .. stack_len];
+                    match fun.invoke(args) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            let error = LittleError::CallError {
+                                name: self.runtime.call_names.get(&call).cloned().unwrap_or_default(),
+                                pc: pc,
+                                argc: argc,
+                                error: e,
+                            };
+                            let fallback = match self.runtime.call_error_handler {
+                                Some(ref handler) => handler.handle(&error, args),
+                                None => None,
+                            };
+                            match fallback {
+                                Some(value) => value,
+                                None => return Err(error),
+                            }
+                        },
+                    }
+                };
+
+                if push_result_to_stack {
+                    try!(self.push(value));
+                }
+            },
+            Instruction::IterStart { ref location } => {
+                debug!("IterStart (location: {:?})", location);
+                let value = try!(self.get_mem_value(location)).into_owned();
+                let len = match value.iterate_len() {
+                    Some(len) => len,
+                    None => return Err(LittleError::NotIterable { pc: pc }),
+                };
+                if let Some(max) = self.limits.max_stack_depth {
+                    if self.iterations.len() >= max {
+                        return Err(LittleError::StackLimitExceeded(max));
+                    }
+                }
+                self.iterations.push(Iteration { value: value, index: 0, len: len });
+            },
+            Instruction::IterNext { binding, pc } => {
+                debug!("IterNext (binding: {:?}, pc: {:?})", binding, pc);
+                let item = match self.iterations.last_mut() {
+                    Some(iteration) if iteration.index < iteration.len => {
+                        iteration.index += 1;
+                        iteration.value.iterate_item(iteration.index - 1)
+                    },
+                    Some(_) => None,
+                    None => return Err(LittleError::IterationMissing),
+                };
+                match item {
+                    Some(item) => try!(self.set(binding, item)),
+                    None => {
+                        self.iterations.pop();
+                        return Ok(Step::Jump(pc as usize));
+                    },
+                }
+            },
+            Instruction::Operate { op, ref location } => {
+                debug!("Operate (op: {:?}, location: {:?})", op, location);
+                let value = {
+                    let other = try!(self.get_mem_value(location));
+                    let stack = match self.stack.last() {
+                        Some(value) => value,
+                        None => return Err(LittleError::StackUnderflow),
+                    };
+                    stack.operate(op, other.as_ref())
+                };
+                try!(self.replace_top(value, op.name(), pc));
+            },
+            Instruction::Negate => {
+                debug!("Negate");
+                let value = match self.stack.last() {
+                    Some(value) => value.negate(),
+                    None => return Err(LittleError::StackUnderflow),
+                };
+                try!(self.replace_top(value, "negate", pc));
+            },
+            Instruction::Not => {
+                debug!("Not");
+                let value = match self.stack.last() {
+                    Some(value) => V::from_bool(*value == V::default()),
+                    None => return Err(LittleError::StackUnderflow),
+                };
+                try!(self.replace_top(value, "not", pc));
+            },
+            Instruction::Compare { ref location, test } => {
+                debug!("Compare (location: {:?}, test: {:?})", location, test);
+                let value = {
+                    let other = try!(self.get_mem_value(location));
+                    let stack = match self.stack.last() {
+                        Some(value) => value,
+                        None => return Err(LittleError::StackUnderflow),
+                    };
+                    V::from_bool(compare(stack, other.as_ref(), test))
+                };
+                try!(self.replace_top(value, "compare", pc));
+            },
+            Instruction::Include { ref name, ref location } => {
+                debug!("Include (name: {:?}, location: {:?})", name, location);
+                let mut name = try!(self.get_mem_value(name)).to_string();
+                let data = try!(self.get_mem_value(location)).into_owned();
+                if loader::is_relative(&name) {
+                    name = match loader::resolve_name(&self.include_name, &name) {
+                        Ok(resolved) => resolved,
+                        Err(_) => return Err(LittleError::IncludeMissing { name: name, pc: pc }),
+                    };
+                }
+                let registry = self.registry;
+                let executable = match registry.and_then(|r| r.get(&name)) {
+                    Some(executable) => executable,
+                    None => return Err(LittleError::IncludeMissing { name: name, pc: pc }),
+                };
+                if let Some(max) = self.limits.max_include_depth {
+                    if self.include_depth >= max {
+                        return Err(LittleError::IncludeDepthExceeded(max));
+                    }
+                }
+                let mut context = IncludeContext {
+                    registry: registry.unwrap(),
+                    name: name.clone(),
+                    depth: self.include_depth + 1,
+                    limits: self.limits,
+                    executed: self.executed,
+                    output_bytes: self.output_bytes,
+                };
+                let result = executable.render(data, out, &mut context);
+                self.executed = context.executed;
+                self.output_bytes = context.output_bytes;
+                match result {
+                    Ok(()) => (),
+                    // Fallback of registry does not have it.
+                    Err(LittleError::BuildError(BuildError::ExecutableNotFound { ref id })) if *id == name => {
+                        return Err(LittleError::IncludeMissing { name: name, pc: pc });
+                    },
+                    Err(e) => return Err(LittleError::Included { name: name, error: Box::new(e) }),
+                }
+            },
+            Instruction::CallLocal { pc: target } => {
+                debug!("CallLocal (pc: {:?})", target);
+                if let Some(max) = self.limits.max_stack_depth {
+                    if self.frames.len() >= max {
+                        return Err(LittleError::StackLimitExceeded(max));
+                    }
+                }
+                return Ok(Step::Call(target as usize));
+            },
+            Instruction::Return => {
+                debug!("Return");
+                let frame = match self.frames.pop() {
+                    Some(frame) => frame,
+                    None => return Err(LittleError::ReturnWithoutCall { pc: pc }),
+                };
+                self.base = frame.base;
+                return Ok(Step::Jump(frame.return_pc));
+            },
+            Instruction::Interupt { code } => {
+                debug!("Interupt (code: {:?})", code);
+                return Ok(Step::Interupt(code));
+            }
+        };
+        Ok(Step::Next)
+    }
+
+    /// Replaces stack value with operation result, `None` result fails with `LittleError::InvalidOperation`.
+    fn replace_top(&mut self, value: Option<V>, operation: &'static str, pc: usize) -> Result<(), LittleError> {
+        match (value, self.stack.last_mut()) {
+            (Some(value), Some(top)) => *top = value,
+            (None, _) => return Err(LittleError::InvalidOperation { operation: operation, pc: pc }),
+            (_, None) => return Err(LittleError::StackUnderflow),
+        }
+        Ok(())
+    }
+
+    fn get_const(&self, i: Constant) -> Result<Cow<V>, LittleError> {
+        match self.runtime.constants.get(i) {
+            Some(value) => Ok(Cow::Borrowed(value)),
+            None => return Err(LittleError::ConstantMissing(i)),
+        }
+    }
+
+    pub fn get_mem_value(&self, mem: &Mem) -> Result<Cow<V>, LittleError> {
+        Ok(match *mem {
+            Mem::Binding(i) => self.get(i),
+            Mem::Parameter { name: name_constant } => {
+                let name = try!(self.get_const(name_constant));
+                let globals = self.runtime.globals.or_else(|| self.registry.and_then(|registry| registry.globals()));
+                let global = || globals.and_then(|globals| globals.get_property(name.clone().into_owned()));
+                let value = match self.parameters.get_property(name.clone().into_owned()).or_else(global) {
+                    Some(value) => value,
+                    None => try!(self.resolve_missing(&self.parameters, &name, || LittleError::ParameterMissing(name_constant))),
+                };
+                Cow::Owned(value)
+            },
+            Mem::Parameters => { Cow::Borrowed(&self.parameters) },
+            Mem::Const(i) => try!(self.get_const(i)),
+            Mem::StackTop1 => match self.stack.last() {
+                Some(value) => Cow::Borrowed(value),
+                None => return Err(LittleError::StackUnderflow),
+            },
+            Mem::StackTop2 => match self.stack.len().checked_sub(2).and_then(|i| self.stack.get(i)) {
+                Some(value) => Cow::Borrowed(value),
+                None => return Err(LittleError::StackUnderflow),
+            },
+        })
+    }
+
+    /// Finds value for missing property or parameter according to policy.
+    fn resolve_missing<F: FnOnce() -> LittleError>(&self, object: &V, name: &V, error: F) -> Result<V, LittleError> {
+        match self.runtime.missing_policy {
+            MissingPolicy::Error => Err(error()),
+            MissingPolicy::Default => Ok(V::default()),
+            MissingPolicy::Hook(ref handler) => handler.handle(object, name).ok_or_else(error),
+        }
+    }
+
+    pub fn push(&mut self, value: V) -> Result<(), LittleError> {
+        match self.limits.max_stack_depth {
+            Some(max) if self.stack.len() >= max => Err(LittleError::StackLimitExceeded(max)),
+            _ => {
+                self.stack.push(value);
+                Ok(())
+            },
+        }
+    }
+
+    pub fn set(&mut self, Binding(index): Binding, value: V) -> Result<(), LittleError> {
+        let i = self.base + index as usize;
+        try!(self.ensure_capacity_for_index(i));
+        * unsafe { self.values.get_unchecked_mut(i) } = value;
+        Ok(())
+    }
+
+    pub fn get<'r>(&'r self, Binding(index): Binding) -> Cow<'r, V> {
+        let i = self.base + index as usize;
+        if i >= self.values.len() {
+            Cow::Owned(V::default())
+        } else {
+            Cow::Borrowed(self.values.get(i).unwrap())
+        }
+    }
+
+    fn ensure_capacity_for_index(&mut self, index: usize) -> Result<(), LittleError> {
+        let required_len = index + 1;
+        if let Some(max) = self.limits.max_bindings {
+            if required_len > max {
+                return Err(LittleError::BindingLimitExceeded(max));
+            }
+        }
+        if required_len > self.values.len() {
+            self.values.resize(required_len, V::default());
+        }
+        Ok(())
+    }
+}
+
+fn compare<V: PartialOrd>(a: &V, b: &V, test: Cond) -> bool {
+    match test {
+        Cond::Eq => a == b,
+        Cond::Gt => a > b,
+        Cond::Gte => a >= b,
+        Cond::Lt => a < b,
+        Cond::Lte => a <= b,
+        Cond::Ne => a != b,
+    }
+}
+
+/// Writer that counts written bytes, and fails instead of writing more than `limit` bytes.
+pub struct Counter<'w, W: 'w> {
+    inner: &'w mut W,
+    count: u64,
+    limit: Option<u64>,
+    exceeded: bool,
+}
+
+impl<'w, W: Write> Write for Counter<'w, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let buf = match self.limit {
+            Some(limit) if self.count + buf.len() as u64 > limit => {
+                if self.count >= limit {
+                    self.exceeded = true;
+                    return Err(io::Error::new(io::ErrorKind::Other, "output limit exceeded"));
+                }
+                &buf[.. (limit - self.count) as usize]
+            },
+            _ => buf,
+        };
+        let len = try!(self.inner.write(buf));
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
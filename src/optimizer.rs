@@ -0,0 +1,441 @@
+/*!
+Template optimizer.
+
+Rewrites template instructions and constants without changing the output.
+The optimizer is a list of passes that are run in order:
+
+```
+use little::optimizer::{ Optimizer, Pass };
+
+let optimizer = Optimizer::empty()
+    .with_pass(Pass::ThreadJumps)
+    .with_pass(Pass::RemoveDeadCode);
+```
+
+Passes expect a template that passed `verifier::verify`.
+Backends run the optimizer after verification, by default only in release builds.
+*/
+
+use std::collections::HashSet;
+use std::fmt;
+
+use {
+    Constant,
+    Escape,
+    Instruction,
+    LittleValue,
+    Mem,
+    Template,
+};
+
+/// Optimization pass.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pass {
+    /// Retarget jumps that land on another `Jump` and remove jumps to the next instruction.
+    ThreadJumps,
+    /// Remove instructions that can not be reached.
+    ///
+    /// Block bodies are kept even if nothing calls them, linking can still call them
+    /// as overrides or `super` of another template.
+    RemoveDeadCode,
+    /// Remove `Push` immediately followed by `Pop`.
+    RemovePushPop,
+    /// Merge consecutive constant `Output` instructions with the same escaping into one,
+    /// using `LittleValue::concat_output`.
+    MergeOutput,
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Pass::ThreadJumps => "thread jumps",
+            Pass::RemoveDeadCode => "remove dead code",
+            Pass::RemovePushPop => "remove push pop",
+            Pass::MergeOutput => "merge output",
+        }
+    }
+
+    /// Run pass on template, returns the number of changes made.
+    pub fn run<V: LittleValue>(&self, template: &mut Template<V>) -> usize {
+        match *self {
+            Pass::ThreadJumps => thread_jumps(template),
+            Pass::RemoveDeadCode => remove_dead_code(template),
+            Pass::RemovePushPop => remove_push_pop(template),
+            Pass::MergeOutput => merge_output(template),
+        }
+    }
+}
+
+/// Changes made by a single pass.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PassReport {
+    pub pass: Pass,
+    pub changes: usize,
+    /// Instruction count after the pass.
+    pub instructions: usize,
+}
+
+/// Changes made by optimizer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Report {
+    /// Instruction count before optimization.
+    pub instructions: usize,
+    pub passes: Vec<PassReport>,
+}
+
+impl Report {
+    /// Total number of changes made by all passes.
+    pub fn changes(&self) -> usize {
+        self.passes.iter().map(|p| p.changes).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} instructions", self.instructions));
+        for pass in &self.passes {
+            try!(write!(f, ", {}: {} changes, {} instructions", pass.pass.name(), pass.changes, pass.instructions));
+        }
+        Ok(())
+    }
+}
+
+/// Configurable list of passes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Optimizer {
+    passes: Vec<Pass>,
+}
+
+impl Optimizer {
+    /// Optimizer with all passes.
+    pub fn new() -> Optimizer {
+        Optimizer {
+            passes: vec![
+                Pass::ThreadJumps,
+                Pass::RemoveDeadCode,
+                Pass::RemovePushPop,
+                Pass::MergeOutput,
+            ],
+        }
+    }
+
+    /// Optimizer without passes, it does not change templates.
+    pub fn empty() -> Optimizer {
+        Optimizer {
+            passes: Vec::new(),
+        }
+    }
+
+    /// Optimizer used by backends if not configured otherwise:
+    /// all passes in release builds, no passes in debug builds.
+    pub fn for_build() -> Optimizer {
+        if cfg!(debug_assertions) {
+            Optimizer::empty()
+        } else {
+            Optimizer::new()
+        }
+    }
+
+    pub fn with_pass(mut self, pass: Pass) -> Optimizer {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Run all passes on template.
+    pub fn optimize<V: LittleValue>(&self, template: &mut Template<V>) -> Report {
+        let mut report = Report {
+            instructions: template.instructions.len(),
+            passes: Vec::with_capacity(self.passes.len()),
+        };
+        for &pass in &self.passes {
+            let changes = pass.run(template);
+            report.passes.push(PassReport {
+                pass: pass,
+                changes: changes,
+                instructions: template.instructions.len(),
+            });
+        }
+        report
+    }
+}
+
+fn jump_targets(instructions: &[Instruction]) -> HashSet<usize> {
+    let mut targets = HashSet::new();
+    for instruction in instructions {
+        match *instruction {
+            Instruction::Jump { pc }
+            | Instruction::CondJump { pc, .. }
+            | Instruction::IterNext { pc, .. }
+            | Instruction::CallLocal { pc } => { targets.insert(pc as usize); },
+            _ => (),
+        }
+    }
+    targets
+}
+
+/// Replace instructions, where `replaced[pc]` is the new instruction for old `pc`,
+/// or `None` if it is removed.
+///
+/// Jumps and blocks that start at removed instruction continue at the next kept one.
+/// Source map keeps spans of kept instructions, `super` calls are dropped
+/// with their instructions.
+fn rewrite<V>(template: &mut Template<V>, replaced: Vec<Option<Instruction>>) {
+    if let Some(ref mut source_map) = template.source_map {
+        let spans = replaced.iter()
+            .enumerate()
+            .filter(|&(_, instruction)| instruction.is_some())
+            .map(|(pc, _)| source_map.get(pc))
+            .collect();
+        source_map.spans = spans;
+    }
+
+    let mut new_pcs = Vec::with_capacity(replaced.len() + 1);
+    let mut kept = 0;
+    for instruction in &replaced {
+        new_pcs.push(kept);
+        if instruction.is_some() {
+            kept += 1;
+        }
+    }
+
+    let remap = |pc: u16| -> u16 {
+        *new_pcs.get(pc as usize).unwrap_or(&kept) as u16
+    };
+    let is_kept = |pc: u16| replaced.get(pc as usize).map_or(false, |i| i.is_some());
+
+    for block in &mut template.blocks {
+        block.pc = remap(block.pc);
+        block.super_calls = block.super_calls.iter()
+            .cloned()
+            .filter(|&pc| is_kept(pc))
+            .map(|pc| remap(pc))
+            .collect();
+    }
+
+    template.instructions = replaced.into_iter()
+        .filter_map(|instruction| instruction)
+        .map(|instruction| match instruction {
+            Instruction::Jump { pc } => Instruction::Jump { pc: remap(pc) },
+            Instruction::CondJump { pc, location, test } => Instruction::CondJump { pc: remap(pc), location: location, test: test },
+            Instruction::IterNext { binding, pc } => Instruction::IterNext { binding: binding, pc: remap(pc) },
+            Instruction::CallLocal { pc } => Instruction::CallLocal { pc: remap(pc) },
+            other => other,
+        })
+        .collect();
+}
+
+fn thread_jumps<V>(template: &mut Template<V>) -> usize {
+    let len = template.instructions.len();
+    let mut changes = 0;
+
+    // Follows chain of jumps, stops if it loops.
+    let final_target = |instructions: &[Instruction], mut pc: u16| -> u16 {
+        let mut seen = HashSet::new();
+        while let Some(&Instruction::Jump { pc: next }) = instructions.get(pc as usize) {
+            if !seen.insert(pc) {
+                break;
+            }
+            pc = next;
+        }
+        pc
+    };
+
+    let mut replaced = Vec::with_capacity(len);
+    for pc in 0..len {
+        replaced.push(match template.instructions[pc] {
+            Instruction::Jump { pc: target } => {
+                let threaded = final_target(&template.instructions, target);
+                if threaded as usize == pc + 1 {
+                    changes += 1;
+                    None
+                } else {
+                    if threaded != target {
+                        changes += 1;
+                    }
+                    Some(Instruction::Jump { pc: threaded })
+                }
+            },
+            Instruction::CondJump { pc: target, location, test } => {
+                let threaded = final_target(&template.instructions, target);
+                if threaded != target {
+                    changes += 1;
+                }
+                Some(Instruction::CondJump { pc: threaded, location: location, test: test })
+            },
+            Instruction::IterNext { binding, pc: target } => {
+                let threaded = final_target(&template.instructions, target);
+                if threaded != target {
+                    changes += 1;
+                }
+                Some(Instruction::IterNext { binding: binding, pc: threaded })
+            },
+            Instruction::CallLocal { pc: target } => {
+                let threaded = final_target(&template.instructions, target);
+                if threaded != target {
+                    changes += 1;
+                }
+                Some(Instruction::CallLocal { pc: threaded })
+            },
+            other => Some(other),
+        });
+    }
+
+    if changes > 0 {
+        rewrite(template, replaced);
+    }
+    changes
+}
+
+fn remove_dead_code<V>(template: &mut Template<V>) -> usize {
+    let len = template.instructions.len();
+    let mut reachable = vec![false; len];
+    let mut pending: Vec<usize> = template.blocks.iter().map(|block| block.pc as usize).collect();
+    pending.push(0);
+
+    while let Some(pc) = pending.pop() {
+        if pc >= len || reachable[pc] {
+            continue;
+        }
+        reachable[pc] = true;
+        match template.instructions[pc] {
+            Instruction::Jump { pc: target } => pending.push(target as usize),
+            Instruction::Return => (),
+            Instruction::CondJump { pc: target, .. }
+            | Instruction::IterNext { pc: target, .. }
+            | Instruction::CallLocal { pc: target } => {
+                pending.push(target as usize);
+                pending.push(pc + 1);
+            },
+            _ => pending.push(pc + 1),
+        }
+    }
+
+    let changes = reachable.iter().filter(|r| !**r).count();
+    if changes > 0 {
+        let replaced = template.instructions.iter()
+            .zip(reachable.iter())
+            .map(|(instruction, &reachable)| if reachable { Some(*instruction) } else { None })
+            .collect();
+        rewrite(template, replaced);
+    }
+    changes
+}
+
+fn remove_push_pop<V>(template: &mut Template<V>) -> usize {
+    let targets = jump_targets(&template.instructions);
+    let mut replaced: Vec<Option<Instruction>> = template.instructions.iter().map(|i| Some(*i)).collect();
+    let mut changes = 0;
+
+    for pc in 1..replaced.len() {
+        // Pushing a parameter may fail, so it is not removed.
+        let removable = match replaced[pc - 1] {
+            Some(Instruction::Push { location: Mem::Parameter { .. } }) => false,
+            Some(Instruction::Push { .. }) => true,
+            _ => false,
+        };
+        if !removable || targets.contains(&pc) {
+            continue;
+        }
+        if let Some(Instruction::Pop { times }) = replaced[pc] {
+            if times == 0 {
+                continue;
+            }
+            replaced[pc - 1] = None;
+            replaced[pc] = if times > 1 { Some(Instruction::Pop { times: times - 1 }) } else { None };
+            changes += 1;
+        }
+    }
+
+    if changes > 0 {
+        rewrite(template, replaced);
+    }
+    changes
+}
+
+fn merge_output<V: LittleValue>(template: &mut Template<V>) -> usize {
+    let targets = jump_targets(&template.instructions);
+    let len = template.instructions.len();
+    let mut replaced: Vec<Option<Instruction>> = template.instructions.iter().map(|i| Some(*i)).collect();
+    let mut merged_constants = HashSet::new();
+    let mut next_constant = template.constants.iter().map(|(&Constant(c), _)| c + 1).max().unwrap_or(0);
+    let mut changes = 0;
+
+    let mut pc = 0;
+    while pc < len {
+        let (first, escape) = match template.instructions[pc] {
+            Instruction::Output { location: Mem::Const(c), escape } => (c, escape),
+            _ => {
+                pc += 1;
+                continue;
+            },
+        };
+
+        let mut merged: Option<V> = None;
+        let mut next = pc + 1;
+        while next < len && !targets.contains(&next) {
+            let c = match template.instructions[next] {
+                Instruction::Output { location: Mem::Const(c), escape: e } if e == escape => c,
+                _ => break,
+            };
+            let value = match (merged.as_ref().or(template.constants.get(first)), template.constants.get(c)) {
+                // Safe values are not escaped, merged value would be.
+                (Some(a), Some(b)) if escape != Escape::None && (a.is_safe() || b.is_safe()) => None,
+                (Some(a), Some(b)) => a.concat_output(b),
+                _ => None,
+            };
+            match value {
+                Some(value) => merged = Some(value),
+                None => break,
+            }
+            merged_constants.insert(c);
+            replaced[next] = None;
+            changes += 1;
+            next += 1;
+        }
+
+        if let Some(value) = merged {
+            merged_constants.insert(first);
+            template.constants.push(Constant(next_constant), value);
+            replaced[pc] = Some(Instruction::Output { location: Mem::Const(Constant(next_constant)), escape: escape });
+            next_constant += 1;
+        }
+        pc = next;
+    }
+
+    if changes > 0 {
+        rewrite(template, replaced);
+        remove_unused_constants(template, merged_constants);
+    }
+    changes
+}
+
+/// Removes constants from `candidates` that are not referenced by any instruction.
+fn remove_unused_constants<V>(template: &mut Template<V>, mut candidates: HashSet<Constant>) {
+    for instruction in &template.instructions {
+        let locations = match *instruction {
+            Instruction::Include { name, location } => [name, location],
+            Instruction::Output { location: a, .. }
+            | Instruction::Property { name: a }
+            | Instruction::Push { location: a }
+            | Instruction::CondJump { location: a, .. }
+            | Instruction::IterStart { location: a }
+            | Instruction::Operate { location: a, .. }
+            | Instruction::Compare { location: a, .. }
+            | Instruction::Load { location: a, .. } => [a, a],
+            _ => continue,
+        };
+        for location in &locations {
+            match *location {
+                Mem::Const(c) | Mem::Parameter { name: c } => { candidates.remove(&c); },
+                _ => (),
+            }
+        }
+    }
+    for c in candidates {
+        template.constants.remove(c);
+    }
+}
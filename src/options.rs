@@ -2,14 +2,14 @@ use std::cmp::Eq;
 use std::hash::Hash;
 use std::convert::AsRef;
 use std::ops::Index;
-use std::collections::{ HashMap };
+use std::collections::{ HashMap, hash_map };
 
 pub enum Error {
     ParameterMissing(String),
 }
 
 /// Stores a map between String name and its index `I`.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct OptionsTemplate<I> {
     key_indices: HashMap<String, I>,
 }
@@ -53,10 +53,15 @@ impl<I: Eq + Hash + Copy> OptionsTemplate<I> {
     pub fn index_of<'a>(&self, key: &'a str) -> Option<I> {
         self.key_indices.get(key).map(|i| *i)
     }
+
+    /// Iterate over names and their indices.
+    pub fn iter<'r>(&'r self) -> hash_map::Iter<'r, String, I> {
+        self.key_indices.iter()
+    }
 }
 
 /// Runtime options maped to index list.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Options<I: Eq + Hash, V> {
     map: HashMap<I, V>,
 }
@@ -81,6 +86,20 @@ impl<I: Eq + Hash, V> Options<I, V> {
     pub fn get<'a>(&'a self, index: I) -> Option<&'a V> {
         self.map.get(&index)
     }
+
+    pub fn remove(&mut self, index: I) -> Option<V> {
+        self.map.remove(&index)
+    }
+
+    /// Convert every value with `f`, keeping their indices.
+    pub fn map<W, F: FnMut(V) -> W>(self, mut f: F) -> Options<I, W> {
+        Options::new(self.map.into_iter().map(|(index, value)| (index, f(value))).collect())
+    }
+
+    /// Iterate over indices and their values.
+    pub fn iter<'r>(&'r self) -> hash_map::Iter<'r, I, V> {
+        self.map.iter()
+    }
 }
 
 impl<I: Eq + Hash, V> Index<I> for Options<I, V> {
@@ -0,0 +1,52 @@
+//! Template syntax tree.
+
+use super::Span;
+use { Cond, Operator };
+
+/// Template expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    /// Loop variable or template parameter.
+    Var(String),
+    /// String literal.
+    Str(String),
+    /// Integer literal.
+    Int(i64),
+    /// Property of expression.
+    Property(Box<Expr>, String),
+    /// Function call with arguments.
+    Call(String, Vec<Expr>),
+    /// Arithmetic operation on two expressions.
+    Operate(Operator, Box<Expr>, Box<Expr>),
+    /// Comparison of two expressions, evaluates to boolean.
+    Compare(Cond, Box<Expr>, Box<Expr>),
+    /// `-expr`
+    Negate(Box<Expr>),
+    /// `not expr`
+    Not(Box<Expr>),
+}
+
+/// Template node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// Static text.
+    Text(String, Span),
+    /// `{{ expr }}` output.
+    Output(Expr),
+    /// `{% if expr %} .. {% else %} .. {% endif %}`
+    If { cond: Expr, then: Vec<Node>, otherwise: Vec<Node> },
+    /// `{% for name in expr %} .. {% endfor %}`
+    For { name: String, iterable: Expr, body: Vec<Node>, span: Span },
+    /// `{% include expr %}` or `{% include expr with expr %}`
+    Include { name: Expr, params: Option<Expr>, span: Span },
+    /// `{% block name %} .. {% endblock %}`
+    Block { name: String, body: Vec<Node>, span: Span },
+    /// `{{ super() }}` inside of a block.
+    Super(Span),
+}
@@ -0,0 +1,393 @@
+//! Generates template instructions from syntax tree.
+
+use std::collections::{ HashMap, HashSet };
+use std::mem;
+
+use error::parse::{ ParseError, ParseErrorKind };
+use super::Span;
+use super::ast::{ Expr, ExprKind, Node };
+use {
+    Block,
+    Template,
+    Instruction,
+    Mem,
+    Cond,
+    Escape,
+    Constant,
+    Call,
+    Binding,
+    SourceMap,
+    SourceSpan,
+};
+
+/// Constant pool key, used to reuse equal constants.
+#[derive(Clone, Hash, Eq, PartialEq)]
+enum Literal {
+    Str(String),
+    Int(i64),
+    Default,
+}
+
+pub struct Codegen<V> {
+    template: Template<V>,
+    constants: HashMap<Literal, Constant>,
+    calls: HashMap<String, Call>,
+    scopes: Vec<(String, Binding)>,
+    bindings: u32,
+    scratch: Option<Binding>,
+    source_map: SourceMap,
+    file: u32,
+    /// Blocks waiting to be generated after template code: name, body,
+    /// `CallLocal` that calls the block and span.
+    pending_blocks: Vec<(String, Vec<Node>, usize, Span)>,
+    block_names: HashSet<String>,
+    /// Index of generated block in `template.blocks`.
+    current_block: Option<usize>,
+}
+
+impl<V: Default + From<String> + From<i64>> Codegen<V> {
+    pub fn new(file: &str) -> Codegen<V> {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(file);
+        Codegen {
+            template: Template::empty(),
+            constants: HashMap::new(),
+            calls: HashMap::new(),
+            scopes: Vec::new(),
+            bindings: 0,
+            scratch: None,
+            source_map: source_map,
+            file: file,
+            pending_blocks: Vec::new(),
+            block_names: HashSet::new(),
+            current_block: None,
+        }
+    }
+
+    pub fn finish(mut self) -> Template<V> {
+        self.template.bindings_capacity = self.bindings;
+        self.template.source_map = Some(self.source_map);
+        self.template
+    }
+
+    /// Generates bodies of blocks used by generated nodes.
+    ///
+    /// Blocks are subroutines after the template code, so that child templates
+    /// can replace them, see `link::link`. They do not see loop variables.
+    /// `super()` calls an empty subroutine until the template is linked.
+    pub fn blocks(&mut self) -> Result<(), ParseError> {
+        if self.pending_blocks.is_empty() {
+            return Ok(());
+        }
+
+        let span = self.pending_blocks[0].3;
+        let to_end = try!(self.emit(Instruction::Jump { pc: 0 }, span));
+
+        let mut next = 0;
+        while next < self.pending_blocks.len() {
+            let (name, body, call_pc, span) = self.pending_blocks[next].clone();
+            next += 1;
+
+            let pc = self.template.instructions.len();
+            try!(self.patch(call_pc, pc, span));
+            self.template.blocks.push(Block::new(name, pc as u16));
+
+            let scopes = mem::replace(&mut self.scopes, Vec::new());
+            let current_block = mem::replace(&mut self.current_block, Some(self.template.blocks.len() - 1));
+            let result = self.nodes(&body);
+            self.scopes = scopes;
+            self.current_block = current_block;
+            try!(result);
+
+            try!(self.emit(Instruction::Return, span));
+        }
+
+        let super_calls: Vec<u16> = self.template.blocks.iter()
+            .flat_map(|block| block.super_calls.iter().cloned())
+            .collect();
+        if !super_calls.is_empty() {
+            let empty = try!(self.emit(Instruction::Return, span));
+            for pc in super_calls {
+                try!(self.patch(pc as usize, empty, span));
+            }
+        }
+
+        let end_pc = self.template.instructions.len();
+        self.patch(to_end, end_pc, span)
+    }
+
+    pub fn nodes(&mut self, nodes: &[Node]) -> Result<(), ParseError> {
+        for node in nodes {
+            try!(self.node(node));
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), ParseError> {
+        match *node {
+            Node::Text(ref text, span) => {
+                let location = Mem::Const(self.constant(Literal::Str(text.clone())));
+                try!(self.emit(Instruction::Output { location: location, escape: Escape::None }, span));
+            },
+            Node::Output(ref expr) => match self.mem(expr) {
+                Some(location) => {
+                    try!(self.emit(Instruction::Output { location: location, escape: Escape::Inherit }, expr.span));
+                },
+                None => {
+                    try!(self.push_expr(expr));
+                    try!(self.emit(Instruction::Output { location: Mem::StackTop1, escape: Escape::Inherit }, expr.span));
+                    try!(self.emit(Instruction::Pop { times: 1 }, expr.span));
+                },
+            },
+            Node::If { ref cond, ref then, ref otherwise } => {
+                let span = cond.span;
+                let default = Mem::Const(self.constant(Literal::Default));
+
+                try!(self.push_expr(cond));
+                let to_otherwise = try!(self.emit(Instruction::CondJump { pc: 0, location: default, test: Cond::Eq }, span));
+                try!(self.emit(Instruction::Pop { times: 1 }, span));
+                try!(self.nodes(then));
+                let to_end = try!(self.emit(Instruction::Jump { pc: 0 }, span));
+
+                let otherwise_pc = self.template.instructions.len();
+                try!(self.patch(to_otherwise, otherwise_pc, span));
+                try!(self.emit(Instruction::Pop { times: 1 }, span));
+                try!(self.nodes(otherwise));
+
+                let end_pc = self.template.instructions.len();
+                try!(self.patch(to_end, end_pc, span));
+            },
+            Node::For { ref name, ref iterable, ref body, span } => {
+                let item = self.binding();
+
+                match self.mem(iterable) {
+                    Some(location) => {
+                        try!(self.emit(Instruction::IterStart { location: location }, span));
+                    },
+                    None => {
+                        try!(self.push_expr(iterable));
+                        try!(self.emit(Instruction::IterStart { location: Mem::StackTop1 }, span));
+                        try!(self.emit(Instruction::Pop { times: 1 }, span));
+                    },
+                }
+
+                let loop_pc = try!(self.emit(Instruction::IterNext { binding: item, pc: 0 }, span));
+                self.scopes.push((name.clone(), item));
+                let result = self.nodes(body);
+                self.scopes.pop();
+                try!(result);
+
+                let to_loop = try!(self.emit(Instruction::Jump { pc: 0 }, span));
+                try!(self.patch(to_loop, loop_pc, span));
+                let end_pc = self.template.instructions.len();
+                try!(self.patch(loop_pc, end_pc, span));
+            },
+            Node::Include { ref name, ref params, span } => {
+                let mut pushed = 0;
+                let mut name = match self.mem(name) {
+                    Some(location) => location,
+                    None => {
+                        try!(self.push_expr(name));
+                        pushed += 1;
+                        Mem::StackTop1
+                    },
+                };
+                let location = match *params {
+                    None => Mem::Parameters,
+                    Some(ref params) => match self.mem(params) {
+                        Some(location) => location,
+                        None => {
+                            try!(self.push_expr(params));
+                            pushed += 1;
+                            Mem::StackTop1
+                        },
+                    },
+                };
+                if pushed == 2 {
+                    name = Mem::StackTop2;
+                }
+                try!(self.emit(Instruction::Include { name: name, location: location }, span));
+                if pushed > 0 {
+                    try!(self.emit(Instruction::Pop { times: pushed }, span));
+                }
+            },
+            Node::Block { ref name, ref body, span } => {
+                if !self.block_names.insert(name.clone()) {
+                    return Err(ParseError::new(ParseErrorKind::DuplicateBlock(name.clone()), span));
+                }
+                let call_pc = try!(self.emit(Instruction::CallLocal { pc: 0 }, span));
+                self.pending_blocks.push((name.clone(), body.clone(), call_pc, span));
+            },
+            Node::Super(span) => {
+                let block = match self.current_block {
+                    Some(block) => block,
+                    None => return Err(ParseError::new(ParseErrorKind::SuperOutsideBlock, span)),
+                };
+                let pc = try!(self.emit(Instruction::CallLocal { pc: 0 }, span));
+                self.template.blocks[block].super_calls.push(pc as u16);
+            },
+        }
+        Ok(())
+    }
+
+    /// Returns memory location of expression, if it can be used without evaluation.
+    fn mem(&mut self, expr: &Expr) -> Option<Mem> {
+        Some(match expr.kind {
+            ExprKind::Var(ref name) => match self.lookup(name) {
+                Some(binding) => Mem::Binding(binding),
+                None => Mem::Parameter { name: self.constant(Literal::Str(name.clone())) },
+            },
+            ExprKind::Str(ref s) => Mem::Const(self.constant(Literal::Str(s.clone()))),
+            ExprKind::Int(i) => Mem::Const(self.constant(Literal::Int(i))),
+            ExprKind::Property(..)
+            | ExprKind::Call(..)
+            | ExprKind::Operate(..)
+            | ExprKind::Compare(..)
+            | ExprKind::Negate(..)
+            | ExprKind::Not(..) => return None,
+        })
+    }
+
+    /// Emits instructions that push the value of expression to the stack.
+    fn push_expr(&mut self, expr: &Expr) -> Result<(), ParseError> {
+        if let Some(location) = self.mem(expr) {
+            try!(self.emit(Instruction::Push { location: location }, expr.span));
+            return Ok(());
+        }
+
+        match expr.kind {
+            ExprKind::Property(ref object, ref name) => {
+                try!(self.push_expr(object));
+                let name = Mem::Const(self.constant(Literal::Str(name.clone())));
+                try!(self.emit(Instruction::Property { name: name }, expr.span));
+            },
+            ExprKind::Call(ref name, ref args) => {
+                if args.len() > u8::MAX as usize {
+                    return Err(ParseError::new(ParseErrorKind::TooManyArguments(args.len()), expr.span));
+                }
+                for arg in args {
+                    try!(self.push_expr(arg));
+                }
+                let call = self.call(name);
+                let argc = args.len() as u8;
+                try!(self.emit(Instruction::Call { call: call, argc: argc, push_result_to_stack: true }, expr.span));
+                if argc > 0 {
+                    // Arguments stay below the result, move the result over them.
+                    let scratch = self.scratch();
+                    try!(self.emit(Instruction::Load { binding: scratch, location: Mem::StackTop1 }, expr.span));
+                    try!(self.emit(Instruction::Pop { times: argc as u16 + 1 }, expr.span));
+                    try!(self.emit(Instruction::Push { location: Mem::Binding(scratch) }, expr.span));
+                }
+            },
+            ExprKind::Operate(op, ref left, ref right) => {
+                try!(self.push_expr(left));
+                let location = try!(self.operand(right));
+                try!(self.emit(Instruction::Operate { op: op, location: location }, expr.span));
+            },
+            ExprKind::Compare(test, ref left, ref right) => {
+                try!(self.push_expr(left));
+                let location = try!(self.operand(right));
+                try!(self.emit(Instruction::Compare { location: location, test: test }, expr.span));
+            },
+            ExprKind::Negate(ref operand) => {
+                try!(self.push_expr(operand));
+                try!(self.emit(Instruction::Negate, expr.span));
+            },
+            ExprKind::Not(ref operand) => {
+                try!(self.push_expr(operand));
+                try!(self.emit(Instruction::Not, expr.span));
+            },
+            _ => unreachable!("simple expressions are handled by mem"),
+        }
+
+        Ok(())
+    }
+
+    /// Returns memory location of the right operand, evaluating it if needed.
+    fn operand(&mut self, expr: &Expr) -> Result<Mem, ParseError> {
+        if let Some(location) = self.mem(expr) {
+            return Ok(location);
+        }
+        // The left operand stays on the stack, keep the right one aside.
+        let scratch = self.scratch();
+        try!(self.push_expr(expr));
+        try!(self.emit(Instruction::Load { binding: scratch, location: Mem::StackTop1 }, expr.span));
+        try!(self.emit(Instruction::Pop { times: 1 }, expr.span));
+        Ok(Mem::Binding(scratch))
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> Result<usize, ParseError> {
+        let pc = self.template.instructions.len();
+        if pc >= u16::MAX as usize {
+            return Err(ParseError::new(ParseErrorKind::TooManyInstructions, span));
+        }
+        self.template.push_instruction(instruction);
+        self.source_map.push(Some(SourceSpan::new(self.file, span.start.line, span.start.column)));
+        Ok(pc)
+    }
+
+    /// Sets jump target of instruction at `at` to `target`.
+    fn patch(&mut self, at: usize, target: usize, span: Span) -> Result<(), ParseError> {
+        if target > u16::MAX as usize {
+            return Err(ParseError::new(ParseErrorKind::TooManyInstructions, span));
+        }
+        match self.template.instructions[at] {
+            Instruction::Jump { ref mut pc } => *pc = target as u16,
+            Instruction::CondJump { ref mut pc, .. } => *pc = target as u16,
+            Instruction::IterNext { ref mut pc, .. } => *pc = target as u16,
+            Instruction::CallLocal { ref mut pc } => *pc = target as u16,
+            ref other => unreachable!("attempt to patch non-jump instruction {:?}", other),
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes.iter().rev()
+            .find(|&(n, _)| n == name)
+            .map(|&(_, binding)| binding)
+    }
+
+    fn constant(&mut self, literal: Literal) -> Constant {
+        if let Some(constant) = self.constants.get(&literal) {
+            return *constant;
+        }
+
+        let constant = Constant(self.constants.len() as u32);
+        let value = match literal {
+            Literal::Str(ref s) => V::from(s.clone()),
+            Literal::Int(i) => V::from(i),
+            Literal::Default => V::default(),
+        };
+        self.template.push_constant(constant, value);
+        self.constants.insert(literal, constant);
+        constant
+    }
+
+    fn call(&mut self, name: &str) -> Call {
+        if let Some(call) = self.calls.get(name) {
+            return *call;
+        }
+
+        let call = Call(self.calls.len() as u32);
+        self.template.calls_template.push(name, call);
+        self.calls.insert(name.into(), call);
+        call
+    }
+
+    fn binding(&mut self) -> Binding {
+        let binding = Binding(self.bindings);
+        self.bindings += 1;
+        binding
+    }
+
+    fn scratch(&mut self) -> Binding {
+        match self.scratch {
+            Some(binding) => binding,
+            None => {
+                let binding = self.binding();
+                self.scratch = Some(binding);
+                binding
+            },
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Splits template source into tokens.
+
+use std::str::Chars;
+use std::iter::Peekable;
+
+use error::parse::{ ParseError, ParseErrorKind };
+use super::{ Position, Span };
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tok {
+    /// Static text outside of tags.
+    Text(String),
+    /// `{{`
+    ExprStart,
+    /// `}}`
+    ExprEnd,
+    /// `{%`
+    TagStart,
+    /// `%}`
+    TagEnd,
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    /// `==`
+    EqEq,
+    /// `!=`
+    NotEq,
+    Lt,
+    Gt,
+    /// `<=`
+    LtEq,
+    /// `>=`
+    GtEq,
+    Eof,
+}
+
+impl Tok {
+    /// Short token description for error messages.
+    pub fn describe(&self) -> String {
+        match *self {
+            Tok::Text(_) => "text".into(),
+            Tok::ExprStart => "\"{{\"".into(),
+            Tok::ExprEnd => "\"}}\"".into(),
+            Tok::TagStart => "\"{%\"".into(),
+            Tok::TagEnd => "\"%}\"".into(),
+            Tok::Ident(ref i) => format!("{:?}", i),
+            Tok::Str(ref s) => format!("string {:?}", s),
+            Tok::Int(i) => format!("number {}", i),
+            Tok::Dot => "\".\"".into(),
+            Tok::Comma => "\",\"".into(),
+            Tok::LParen => "\"(\"".into(),
+            Tok::RParen => "\")\"".into(),
+            Tok::Plus => "\"+\"".into(),
+            Tok::Minus => "\"-\"".into(),
+            Tok::Star => "\"*\"".into(),
+            Tok::Slash => "\"/\"".into(),
+            Tok::Percent => "\"%\"".into(),
+            Tok::EqEq => "\"==\"".into(),
+            Tok::NotEq => "\"!=\"".into(),
+            Tok::Lt => "\"<\"".into(),
+            Tok::Gt => "\">\"".into(),
+            Tok::LtEq => "\"<=\"".into(),
+            Tok::GtEq => "\">=\"".into(),
+            Tok::Eof => "end of template".into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub tok: Tok,
+    pub span: Span,
+}
+
+struct Cursor<'s> {
+    chars: Peekable<Chars<'s>>,
+    pos: Position,
+}
+
+impl<'s> Cursor<'s> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        match c {
+            Some('\n') => {
+                self.pos.line += 1;
+                self.pos.column = 1;
+            },
+            Some(_) => self.pos.column += 1,
+            None => (),
+        }
+        c
+    }
+
+    /// Checks if remaining input starts with `s` without consuming it.
+    fn starts_with(&self, s: &str) -> bool {
+        self.chars.clone().take(s.len()).eq(s.chars())
+    }
+
+    fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.bump();
+        }
+    }
+}
+
+/// Converts template source into a list of tokens terminated by `Tok::Eof`.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut cursor = Cursor {
+        chars: source.chars().peekable(),
+        pos: Position::start(),
+    };
+    let mut tokens = Vec::new();
+
+    loop {
+        try!(lex_text(&mut cursor, &mut tokens));
+
+        let start = cursor.pos;
+        if cursor.starts_with("{#") {
+            cursor.skip(2);
+            loop {
+                if cursor.starts_with("#}") {
+                    cursor.skip(2);
+                    break;
+                }
+                if cursor.bump().is_none() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedEof { expected: "\"#}\"" },
+                        Span::new(start, cursor.pos)
+                    ));
+                }
+            }
+            continue;
+        }
+
+        let (open, close, close_str) = if cursor.starts_with("{{") {
+            (Tok::ExprStart, Tok::ExprEnd, "}}")
+        } else if cursor.starts_with("{%") {
+            (Tok::TagStart, Tok::TagEnd, "%}")
+        } else {
+            break;
+        };
+
+        cursor.skip(2);
+        tokens.push(Token { tok: open, span: Span::new(start, cursor.pos) });
+        try!(lex_code(&mut cursor, &mut tokens, close, close_str));
+    }
+
+    let end = cursor.pos;
+    tokens.push(Token { tok: Tok::Eof, span: Span::new(end, end) });
+
+    Ok(tokens)
+}
+
+/// Reads static text until the next tag opening.
+fn lex_text(cursor: &mut Cursor, tokens: &mut Vec<Token>) -> Result<(), ParseError> {
+    let start = cursor.pos;
+    let mut text = String::new();
+
+    while let Some(c) = cursor.peek() {
+        if c == '{' && (cursor.starts_with("{{") || cursor.starts_with("{%") || cursor.starts_with("{#")) {
+            break;
+        }
+        text.push(c);
+        cursor.bump();
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token { tok: Tok::Text(text), span: Span::new(start, cursor.pos) });
+    }
+
+    Ok(())
+}
+
+/// Reads tokens inside the tag until `close_str`.
+fn lex_code(cursor: &mut Cursor, tokens: &mut Vec<Token>, close: Tok, close_str: &'static str)
+    -> Result<(), ParseError>
+{
+    loop {
+        while let Some(c) = cursor.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            cursor.bump();
+        }
+
+        let start = cursor.pos;
+
+        if cursor.starts_with(close_str) {
+            cursor.skip(2);
+            tokens.push(Token { tok: close, span: Span::new(start, cursor.pos) });
+            return Ok(());
+        }
+
+        let c = match cursor.peek() {
+            Some(c) => c,
+            None => return Err(ParseError::new(
+                ParseErrorKind::UnexpectedEof { expected: close_str },
+                Span::new(start, start)
+            )),
+        };
+
+        let tok = match c {
+            '.' => { cursor.bump(); Tok::Dot },
+            ',' => { cursor.bump(); Tok::Comma },
+            '(' => { cursor.bump(); Tok::LParen },
+            ')' => { cursor.bump(); Tok::RParen },
+            '+' => { cursor.bump(); Tok::Plus },
+            '-' => { cursor.bump(); Tok::Minus },
+            '*' => { cursor.bump(); Tok::Star },
+            '/' => { cursor.bump(); Tok::Slash },
+            '%' => { cursor.bump(); Tok::Percent },
+            _ if cursor.starts_with("==") => { cursor.skip(2); Tok::EqEq },
+            _ if cursor.starts_with("!=") => { cursor.skip(2); Tok::NotEq },
+            _ if cursor.starts_with("<=") => { cursor.skip(2); Tok::LtEq },
+            _ if cursor.starts_with(">=") => { cursor.skip(2); Tok::GtEq },
+            '<' => { cursor.bump(); Tok::Lt },
+            '>' => { cursor.bump(); Tok::Gt },
+            '"' | '\'' => try!(lex_string(cursor, c)),
+            c if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(c) = cursor.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    digits.push(c);
+                    cursor.bump();
+                }
+                match digits.parse() {
+                    Ok(value) => Tok::Int(value),
+                    Err(_) => return Err(ParseError::new(
+                        ParseErrorKind::InvalidNumber(digits),
+                        Span::new(start, cursor.pos)
+                    )),
+                }
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(c) = cursor.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    cursor.bump();
+                }
+                Tok::Ident(ident)
+            },
+            c => {
+                cursor.bump();
+                return Err(ParseError::new(
+                    ParseErrorKind::UnexpectedChar(c),
+                    Span::new(start, cursor.pos)
+                ));
+            },
+        };
+
+        tokens.push(Token { tok: tok, span: Span::new(start, cursor.pos) });
+    }
+}
+
+/// Reads quoted string, supports `\\`, `\n`, `\t` and quote escapes.
+fn lex_string(cursor: &mut Cursor, quote: char) -> Result<Tok, ParseError> {
+    let start = cursor.pos;
+    cursor.bump();
+
+    let mut value = String::new();
+    loop {
+        match cursor.bump() {
+            None => return Err(ParseError::new(
+                ParseErrorKind::UnterminatedString,
+                Span::new(start, cursor.pos)
+            )),
+            Some(c) if c == quote => return Ok(Tok::Str(value)),
+            Some('\\') => match cursor.bump() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c) => value.push(c),
+                None => return Err(ParseError::new(
+                    ParseErrorKind::UnterminatedString,
+                    Span::new(start, cursor.pos)
+                )),
+            },
+            Some(c) => value.push(c),
+        }
+    }
+}
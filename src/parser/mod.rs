@@ -0,0 +1,123 @@
+/*!
+Template language frontend.
+
+Compiles template source into `Template`:
+
+- Static text is output as is.
+- `{{ expr }}` outputs the value of expression, escaped as configured for executable.
+- `{% if expr %} .. {% else %} .. {% endif %}` renders first block if
+  expression is not equal to `V::default()`, otherwise the `else` block.
+- `{% for item in expr %} .. {% endfor %}` renders block for every item.
+- `{% include "name" %}` renders executable from `registry::Registry` with the
+  same parameters, `{% include "name" with expr %}` with other parameters.
+- `{% block name %} .. {% endblock %}` renders a block that a child template
+  can replace when it is linked with `link::link`, `{{ super() }}` in a child
+  block renders the parent block.
+- `{# comment #}` is skipped.
+
+Expressions are template parameters (`name`), properties (`user.name`),
+string (`"text"`) and integer (`42`) literals and function calls (`join(a, b)`).
+Functions are resolved by name when the template is built.
+
+Operators are `not`, comparisons `== != < > <= >=`, arithmetic `+ - * / %`
+and unary `-`, from the lowest precedence. Values implement them with
+`OperateValue`, comparisons use `PartialOrd`.
+
+Templates carry a source map, so that runtime errors report the line and
+column of the expression that failed.
+
+Loops iterate items of values that implement `IterateValue`.
+
+Expressions and blocks can be nested up to `MAX_DEPTH` levels.
+
+## Example
+
+```
+use little::parser;
+# #[derive(Default)] struct Value;
+# impl From<String> for Value { fn from(_: String) -> Value { Value } }
+# impl From<i64> for Value { fn from(_: i64) -> Value { Value } }
+
+let template = parser::parse::<Value>("Hello, {{ user.name }}!").unwrap();
+assert_eq!(6, template.instructions.len());
+```
+*/
+
+mod lexer;
+mod ast;
+mod syntax;
+mod codegen;
+
+pub use self::syntax::MAX_DEPTH;
+
+use std::fmt;
+
+use error::parse::ParseError;
+use Template;
+
+/// Location in template source, line and column start from 1.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Position {
+    pub fn new(line: u32, column: u32) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+    /// Position of the first character.
+    pub fn start() -> Position {
+        Position::new(1, 1)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Range in template source, `end` is exclusive.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+}
+
+/// Parse template source into `Template`.
+///
+/// Literals and static text are converted to constants using `From` implementations,
+/// `V::default()` is used as "false" value for conditions.
+pub fn parse<V>(source: &str) -> Result<Template<V>, ParseError>
+    where V: Default + From<String> + From<i64>
+{
+    parse_file("", source)
+}
+
+/// Parse template source like `parse`, source map locations refer to `file`.
+pub fn parse_file<V>(file: &str, source: &str) -> Result<Template<V>, ParseError>
+    where V: Default + From<String> + From<i64>
+{
+    let tokens = try!(lexer::tokenize(source));
+    let nodes = try!(syntax::Parser::new(tokens).parse_template());
+
+    let mut codegen = codegen::Codegen::new(file);
+    try!(codegen.nodes(&nodes));
+    try!(codegen.blocks());
+
+    Ok(codegen.finish())
+}
@@ -0,0 +1,412 @@
+//! Builds syntax tree from tokens.
+
+use error::parse::{ ParseError, ParseErrorKind };
+use super::Span;
+use super::lexer::{ Tok, Token };
+use super::ast::{ Expr, ExprKind, Node };
+use { Cond, Operator };
+
+/// Maximal nesting of expressions and blocks, deeper input is rejected
+/// instead of overflowing the stack.
+pub const MAX_DEPTH: usize = 64;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Parser {
+        Parser {
+            tokens: tokens,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    /// Parses the whole template.
+    pub fn parse_template(&mut self) -> Result<Vec<Node>, ParseError> {
+        let nodes = try!(self.parse_nodes());
+        match self.peek().tok {
+            Tok::Eof => Ok(nodes),
+            _ => {
+                // Only block terminators stop `parse_nodes` before the end.
+                let (tag, span) = match self.peek_tag() {
+                    Some((tag, span)) => (tag, span),
+                    None => return Err(self.unexpected("template content")),
+                };
+                Err(ParseError::new(
+                    ParseErrorKind::UnexpectedToken { found: format!("{:?}", tag), expected: "template content" },
+                    span
+                ))
+            },
+        }
+    }
+
+    /// Parses nodes until end of template or a block terminator tag.
+    fn parse_nodes(&mut self) -> Result<Vec<Node>, ParseError> {
+        let mut nodes = Vec::new();
+
+        loop {
+            let token = self.peek().clone();
+            match token.tok {
+                Tok::Eof => return Ok(nodes),
+                Tok::Text(text) => {
+                    self.pos += 1;
+                    nodes.push(Node::Text(text, token.span));
+                },
+                Tok::ExprStart => {
+                    self.pos += 1;
+                    if let Some(span) = self.super_call() {
+                        nodes.push(Node::Super(span));
+                        continue;
+                    }
+                    let expr = try!(self.parse_expr());
+                    try!(self.expect(Tok::ExprEnd, "\"}}\""));
+                    nodes.push(Node::Output(expr));
+                },
+                Tok::TagStart => {
+                    let tag = match self.peek_tag() {
+                        Some((tag, _)) => tag,
+                        None => {
+                            self.pos += 1;
+                            return Err(self.unexpected("tag name"));
+                        },
+                    };
+                    match tag.as_ref() {
+                        "if" => nodes.push(try!(self.parse_if())),
+                        "for" => nodes.push(try!(self.parse_for())),
+                        "include" => nodes.push(try!(self.parse_include())),
+                        "block" => nodes.push(try!(self.parse_block())),
+                        "else" | "endif" | "endfor" | "endblock" => return Ok(nodes),
+                        _ => {
+                            let span = self.tokens[self.pos + 1].span;
+                            return Err(ParseError::new(ParseErrorKind::UnknownTag(tag), span));
+                        },
+                    }
+                },
+                _ => return Err(self.unexpected("template content")),
+            }
+        }
+    }
+
+    /// Parses `{% if expr %}` block, current token is `{%`.
+    fn parse_if(&mut self) -> Result<Node, ParseError> {
+        let start = self.peek().span;
+        self.pos += 2;
+        let cond = try!(self.parse_expr());
+        try!(self.expect(Tok::TagEnd, "\"%}\""));
+
+        let then = try!(self.nested(Parser::parse_nodes));
+        let mut otherwise = Vec::new();
+
+        if self.is_tag("else") {
+            self.pos += 2;
+            try!(self.expect(Tok::TagEnd, "\"%}\""));
+            otherwise = try!(self.nested(Parser::parse_nodes));
+        }
+
+        try!(self.close_block("if", "endif", start));
+
+        Ok(Node::If { cond: cond, then: then, otherwise: otherwise })
+    }
+
+    /// Parses `{% for name in expr %}` block, current token is `{%`.
+    fn parse_for(&mut self) -> Result<Node, ParseError> {
+        let start = self.peek().span;
+        self.pos += 2;
+        let name = try!(self.expect_ident("loop variable name"));
+        match self.next().tok {
+            Tok::Ident(ref kw) if kw == "in" => (),
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("\"in\""));
+            },
+        }
+        let iterable = try!(self.parse_expr());
+        try!(self.expect(Tok::TagEnd, "\"%}\""));
+
+        let body = try!(self.nested(Parser::parse_nodes));
+
+        try!(self.close_block("for", "endfor", start));
+
+        Ok(Node::For { name: name, iterable: iterable, body: body, span: start })
+    }
+
+    /// Parses `{% include expr %}` tag with optional `with expr`, current token is `{%`.
+    fn parse_include(&mut self) -> Result<Node, ParseError> {
+        let start = self.peek().span;
+        self.pos += 2;
+        let name = try!(self.parse_expr());
+        let params = match self.peek().tok {
+            Tok::Ident(ref kw) if kw == "with" => {
+                self.pos += 1;
+                Some(try!(self.parse_expr()))
+            },
+            _ => None,
+        };
+        try!(self.expect(Tok::TagEnd, "\"with\" or \"%}\""));
+
+        Ok(Node::Include { name: name, params: params, span: start })
+    }
+
+    /// Parses `{% block name %}` block, current token is `{%`.
+    fn parse_block(&mut self) -> Result<Node, ParseError> {
+        let start = self.peek().span;
+        self.pos += 2;
+        let name = try!(self.expect_ident("block name"));
+        try!(self.expect(Tok::TagEnd, "\"%}\""));
+
+        let body = try!(self.nested(Parser::parse_nodes));
+
+        try!(self.close_block("block", "endblock", start));
+
+        Ok(Node::Block { name: name, body: body, span: start })
+    }
+
+    fn close_block(&mut self, tag: &'static str, end_tag: &str, start: Span) -> Result<(), ParseError> {
+        if !self.is_tag(end_tag) {
+            return Err(match self.peek().tok {
+                Tok::Eof => ParseError::new(ParseErrorKind::UnclosedBlock { tag: tag }, start),
+                _ => {
+                    self.pos += 1;
+                    self.unexpected("block end")
+                },
+            });
+        }
+        self.pos += 2;
+        self.expect(Tok::TagEnd, "\"%}\"")
+    }
+
+    /// Skips `super() }}` and returns its span if current position is at it.
+    fn super_call(&mut self) -> Option<Span> {
+        let tok = |i: usize| self.tokens.get(self.pos + i).map(|token| &token.tok);
+        match (tok(0), tok(1), tok(2), tok(3)) {
+            (Some(&Tok::Ident(ref name)), Some(&Tok::LParen), Some(&Tok::RParen), Some(&Tok::ExprEnd)) if name == "super" => (),
+            _ => return None,
+        }
+        let span = Span::new(self.tokens[self.pos].span.start, self.tokens[self.pos + 2].span.end);
+        self.pos += 4;
+        Some(span)
+    }
+
+    /// Runs `parse` one nesting level deeper.
+    fn nested<T, F>(&mut self, parse: F) -> Result<T, ParseError>
+        where F: FnOnce(&mut Parser) -> Result<T, ParseError>
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(ParseError::new(ParseErrorKind::TooDeeplyNested, self.peek().span));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Parses expression, operators from the lowest precedence are
+    /// `not`, comparisons, `+ -`, `* / %` and unary `-`.
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Parser::parse_not)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ParseError> {
+        if let Tok::Ident(ref name) = self.peek().tok {
+            if name == "not" {
+                let start = self.next().span.start;
+                let operand = try!(self.parse_expr());
+                let span = Span::new(start, operand.span.end);
+                return Ok(Expr { kind: ExprKind::Not(Box::new(operand)), span: span });
+            }
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.parse_sum());
+        loop {
+            let test = match self.peek().tok {
+                Tok::EqEq => Cond::Eq,
+                Tok::NotEq => Cond::Ne,
+                Tok::Lt => Cond::Lt,
+                Tok::Gt => Cond::Gt,
+                Tok::LtEq => Cond::Lte,
+                Tok::GtEq => Cond::Gte,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let right = try!(self.parse_sum());
+            let span = Span::new(expr.span.start, right.span.end);
+            expr = Expr { kind: ExprKind::Compare(test, Box::new(expr), Box::new(right)), span: span };
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.parse_product());
+        loop {
+            let op = match self.peek().tok {
+                Tok::Plus => Operator::Add,
+                Tok::Minus => Operator::Sub,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let right = try!(self.parse_product());
+            let span = Span::new(expr.span.start, right.span.end);
+            expr = Expr { kind: ExprKind::Operate(op, Box::new(expr), Box::new(right)), span: span };
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.parse_unary());
+        loop {
+            let op = match self.peek().tok {
+                Tok::Star => Operator::Mul,
+                Tok::Slash => Operator::Div,
+                Tok::Percent => Operator::Rem,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let right = try!(self.parse_unary());
+            let span = Span::new(expr.span.start, right.span.end);
+            expr = Expr { kind: ExprKind::Operate(op, Box::new(expr), Box::new(right)), span: span };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if let Tok::Minus = self.peek().tok {
+            let start = self.next().span.start;
+            let operand = try!(self.nested(Parser::parse_unary));
+            let span = Span::new(start, operand.span.end);
+            let kind = match operand.kind {
+                ExprKind::Int(i) => ExprKind::Int(-i),
+                _ => ExprKind::Negate(Box::new(operand)),
+            };
+            return Ok(Expr { kind: kind, span: span });
+        }
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.parse_primary());
+
+        while let Tok::Dot = self.peek().tok {
+            self.pos += 1;
+            let name_span = self.peek().span;
+            let name = try!(self.expect_ident("property name"));
+            let span = Span::new(expr.span.start, name_span.end);
+            expr = Expr { kind: ExprKind::Property(Box::new(expr), name), span: span };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next();
+        let kind = match token.tok {
+            Tok::Str(s) => ExprKind::Str(s),
+            Tok::Int(i) => ExprKind::Int(i),
+            Tok::LParen => {
+                let expr = try!(self.parse_expr());
+                try!(self.expect(Tok::RParen, "\")\""));
+                return Ok(expr);
+            },
+            Tok::Ident(name) => {
+                if let Tok::LParen = self.peek().tok {
+                    self.pos += 1;
+                    let args = try!(self.parse_args());
+                    let span = Span::new(token.span.start, self.tokens[self.pos - 1].span.end);
+                    if name == "super" {
+                        return Err(ParseError::new(ParseErrorKind::InvalidSuperCall, span));
+                    }
+                    return Ok(Expr { kind: ExprKind::Call(name, args), span: span });
+                }
+                ExprKind::Var(name)
+            },
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("expression"));
+            },
+        };
+
+        Ok(Expr { kind: kind, span: token.span })
+    }
+
+    /// Parses call arguments after `(`, including closing `)`.
+    fn parse_args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+
+        if let Tok::RParen = self.peek().tok {
+            self.pos += 1;
+            return Ok(args);
+        }
+
+        loop {
+            args.push(try!(self.parse_expr()));
+            match self.next().tok {
+                Tok::Comma => (),
+                Tok::RParen => return Ok(args),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("\",\" or \")\""));
+                },
+            }
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Returns tag name if current position is at `{% name`.
+    fn peek_tag(&self) -> Option<(String, Span)> {
+        if let Tok::TagStart = self.peek().tok {
+            if let Some(&Token { tok: Tok::Ident(ref name), span }) = self.tokens.get(self.pos + 1) {
+                return Some((name.clone(), span));
+            }
+        }
+        None
+    }
+
+    fn is_tag(&self, name: &str) -> bool {
+        match self.peek_tag() {
+            Some((ref tag, _)) => tag == name,
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, tok: Tok, expected: &'static str) -> Result<(), ParseError> {
+        if self.peek().tok == tok {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn expect_ident(&mut self, expected: &'static str) -> Result<String, ParseError> {
+        match self.peek().tok.clone() {
+            Tok::Ident(name) => {
+                self.next();
+                Ok(name)
+            },
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        let token = self.peek();
+        let kind = match token.tok {
+            Tok::Eof => ParseErrorKind::UnexpectedEof { expected: expected },
+            ref tok => ParseErrorKind::UnexpectedToken { found: tok.describe(), expected: expected },
+        };
+        ParseError::new(kind, token.span)
+    }
+}
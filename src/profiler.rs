@@ -0,0 +1,125 @@
+/*!
+Execution profile.
+
+Interpreter stream collects a `Profile` when profiling is enabled:
+
+```ignore
+let mut stream = executable.execute(data);
+stream.enable_profiler();
+stream.read_to_string(&mut output).unwrap();
+println!("{}", stream.profile().unwrap());
+```
+
+Instructions are reported by `pc`, calls by function name.
+If the template has a source map, `Profile::by_location` sums instruction
+counters by template source location.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use { SourceLocation, SourceMap };
+
+/// Counters of a single instruction.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct InstructionProfile {
+    /// How many times instruction was executed.
+    pub executions: u64,
+    /// Bytes written by `Output` instruction.
+    pub output_bytes: u64,
+}
+
+/// Counters of a single function.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CallProfile {
+    pub calls: u64,
+    /// Time spent inside the function.
+    pub time: Duration,
+}
+
+/// Collected execution counters.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+    /// Counters indexed by `pc`.
+    pub instructions: Vec<InstructionProfile>,
+    /// Counters by function name from `calls_template`.
+    pub calls: HashMap<String, CallProfile>,
+}
+
+impl Profile {
+    /// Empty profile for template with `len` instructions.
+    pub fn new(len: usize) -> Profile {
+        Profile {
+            instructions: vec![InstructionProfile::default(); len],
+            calls: HashMap::new(),
+        }
+    }
+
+    pub fn count_instruction(&mut self, pc: usize, output_bytes: u64) {
+        if let Some(instruction) = self.instructions.get_mut(pc) {
+            instruction.executions += 1;
+            instruction.output_bytes += output_bytes;
+        }
+    }
+
+    pub fn count_call(&mut self, name: &str, time: Duration) {
+        let call = self.calls.entry(name.into()).or_insert_with(CallProfile::default);
+        call.calls += 1;
+        call.time += time;
+    }
+
+    /// Total number of executed instructions.
+    pub fn executions(&self) -> u64 {
+        self.instructions.iter().map(|i| i.executions).sum()
+    }
+
+    /// Executed instructions as `(pc, counters)`, the most executed first.
+    pub fn hottest(&self) -> Vec<(usize, InstructionProfile)> {
+        let mut executed: Vec<_> = self.instructions.iter()
+            .cloned()
+            .enumerate()
+            .filter(|&(_, i)| i.executions > 0)
+            .collect();
+        executed.sort_by(|a, b| b.1.executions.cmp(&a.1.executions).then(a.0.cmp(&b.0)));
+        executed
+    }
+
+    /// Instruction counters summed by source location, the most executed first.
+    ///
+    /// Instructions without location are skipped.
+    pub fn by_location(&self, source_map: &SourceMap) -> Vec<(SourceLocation, InstructionProfile)> {
+        let mut locations: HashMap<SourceLocation, InstructionProfile> = HashMap::new();
+        for (pc, instruction) in self.instructions.iter().enumerate() {
+            if let Some(location) = source_map.resolve(pc) {
+                let total = locations.entry(location).or_insert_with(InstructionProfile::default);
+                total.executions += instruction.executions;
+                total.output_bytes += instruction.output_bytes;
+            }
+        }
+        let mut locations: Vec<_> = locations.into_iter().collect();
+        locations.sort_by(|a, b| b.1.executions.cmp(&a.1.executions)
+            .then((&a.0.file, a.0.line, a.0.column).cmp(&(&b.0.file, b.0.line, b.0.column))));
+        locations
+    }
+
+    /// Calls as `(name, counters)`, the slowest first.
+    pub fn slowest_calls(&self) -> Vec<(&str, CallProfile)> {
+        let mut calls: Vec<_> = self.calls.iter().map(|(name, &call)| (&name[..], call)).collect();
+        calls.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        calls
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{} instructions executed", self.executions()));
+        for (pc, instruction) in self.hottest() {
+            try!(writeln!(f, "pc {}: {} executions, {} output bytes", pc, instruction.executions, instruction.output_bytes));
+        }
+        for (name, call) in self.slowest_calls() {
+            try!(writeln!(f, "call {:?}: {} calls, {:?}", name, call.calls, call.time));
+        }
+        Ok(())
+    }
+}
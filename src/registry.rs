@@ -0,0 +1,144 @@
+/*!
+Named executables that templates can include.
+
+`Include` instruction renders another executable from the registry by name,
+in place of the instruction:
+
+```ignore
+let mut registry = Registry::new();
+registry.insert("nav", nav_executable);
+registry.insert("page", page_executable);
+
+let mut out = Vec::new();
+registry.render("page", data, &mut out).unwrap();
+```
+
+Names that start with `./` or `../` are resolved relative to the name of
+the including executable with `loader::resolve_name`, so `pages/home` can
+include `../partials/nav`.
+
+Executables that are not inserted can be provided by a fallback, which
+renders executable named by `IncludeContext::name`.
+
+Included executables share limits of the rendered one, the count of executed
+instructions and output bytes continues across includes. Nesting is limited
+by `Limits::max_include_depth`.
+
+Errors inside included executable are wrapped in `LittleError::Included`,
+so that `LittleError::include_chain` lists included executable names.
+Interupts inside included executables fail the execution.
+
+Globals set with `set_globals` provide parameters that are not found in
+render parameters to executables that do not have globals of their own.
+*/
+
+use std::collections::HashMap;
+use std::io;
+
+use {
+    BuildError,
+    GetProperty,
+    Limits,
+    LittleError,
+};
+
+/// Executable that can be rendered from a registry.
+pub trait Render<V> {
+    /// Renders executable into `out` as a part of execution described by `context`.
+    ///
+    /// Execution counters of `context` are updated even if rendering fails.
+    fn render(&self, data: V, out: &mut io::Write, context: &mut IncludeContext<V>) -> Result<(), LittleError>;
+}
+
+/// State of execution that is rendering an executable from registry.
+pub struct IncludeContext<'r, V: 'r> {
+    /// Registry used to resolve `Include` instructions.
+    pub registry: &'r Registry<'r, V>,
+    /// Name of the rendered executable.
+    pub name: String,
+    /// Number of includes that lead to this executable, 0 for the rendered one.
+    pub depth: usize,
+    pub limits: Limits,
+    /// Instructions executed so far.
+    pub executed: u64,
+    /// Bytes written so far.
+    pub output_bytes: u64,
+}
+
+/// Executables by name.
+pub struct Registry<'a, V: 'a> {
+    executables: HashMap<String, Box<Render<V> + 'a>>,
+    fallback: Option<Box<Render<V> + 'a>>,
+    globals: Option<&'a GetProperty<V>>,
+    limits: Limits,
+}
+
+impl<'a, V: 'a> Registry<'a, V> {
+    pub fn new() -> Registry<'a, V> {
+        Registry {
+            executables: HashMap::new(),
+            fallback: None,
+            globals: None,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Adds executable, replaces existing one with the same name.
+    pub fn insert<R: Render<V> + 'a>(&mut self, name: &str, executable: R) {
+        self.executables.insert(name.into(), Box::new(executable));
+    }
+
+    /// Returns false if there was no executable with this name.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.executables.remove(name).is_some()
+    }
+
+    /// Returns inserted executable or fallback.
+    pub fn get(&self, name: &str) -> Option<&(Render<V> + 'a)> {
+        self.executables.get(name).or(self.fallback.as_ref()).map(|e| &**e)
+    }
+
+    /// Set executable that renders names that were not inserted.
+    ///
+    /// It should fail with `BuildError::ExecutableNotFound` if it does
+    /// not know `IncludeContext::name`.
+    pub fn set_fallback<R: Render<V> + 'a>(&mut self, fallback: R) {
+        self.fallback = Some(Box::new(fallback));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.executables.contains_key(name)
+    }
+
+    /// Set object that provides parameters not found in execution parameters,
+    /// used by executables that have no globals set.
+    pub fn set_globals(&mut self, globals: &'a GetProperty<V>) {
+        self.globals = Some(globals);
+    }
+
+    pub fn globals(&self) -> Option<&'a GetProperty<V>> {
+        self.globals
+    }
+
+    /// Set limits used by `render`, the default is `Limits::default()`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Render executable named `name` into `out`.
+    pub fn render<W: io::Write>(&self, name: &str, data: V, out: &mut W) -> Result<(), LittleError> {
+        let executable = match self.get(name) {
+            Some(executable) => executable,
+            None => return Err(BuildError::ExecutableNotFound { id: name.into() }.into()),
+        };
+        let mut context = IncludeContext {
+            registry: self,
+            name: name.into(),
+            depth: 0,
+            limits: self.limits,
+            executed: 0,
+            output_bytes: 0,
+        };
+        executable.render(data, out, &mut context)
+    }
+}
@@ -0,0 +1,152 @@
+//! SHA-1 implementation of `Sha1Hasher`.
+
+use {
+    Fingerprint,
+    Sha1Hasher,
+};
+
+const BLOCK_LEN: usize = 64;
+
+/// SHA-1 hasher.
+///
+/// `finish` does not consume the hasher, more data can be written after it.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    len: u64,
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    fn process_block(state: &mut [u32; 5], block: &[u8]) {
+        let mut w = [0u32; 80];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            w[i] = (chunk[0] as u32) << 24 | (chunk[1] as u32) << 16 | (chunk[2] as u32) << 8 | chunk[3] as u32;
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0 ..= 19 => ((b & c) | (!b & d), 0x5A827999),
+                20 ..= 39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40 ..= 59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+        state[4] = state[4].wrapping_add(e);
+    }
+}
+
+impl Sha1Hasher for Sha1 {
+    fn finish(&self) -> Fingerprint {
+        let mut hasher = self.clone();
+        let bit_len = self.len.wrapping_mul(8);
+
+        hasher.write(&[0x80]);
+        while hasher.block_len != BLOCK_LEN - 8 {
+            hasher.write(&[0]);
+        }
+        let mut len_bytes = [0u8; 8];
+        for (i, b) in len_bytes.iter_mut().enumerate() {
+            *b = (bit_len >> (56 - i * 8)) as u8;
+        }
+        hasher.write(&len_bytes);
+
+        let mut result = [0u8; 20];
+        for (i, word) in hasher.state.iter().enumerate() {
+            result[i * 4] = (word >> 24) as u8;
+            result[i * 4 + 1] = (word >> 16) as u8;
+            result[i * 4 + 2] = (word >> 8) as u8;
+            result[i * 4 + 3] = *word as u8;
+        }
+        Fingerprint::new(result)
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.len = self.len.wrapping_add(bytes.len() as u64);
+        while !bytes.is_empty() {
+            let take = ::std::cmp::min(BLOCK_LEN - self.block_len, bytes.len());
+            self.block[self.block_len .. self.block_len + take].copy_from_slice(&bytes[..take]);
+            self.block_len += take;
+            bytes = &bytes[take..];
+            if self.block_len == BLOCK_LEN {
+                Sha1::process_block(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sha1;
+    use Sha1Hasher;
+
+    fn hex(data: &[u8]) -> String {
+        let mut hasher = Sha1::new();
+        hasher.write(data);
+        hasher.finish().to_string()
+    }
+
+    #[test]
+    fn known_digests() {
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", hex(b""));
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", hex(b"abc"));
+        assert_eq!("84983e441c3bd26ebaae4aa1f95129e5e54670f1", hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"));
+        assert_eq!("34aa973cd4c4daa4f61eeb2bdbad27316534016f", hex(&vec![b'a'; 1000000]));
+    }
+
+    #[test]
+    fn finish_does_not_consume_hasher() {
+        let mut hasher = Sha1::new();
+        hasher.write(b"ab");
+        hasher.finish();
+        hasher.write(b"c");
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", hasher.finish().to_string());
+    }
+
+    #[test]
+    fn integers_are_hashed() {
+        let digest = |write: &Fn(&mut Sha1)| {
+            let mut hasher = Sha1::new();
+            write(&mut hasher);
+            hasher.finish()
+        };
+
+        assert!(digest(&|h| h.write_u16(1)) != digest(&|h| h.write_u16(2)));
+        assert!(digest(&|h| h.write_u32(1)) != digest(&|h| h.write_u32(2)));
+        assert!(digest(&|h| h.write_u64(1)) != digest(&|h| h.write_u64(2)));
+        assert!(digest(&|h| h.write_i16(-1)) != digest(&|h| h.write_i16(1)));
+        assert!(digest(&|h| h.write_i32(-1)) != digest(&|h| h.write_i32(1)));
+        assert!(digest(&|h| h.write_i64(-1)) != digest(&|h| h.write_i64(1)));
+        assert_eq!(digest(&|h| h.write(&[1, 0, 0, 0])), digest(&|h| h.write_u32(1)));
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// Source location of an instruction, `file` is an index into `SourceMap::files`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SourceSpan {
+    pub file: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SourceSpan {
+    pub fn new(file: u32, line: u32, column: u32) -> SourceSpan {
+        SourceSpan {
+            file: file,
+            line: line,
+            column: column,
+        }
+    }
+}
+
+/// Source location with resolved file name.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}:{}", self.line, self.column)
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        }
+    }
+}
+
+/// Table of source locations indexed by instruction `pc`.
+///
+/// Instructions that were not produced from source have no span.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceMap {
+    pub files: Vec<String>,
+    pub spans: Vec<Option<SourceSpan>>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap {
+            files: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Returns index of file name, adding it if it is not known yet.
+    pub fn add_file<S: Into<String>>(&mut self, name: S) -> u32 {
+        let name = name.into();
+        match self.files.iter().position(|f| *f == name) {
+            Some(index) => index as u32,
+            None => {
+                self.files.push(name);
+                (self.files.len() - 1) as u32
+            },
+        }
+    }
+
+    /// Set span of the next instruction.
+    pub fn push(&mut self, span: Option<SourceSpan>) {
+        self.spans.push(span);
+    }
+
+    pub fn get(&self, pc: usize) -> Option<SourceSpan> {
+        self.spans.get(pc).and_then(|span| *span)
+    }
+
+    /// Returns location of instruction at `pc` with file name.
+    pub fn resolve(&self, pc: usize) -> Option<SourceLocation> {
+        self.get(pc).map(|span| SourceLocation {
+            file: self.files.get(span.file as usize).cloned().unwrap_or_default(),
+            line: span.line,
+            column: span.column,
+        })
+    }
+}
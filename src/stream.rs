@@ -1,6 +1,8 @@
 //! Simple helpers to forward bytes from `Read` to `Write`.
 
+use std::fmt;
 use std::io::{ self, Read, Seek, Write, SeekFrom, ErrorKind };
+use std::str;
 
 /// Copy all bytes from `reader` to `writer` using `buf`.
 ///
@@ -60,6 +62,73 @@ pub fn seek_and_buf_copy<I, O>(loc: u64, len: u64, buf: &mut [u8], input: &mut I
     buf_copy(buf, &mut input.take(len), output)
 }
 
+/// Writes UTF-8 bytes to `fmt::Write`.
+///
+/// A character split between writes is kept until the rest of it is written.
+pub struct FmtWriter<'w, W: fmt::Write + 'w> {
+    inner: &'w mut W,
+    pending: Vec<u8>,
+}
+
+impl<'w, W: fmt::Write> FmtWriter<'w, W> {
+    pub fn new(inner: &'w mut W) -> FmtWriter<'w, W> {
+        FmtWriter {
+            inner: inner,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Fails if the last character was not written completely.
+    pub fn finish(self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(ErrorKind::InvalidData, "incomplete UTF-8 character"))
+        }
+    }
+
+    /// Writes valid part of `bytes`, returns the length of incomplete character at the end.
+    fn write_valid(inner: &mut W, bytes: &[u8]) -> io::Result<usize> {
+        let valid = match str::from_utf8(bytes) {
+            Ok(s) => s.len(),
+            Err(e) => match e.error_len() {
+                Some(_) => return Err(io::Error::new(ErrorKind::InvalidData, "invalid UTF-8")),
+                None => e.valid_up_to(),
+            },
+        };
+        let s = unsafe { str::from_utf8_unchecked(&bytes[..valid]) };
+        try!(inner.write_str(s).map_err(|_| io::Error::new(ErrorKind::Other, "formatter error")));
+        Ok(bytes.len() - valid)
+    }
+}
+
+impl<'w, W: fmt::Write> Write for FmtWriter<'w, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let incomplete = try!(FmtWriter::write_valid(self.inner, buf));
+            self.pending.extend_from_slice(&buf[buf.len() - incomplete ..]);
+        } else {
+            let pending_len = self.pending.len();
+            self.pending.extend_from_slice(buf);
+            match FmtWriter::write_valid(self.inner, &self.pending) {
+                Ok(incomplete) => {
+                    let written = self.pending.len() - incomplete;
+                    self.pending.drain(..written);
+                },
+                Err(e) => {
+                    self.pending.truncate(pending_len);
+                    return Err(e);
+                },
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::io::{ Cursor };
@@ -84,6 +153,27 @@ mod test {
             String::from_utf8_lossy(&output[..])
         );
     }
+
+    #[test]
+    fn test_fmt_writer_joins_split_characters() {
+        let mut output = String::new();
+        {
+            let mut writer = FmtWriter::new(&mut output);
+            let bytes = "aé€".as_bytes();
+            writer.write_all(&bytes[..2]).unwrap();
+            writer.write_all(&bytes[2..4]).unwrap();
+            writer.write_all(&bytes[4..]).unwrap();
+            writer.finish().unwrap();
+        }
+        assert_eq!("aé€", output);
+    }
+
+    #[test]
+    fn test_fmt_writer_rejects_invalid_utf8() {
+        let mut output = String::new();
+        let mut writer = FmtWriter::new(&mut output);
+        assert!(writer.write_all(&[b'a', 0xff]).is_err());
+    }
 }
 
 #[cfg(bench)]
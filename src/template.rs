@@ -1,18 +1,51 @@
+use bytecode::Serializer;
+use sha1::Sha1;
 use {
     Constant,
     Call,
+    Fingerprint,
+    IdentifyValue,
     Instruction,
     Options,
     OptionsTemplate,
+    Sha1Hasher,
+    SourceMap,
 };
 
+/// Named subroutine that a child template can replace, see `link::link`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub name: String,
+    /// First instruction of the block, it is called with `CallLocal`.
+    pub pc: u16,
+    /// `CallLocal` instructions that render the parent version of this block (`super`).
+    ///
+    /// They call an empty subroutine until the template is linked to a parent
+    /// that has a block with the same name.
+    pub super_calls: Vec<u16>,
+}
+
+impl Block {
+    pub fn new<S: Into<String>>(name: S, pc: u16) -> Block {
+        Block {
+            name: name.into(),
+            pc: pc,
+            super_calls: Vec::new(),
+        }
+    }
+}
+
 /// All the data required to load the processor.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Template<V> {
     pub constants: Options<Constant, V>,
     pub calls_template: OptionsTemplate<Call>,
     pub instructions: Vec<Instruction>,
     pub bindings_capacity: u32,
+    /// Source locations of instructions, if known.
+    pub source_map: Option<SourceMap>,
+    /// Overridable blocks, in order of definition.
+    pub blocks: Vec<Block>,
 }
 
 impl<V> Template<V> {
@@ -27,6 +60,8 @@ impl<V> Template<V> {
             calls_template: calls_template,
             instructions: instructions,
             bindings_capacity: bindings_capacity,
+            source_map: None,
+            blocks: Vec::new(),
         }
     }
 
@@ -36,6 +71,8 @@ impl<V> Template<V> {
             calls_template: OptionsTemplate::empty(),
             instructions: vec![],
             bindings_capacity: 0,
+            source_map: None,
+            blocks: Vec::new(),
         }
     }
 
@@ -58,8 +95,91 @@ impl<V> Template<V> {
         self.instructions.push(instruction);
     }
 
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    pub fn with_block(mut self, block: Block) -> Self {
+        self.blocks.push(block);
+        self
+    }
+
+    /// Returns block by name.
+    pub fn block(&self, name: &str) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.name == name)
+    }
+
     pub fn with_instructions<I: IntoIterator<Item=Instruction>>(mut self, instructions: I) -> Self {
         self.instructions.extend(instructions.into_iter());
         self
     }
 }
+
+impl<V: IdentifyValue> Template<V> {
+    /// SHA-1 of instructions, constants, call names and blocks.
+    ///
+    /// Source map is not included, it does not change the output.
+    ///
+    /// Returns `None` if some constant value can not be hashed.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        let mut hasher = Sha1::new();
+        hasher.write_u32(self.bindings_capacity);
+
+        hash_calls(&mut hasher, &self.calls_template);
+
+        let mut constants: Vec<_> = self.constants.iter().collect();
+        constants.sort_by_key(|&(&Constant(constant), _)| constant);
+        hasher.write_u64(constants.len() as u64);
+        for (&Constant(constant), value) in constants {
+            hasher.write_u32(constant);
+            if value.hash_value(&mut hasher).is_err() {
+                return None;
+            }
+        }
+
+        let mut instructions = Vec::new();
+        for instruction in &self.instructions {
+            instruction.serialize(&mut instructions).unwrap();
+        }
+        hasher.write_u64(instructions.len() as u64);
+        hasher.write(&instructions);
+
+        hasher.write_u64(self.blocks.len() as u64);
+        for block in &self.blocks {
+            hasher.write_u64(block.name.len() as u64);
+            hasher.write(block.name.as_bytes());
+            hasher.write_u32(block.pc as u32);
+            hasher.write_u64(block.super_calls.len() as u64);
+            for &pc in &block.super_calls {
+                hasher.write_u32(pc as u32);
+            }
+        }
+
+        Some(hasher.finish())
+    }
+}
+
+impl<V> Template<V> {
+    /// SHA-1 of functions this template binds to, by name and call index.
+    ///
+    /// Executables report it as their `identify_env`.
+    pub fn env_fingerprint(&self) -> Fingerprint {
+        let mut hasher = Sha1::new();
+        hash_calls(&mut hasher, &self.calls_template);
+        hasher.finish()
+    }
+}
+
+fn hash_calls<H: Sha1Hasher>(hasher: &mut H, calls_template: &OptionsTemplate<Call>) {
+    let mut calls: Vec<_> = calls_template.iter()
+        .map(|(name, &Call(call))| (call, name))
+        .collect();
+    calls.sort();
+    hasher.write_u64(calls.len() as u64);
+    for (call, name) in calls {
+        hasher.write_u32(call);
+        hasher.write_u64(name.len() as u64);
+        hasher.write(name.as_bytes());
+    }
+}
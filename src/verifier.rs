@@ -0,0 +1,219 @@
+/*!
+Static instruction stream verifier.
+
+Runs before a template is turned into an executable, so that malformed
+instructions are reported at build time instead of failing during execution.
+
+Every reachable instruction is visited once with the stack depth and the number
+of started iterations it is reached with. Paths that meet at the same instruction
+must agree on both.
+
+Subroutines are visited separately from the main code, starting with empty stack
+and no iterations. They may not use values of the caller, and must `Return` with
+the same empty state.
+*/
+
+use std::collections::HashSet;
+
+use {
+    Binding,
+    Call,
+    Instruction,
+    Limits,
+    Mem,
+    Template,
+    VerifyError,
+    VerifyErrorKind,
+};
+
+/// Check template instructions.
+///
+/// Jump targets must be inside instructions or point right past the last one,
+/// stack must have enough items for every instruction, all referenced
+/// constants and calls must exist, and subroutines must not run past the last
+/// instruction.
+///
+/// If template has subroutines, bindings of a subroutine must be below
+/// `bindings_capacity`, and bindings of the main code below `2 * bindings_capacity`,
+/// so it can also pass arguments to the subroutine it calls.
+pub fn verify<V>(template: &Template<V>) -> Result<(), VerifyError> {
+    verify_with_limits(template, &Limits::unlimited())
+}
+
+/// Check template instructions like `verify`, bindings must also be below `limits.max_bindings`.
+pub fn verify_with_limits<V>(template: &Template<V>, limits: &Limits) -> Result<(), VerifyError> {
+    let instructions = &template.instructions;
+    let calls: HashSet<Call> = template.calls_template.iter().map(|(_, &call)| call).collect();
+    let has_subroutines = instructions.iter().any(|i| match *i {
+        Instruction::CallLocal { .. } => true,
+        _ => false,
+    });
+
+    // Depths of the main code and of subroutines.
+    let mut depths: [Vec<Option<(usize, usize)>>; 2] = [vec![None; instructions.len()], vec![None; instructions.len()]];
+    let mut pending = vec![(0, 0, 0, false, 0)];
+
+    while let Some((pc, depth, iterations, subroutine, from)) = pending.pop() {
+        if pc >= instructions.len() {
+            if subroutine {
+                return Err(VerifyError::new(VerifyErrorKind::MissingReturn, from));
+            }
+            continue;
+        }
+        match depths[subroutine as usize][pc] {
+            Some((expected, _)) if expected != depth => return Err(VerifyError::new(VerifyErrorKind::StackMismatch { expected: expected, found: depth }, pc)),
+            Some((_, expected)) if expected != iterations => return Err(VerifyError::new(VerifyErrorKind::IterationMismatch { expected: expected, found: iterations }, pc)),
+            Some(_) => continue,
+            None => depths[subroutine as usize][pc] = Some((depth, iterations)),
+        }
+
+        let require = |required: usize| if depth < required {
+            Err(VerifyError::new(VerifyErrorKind::StackUnderflow { required: required, depth: depth }, pc))
+        } else {
+            Ok(())
+        };
+        let capacity = match (has_subroutines, subroutine) {
+            (false, _) => None,
+            (true, false) => Some(template.bindings_capacity.saturating_mul(2)),
+            (true, true) => Some(template.bindings_capacity),
+        };
+        let check = Bindings { capacity: capacity, limits: limits };
+        let binding = |binding: Binding| check.verify(binding, pc);
+        let target = |target: u16| if target as usize > instructions.len() {
+            Err(VerifyError::new(VerifyErrorKind::JumpOutOfBounds { target: target as usize }, pc))
+        } else {
+            Ok(target as usize)
+        };
+
+        match instructions[pc] {
+            Instruction::Output { ref location, .. } => {
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Property { ref name } => {
+                try!(require(1));
+                try!(verify_mem(template, &check, name, depth, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Push { ref location } => {
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth + 1, iterations, subroutine, pc));
+            },
+            Instruction::Pop { times } => {
+                try!(require(times as usize));
+                pending.push((pc + 1, depth - times as usize, iterations, subroutine, pc));
+            },
+            Instruction::Jump { pc: jump } => {
+                pending.push((try!(target(jump)), depth, iterations, subroutine, pc));
+            },
+            Instruction::CondJump { pc: jump, ref location, .. } => {
+                try!(require(1));
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((try!(target(jump)), depth, iterations, subroutine, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Call { call, argc, push_result_to_stack } => {
+                if !calls.contains(&call) {
+                    return Err(VerifyError::new(VerifyErrorKind::CallMissing(call), pc));
+                }
+                try!(require(argc as usize));
+                pending.push((pc + 1, if push_result_to_stack { depth + 1 } else { depth }, iterations, subroutine, pc));
+            },
+            Instruction::Load { binding: b, ref location } => {
+                try!(binding(b));
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Interupt { .. } => {
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Operate { ref location, .. } | Instruction::Compare { ref location, .. } => {
+                try!(require(1));
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Include { ref name, ref location } => {
+                try!(verify_mem(template, &check, name, depth, pc));
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::CallLocal { pc: call } => {
+                pending.push((try!(target(call)), 0, 0, true, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::Return => {
+                if !subroutine {
+                    return Err(VerifyError::new(VerifyErrorKind::ReturnWithoutCall, pc));
+                }
+                if depth != 0 {
+                    return Err(VerifyError::new(VerifyErrorKind::StackMismatch { expected: 0, found: depth }, pc));
+                }
+                if iterations != 0 {
+                    return Err(VerifyError::new(VerifyErrorKind::IterationMismatch { expected: 0, found: iterations }, pc));
+                }
+            },
+            Instruction::Negate | Instruction::Not => {
+                try!(require(1));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+            Instruction::IterStart { ref location } => {
+                try!(verify_mem(template, &check, location, depth, pc));
+                pending.push((pc + 1, depth, iterations + 1, subroutine, pc));
+            },
+            Instruction::IterNext { binding: b, pc: jump } => {
+                try!(binding(b));
+                if iterations == 0 {
+                    return Err(VerifyError::new(VerifyErrorKind::IterationMissing, pc));
+                }
+                pending.push((try!(target(jump)), depth, iterations - 1, subroutine, pc));
+                pending.push((pc + 1, depth, iterations, subroutine, pc));
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_mem<V>(template: &Template<V>, bindings: &Bindings, mem: &Mem, depth: usize, pc: usize) -> Result<(), VerifyError> {
+    let (constant, required) = match *mem {
+        Mem::Const(c) | Mem::Parameter { name: c } => (Some(c), 0),
+        Mem::StackTop1 => (None, 1),
+        Mem::StackTop2 => (None, 2),
+        Mem::Binding(b) => {
+            try!(bindings.verify(b, pc));
+            (None, 0)
+        },
+        Mem::Parameters => (None, 0),
+    };
+    if let Some(c) = constant {
+        if template.constants.get(c).is_none() {
+            return Err(VerifyError::new(VerifyErrorKind::ConstantMissing(c), pc));
+        }
+    }
+    if depth < required {
+        return Err(VerifyError::new(VerifyErrorKind::StackUnderflow { required: required, depth: depth }, pc));
+    }
+    Ok(())
+}
+
+/// Binding bounds of the current frame.
+struct Bindings<'a> {
+    capacity: Option<u32>,
+    limits: &'a Limits,
+}
+
+impl<'a> Bindings<'a> {
+    fn verify(&self, binding: Binding, pc: usize) -> Result<(), VerifyError> {
+        let Binding(index) = binding;
+        match self.capacity {
+            Some(capacity) if index >= capacity => {
+                return Err(VerifyError::new(VerifyErrorKind::BindingOutOfBounds { binding: binding, capacity: capacity }, pc));
+            },
+            _ => (),
+        }
+        match self.limits.max_bindings {
+            Some(max) if index as usize >= max => Err(VerifyError::new(VerifyErrorKind::BindingLimitExceeded { binding: binding, max: max }, pc)),
+            _ => Ok(()),
+        }
+    }
+}
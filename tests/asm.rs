@@ -0,0 +1,179 @@
+extern crate little;
+
+mod mock;
+
+use little::*;
+use little::asm::{ assemble, disassemble };
+
+use mock::Value;
+
+#[test]
+fn disassembles_template() {
+    let template = Template::empty()
+        .with_call("add", Call(0))
+        .with_constant(Constant(0), Value::Int(1))
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::CondJump { pc: 4, location: Mem::Const(Constant(0)), test: Cond::Gte },
+            Instruction::Call { call: Call(0), argc: 1, push_result_to_stack: false },
+            Instruction::Jump { pc: 1 },
+            Instruction::Load { binding: Binding(2), location: Mem::StackTop1 },
+        ]);
+
+    assert_eq!(
+        "\
+.bindings 0
+
+.call 0 \"add\"
+
+.const 0 0x010100000000000000 ; \"1\"
+
+    push params
+@l1:
+    cjump.gte @l4, const 0
+    call.void 0, 1
+    jump @l1
+@l4:
+    load binding 2, top1
+",
+        disassemble(&template).unwrap()
+    );
+}
+
+#[test]
+fn round_trips_parsed_template() {
+    let template = parser::parse::<Value>(
+        "{% for item in items %}{% if item %}{{ item.name }}{% else %}-{% endif %}{% endfor %}"
+    ).unwrap();
+    let text = disassemble(&template).unwrap();
+
+    let restored = assemble::<Value>(&text).unwrap();
+    assert_eq!(format!("{:?}", template.instructions), format!("{:?}", restored.instructions));
+    assert_eq!(template.bindings_capacity, restored.bindings_capacity);
+    assert_eq!(text, disassemble(&restored).unwrap());
+}
+
+#[test]
+fn round_trips_blocks() {
+    let template = parser::parse::<Value>(
+        "{% block title %}{{ super() }}{% endblock %}{% block content %}{% endblock %}"
+    ).unwrap();
+    let text = disassemble(&template).unwrap();
+    assert!(text.contains(".block 3 \"title\"\n.super 3 \"title\"\n.block 5 \"content\"\n"), "{}", text);
+
+    let restored = assemble::<Value>(&text).unwrap();
+    assert_eq!(template.blocks, restored.blocks);
+    assert_eq!(text, disassemble(&restored).unwrap());
+}
+
+#[test]
+fn round_trips_source_map() {
+    let template = parser::parse_file::<Value>(
+        "pages/home.html",
+        "Hello,\n{% if user %}{{ user.name }}{% endif %}!"
+    ).unwrap();
+    assert!(template.source_map.is_some());
+    let text = disassemble(&template).unwrap();
+    assert!(text.contains(".file 0 \"pages/home.html\"\n.span 0 0:1:1\n"), "{}", text);
+
+    let restored = assemble::<Value>(&text).unwrap();
+    assert_eq!(template.source_map, restored.source_map);
+    assert_eq!(text, disassemble(&restored).unwrap());
+}
+
+#[test]
+fn round_trips_every_instruction() {
+    let text = "\
+.bindings 3
+
+.call 0 \"a \\\"quoted\\\"; name\"
+.call 1 \"b\"
+
+.const 0 0x00 ; \"\"
+.const 3 0x020200000000000000683b ; \"h;\"
+
+@l0:
+    output const 3
+    property param 0
+    push binding 1
+    pop 2
+    jump 300
+    cjump.eq @l0, params
+    cjump.ne @l0, top1
+    cjump.gt @l0, top2
+    cjump.lt @l0, const 0
+    cjump.gte @l0, const 0
+    cjump.lte @l24, const 0
+    call 1, 2
+    call.void 0, 0
+    load binding 2, param 3
+    interupt 3
+    iter.start param 0
+    iter.next @l24, binding 1
+    op.rem const 0
+    neg
+    not
+    cmp.gte top2
+    include const 0, params
+    call.local @l0
+    return
+@l24:
+";
+    let template = assemble::<Value>(text).unwrap();
+    assert_eq!(Some(Call(0)), template.calls_template.index_of("a \"quoted\"; name"));
+    assert_eq!(Some(&Value::Str("h;".into())), template.constants.get(Constant(3)));
+    assert_eq!(text, disassemble(&template).unwrap());
+}
+
+#[test]
+fn assembles_with_comments_and_named_labels() {
+    let template = assemble::<Value>("
+        ; count down
+        @loop:
+            cjump.lte @end, const 0 ; done?
+            jump @loop
+        @end:
+    ").unwrap();
+
+    match template.instructions[0] {
+        Instruction::CondJump { pc: 2, .. } => (),
+        ref other => panic!("unexpected instruction {:?}", other),
+    }
+    match template.instructions[1] {
+        Instruction::Jump { pc: 0 } => (),
+        ref other => panic!("unexpected instruction {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_label_is_undefined() {
+    let err = assemble::<Value>("push params\njump @nowhere").err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::UndefinedLabel("nowhere".into()), 2), err);
+}
+
+#[test]
+fn error_if_span_is_past_last_instruction() {
+    let err = assemble::<Value>(".spans 1\n.span 4294967295 0:1:1\npush params").err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::SpanOutOfBounds { pc: 4294967295, len: 1 }, 2), err);
+
+    let err = assemble::<Value>(&format!(".spans {}\npush params", usize::MAX)).err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::SpanOutOfBounds { pc: usize::MAX - 1, len: 1 }, 1), err);
+}
+
+#[test]
+fn error_if_instruction_is_unknown() {
+    let err = assemble::<Value>("\n\n  cjump.maybe @a, params").err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::UnknownInstruction("cjump.maybe".into()), 3), err);
+}
+
+#[test]
+fn error_if_operand_is_invalid() {
+    let err = assemble::<Value>("load const 1, params").err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::InvalidOperand("const 1".into()), 1), err);
+}
+
+#[test]
+fn error_if_constant_bytes_are_invalid() {
+    let err = assemble::<Value>(".const 0 0x0101").err().unwrap();
+    assert_eq!(AsmError::new(AsmErrorKind::InvalidValue, 1), err);
+}
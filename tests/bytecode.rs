@@ -0,0 +1,119 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{ Cursor, Read };
+
+use little::*;
+use little::bytecode::{ self, Bytecode, Serializer };
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+#[test]
+fn template_round_trip() {
+    let template = mock::hello_template();
+
+    let mut data = Vec::new();
+    let len = template.serialize(&mut data).unwrap();
+    assert_eq!(data.len() as u64, len);
+
+    let (read_len, restored) = Template::<Value>::deserialize(&mut Cursor::new(&data[..])).unwrap();
+    assert_eq!(len, read_len);
+    assert_eq!(format!("{:?}", template.instructions), format!("{:?}", restored.instructions));
+    assert_eq!(template.bindings_capacity, restored.bindings_capacity);
+    assert_eq!(Some(Call(0)), restored.calls_template.index_of("upper"));
+    assert_eq!(Some(&Value::Str("Hello, ".into())), restored.constants.get(Constant(0)));
+    assert_eq!(Some(&Value::Int(42)), restored.constants.get(Constant(1)));
+}
+
+#[test]
+fn template_blocks_round_trip() {
+    let mut block = Block::new("title", 2);
+    block.super_calls.push(3);
+    let template = mock::hello_template().with_block(block);
+
+    let mut data = Vec::new();
+    let len = template.serialize(&mut data).unwrap();
+    assert_eq!(data.len() as u64, len);
+
+    let (read_len, restored) = Template::<Value>::deserialize(&mut Cursor::new(&data[..])).unwrap();
+    assert_eq!(len, read_len);
+    assert_eq!(template.blocks, restored.blocks);
+}
+
+#[test]
+fn template_serialization_is_deterministic() {
+    let mut first = Vec::new();
+    mock::hello_template().serialize(&mut first).unwrap();
+    let mut second = Vec::new();
+    mock::hello_template().serialize(&mut second).unwrap();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn executes_deserialized_template() {
+    let mut data = Vec::new();
+    mock::hello_template().write_bytecode(&mut data).unwrap();
+    let template = Template::<Value>::read_bytecode(&mut Cursor::new(&data[..])).unwrap();
+
+    let mut funs = HashMap::new();
+    funs.insert("upper", &mock::upper as &Function<Value>);
+
+    let mut i = Interpreter::new();
+    let p = i.build("hello", template, &funs).unwrap();
+    assert_eq!("Hello, WORLD42", render(p.execute(Value::Str("world".into()))));
+}
+
+#[test]
+fn writes_and_reads_little_file() {
+    let path = env::temp_dir().join(format!("little-bytecode-test-{}.little", std::process::id()));
+
+    bytecode::write_file(&path, &mock::hello_template()).unwrap();
+    let restored: Result<Template<Value>, _> = bytecode::read_file(&path);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(5, restored.unwrap().instructions.len());
+}
+
+#[test]
+fn error_if_bytecode_is_not_magical() {
+    let data = vec![0u8; 64];
+    match Template::<Value>::read_bytecode(&mut Cursor::new(&data[..])) {
+        Err(bytecode::Error::InvalidBinaryFormat) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_bytecode_version_differs() {
+    let mut data = Vec::new();
+    mock::hello_template().write_bytecode(&mut data).unwrap();
+    data[4] = data[4].wrapping_add(1);
+    match Template::<Value>::read_bytecode(&mut Cursor::new(&data[..])) {
+        Err(bytecode::Error::UnsupportedVersion { found, expected: bytecode::FORMAT_VERSION }) => {
+            assert_eq!(bytecode::FORMAT_VERSION + 1, found);
+        },
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_bytecode_was_written_before_versioning() {
+    let mut data = vec![0xbf, 0xfb, 0x1c, 0x03];
+    mock::hello_template().serialize(&mut data).unwrap();
+    assert!(bytecode::has_header(&data));
+    match Template::<Value>::read_bytecode(&mut Cursor::new(&data[..])) {
+        Err(bytecode::Error::UnsupportedVersion { found: 0, .. }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+fn render<R: Read>(mut stream: R) -> String {
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    res
+}
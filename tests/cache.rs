@@ -0,0 +1,167 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+
+use little::*;
+use little::cache::{ MemoryStore, DirectoryStore, Store };
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+#[test]
+fn interpreter_loads_from_memory_store() {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    let calls = vec![&mock::add as &Function<Value>];
+
+    let mut i = Interpreter::with_store(MemoryStore::new());
+    let env = {
+        let p = i.build("sum", mock::sum_template(), &funs).unwrap();
+        assert_eq!("Sum: 5", render(p.execute(Value::Int(3))));
+        p.identify_env()
+    };
+
+    let p = i.load("sum", env, &calls).unwrap();
+    assert_eq!("sum", p.get_id());
+    assert_eq!("Sum: 7", render(p.execute(Value::Int(5))));
+}
+
+#[test]
+fn compiler_loads_from_memory_store() {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    let calls = vec![&mock::add as &Function<Value>];
+
+    let mut c = Compiler::with_store(MemoryStore::new());
+    let env = c.build("sum", mock::sum_template(), &funs).unwrap().identify_env();
+
+    let p = c.load("sum", env, &calls).unwrap();
+    assert_eq!("Sum: 4", render(p.execute(Value::Int(2))));
+}
+
+#[test]
+fn loads_from_directory_store() {
+    let path = env::temp_dir().join(format!("little-cache-test-{}", std::process::id()));
+
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    let calls = vec![&mock::add as &Function<Value>];
+
+    let env = {
+        let mut i = Interpreter::with_store(DirectoryStore::new(&path));
+        let p = i.build("pages/sum", mock::sum_template(), &funs).unwrap();
+        p.identify_env()
+    };
+
+    assert!(DirectoryStore::new(&path).file_path("pages/sum").exists());
+
+    let result = {
+        let mut i = Interpreter::with_store(DirectoryStore::new(&path));
+        let p = i.load("pages/sum", env, &calls).unwrap();
+        render(p.execute(Value::Int(1)))
+    };
+
+    fs::remove_dir_all(&path).unwrap();
+
+    assert_eq!("Sum: 3", result);
+}
+
+#[test]
+fn directory_store_replaces_data_without_leaving_temporary_files() {
+    let path = env::temp_dir().join(format!("little-cache-replace-test-{}", std::process::id()));
+
+    let mut store = DirectoryStore::new(&path);
+    store.put("sum", vec![1, 2, 3]).unwrap();
+    store.put("sum", vec![4, 5]).unwrap();
+    let data = store.get("sum").unwrap();
+    let files: Vec<_> = fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()).collect();
+
+    fs::remove_dir_all(&path).unwrap();
+
+    assert_eq!(Some(vec![4, 5]), data);
+    assert_eq!(vec![store.file_path("sum")], files);
+}
+
+#[test]
+fn error_if_id_is_unknown() {
+    let calls = vec![];
+    let mut i = Interpreter::with_store(MemoryStore::new());
+    let err = Build::<Value>::load(&mut i, "missing", Fingerprint::empty(), &calls).err().unwrap();
+    match err.downcast_ref::<BuildError>() {
+        Some(&BuildError::ExecutableNotFound { ref id }) => assert_eq!("missing", id),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_there_is_no_store() {
+    let calls = vec![];
+    let mut i = Interpreter::new();
+    let err = Build::<Value>::load(&mut i, "missing", Fingerprint::empty(), &calls).err().unwrap();
+    assert_eq!("executable not found", err.description());
+}
+
+#[test]
+fn error_if_environment_changed() {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    let calls = vec![&mock::add as &Function<Value>];
+
+    let mut i = Interpreter::with_store(MemoryStore::new());
+    {
+        i.build("sum", mock::sum_template(), &funs).unwrap();
+    }
+
+    let err = i.load("sum", Fingerprint::new([1; 20]), &calls).err().unwrap();
+    match err.downcast_ref::<BuildError>() {
+        Some(&BuildError::EnvironmentMismatch { ref id }) => assert_eq!("sum", id),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_load_call_is_missing() {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    let calls: Vec<&Function<Value>> = vec![];
+
+    let mut i = Interpreter::with_store(MemoryStore::new());
+    let env = i.build("sum", mock::sum_template(), &funs).unwrap().identify_env();
+
+    let err = i.load("sum", env, &calls).err().unwrap();
+    match err.downcast_ref::<BuildError>() {
+        Some(&BuildError::FunctionNotFound { ref required }) => assert_eq!("add", required),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn environment_reflects_bound_functions() {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+    funs.insert("sub", &mock::add as &Function<Value>);
+
+    let mut i = Interpreter::new();
+    let env = i.build("sum", mock::sum_template(), &funs).unwrap().identify_env();
+    assert_eq!(mock::sum_template().env_fingerprint(), env);
+
+    let mut i = Interpreter::new();
+    let other = i.build("sum", mock::sum_template().with_call("sub", Call(1)), &funs).unwrap().identify_env();
+    assert!(env != other);
+
+    let mut i = Interpreter::new();
+    let renamed = i.build("sum", Template::empty().with_call("sub", Call(0)), &funs).unwrap().identify_env();
+    assert!(env != renamed);
+}
+
+fn render<R: Read>(mut stream: R) -> String {
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    res
+}
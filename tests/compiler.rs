@@ -1,6 +1,4 @@
 extern crate little;
-#[macro_use] extern crate log;
-extern crate env_logger;
 
 mod mock;
 
@@ -9,29 +7,587 @@ use std::io::Read;
 
 use little::*;
 use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::optimizer::Optimizer;
 
 use mock::Value;
 
-//#[test]
-fn output_param() {
-    env_logger::init().unwrap();
+#[test]
+fn error_if_missing_constant() {
+    let funs = HashMap::new();
+    let mut c = Compiler::new();
+    let err = c.build(
+        "",
+        Template::empty()
+            .with_constant(Constant(2), Value::Str("before".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            ]),
+        &funs
+    ).err().unwrap();
+
+    assert_eq!("invalid template", err.description());
+}
+
+#[test]
+fn error_if_constant_output_can_not_be_formatted() {
+    let template = Template::empty()
+        .with_constant(Constant(0), Value::Unprintable)
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::Html },
+        ]);
+    let funs = HashMap::new();
+
+    let mut c = Compiler::new();
+    match c.build("", template, &funs).err().unwrap().downcast_ref::<BuildError>() {
+        Some(&BuildError::ConstantFormat { constant: Constant(0) }) => (),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn can_handle_interupt() {
+    let (res, errors) = run_template(
+        || Template::empty()
+            .with_constant(Constant(1), Value::Str("Abr".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+                Instruction::Interupt { code: 3 },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            ]),
+        Value::Null
+    );
+
+    assert_eq!("AbrAbr", res);
+    assert_eq!(vec!["interupt"], errors);
+}
 
+#[test]
+fn interupt_lets_host_fetch_data() {
     let funs = HashMap::new();
-    let mut i = Compiler::new();
-    let p = i.build(
+    let mut c = Compiler::new();
+    let p = c.build(
         "",
-        Template::<Value>::empty()
+        Template::empty()
+            .with_constant(Constant(1), Value::Null)
+            .with_constant(Constant(2), Value::Str("Hello ".into()))
             .with_instructions(vec![
-                Instruction::Output { location: Mem::Parameters }
+                Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+                Instruction::Push { location: Mem::Const(Constant(1)) },
+                Instruction::Interupt { code: 1 },
+                Instruction::Load { binding: Binding(0), location: Mem::StackTop1 },
+                Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
             ]),
         &funs
     ).unwrap();
 
     let mut res = String::new();
+    let mut stream = p.execute(Value::Null);
+    loop {
+        match stream.read_to_string(&mut res) {
+            Err(e) => {
+                assert_eq!(Some(1), LittleError::from_io_error(&e).and_then(|e| e.interupt_code()));
+                assert_eq!(Some(1), stream.interupted());
+                stream.pop_stack();
+                stream.push_stack(Value::Str("world".into())).unwrap();
+            },
+            Ok(_) => break,
+        }
+    }
+
+    assert_eq!("Hello world", &res);
+}
+
+#[test]
+fn error_if_pop_empty_stack() {
+    let funs = HashMap::new();
+    let mut c = Compiler::new();
+    let err = c.build(
+        "",
+        Template::<Value>::empty()
+            .with_instructions(vec![
+                Instruction::Pop { times: 1 }
+            ]),
+        &funs
+    ).err().unwrap();
+
+    assert_eq!("invalid template", err.description());
+}
 
-    p.execute(Value::Str("Hello".into()))
-        .read_to_string(&mut res)
-        .unwrap();
+#[test]
+fn exit() {
+    assert_eq!("", from_instructions_and_params(Vec::new(), Value::Null));
+}
+
+#[test]
+fn output_params() {
+    let res = from_instructions_and_params(
+        vec![
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None }
+        ],
+        Value::Str("Hello".into())
+    );
 
     assert_eq!("Hello", res);
 }
+
+#[test]
+fn should_jump() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Jump { pc: 3 },
+            Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello".into())),
+            (Constant(2), Value::Str("No output".into())),
+            (Constant(3), Value::Str("World".into())),
+        ]
+    );
+
+    assert_eq!("HelloWorld", res);
+}
+
+#[test]
+fn should_jump_into_middle_of_text() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Jump { pc: 3 },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("a".into())),
+            (Constant(2), Value::Str("b".into())),
+            (Constant(3), Value::Str("c".into())),
+        ]
+    );
+
+    assert_eq!("ca", res);
+}
+
+#[test]
+fn should_jump_past_end() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Jump { pc: 3 },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("a".into())),
+        ]
+    );
+
+    assert_eq!("a", res);
+}
+
+#[test]
+fn should_jump_if_eq() {
+    assert!(test_cond_jump(1, 1, Cond::Eq));
+}
+
+#[test]
+fn should_not_jump_if_not_eq() {
+    assert!(!test_cond_jump(2, 3, Cond::Eq));
+}
+
+#[test]
+fn should_jump_if_gt() {
+    assert!(test_cond_jump(2, 1, Cond::Gt));
+}
+
+#[test]
+fn should_not_jump_if_not_gt() {
+    assert!(!test_cond_jump(2, 2, Cond::Gt));
+    assert!(!test_cond_jump(1, 2, Cond::Gt));
+}
+
+#[test]
+fn should_jump_if_gte() {
+    assert!(test_cond_jump(2, 1, Cond::Gte));
+    assert!(test_cond_jump(2, 2, Cond::Gte));
+}
+
+#[test]
+fn should_not_jump_if_not_gte() {
+    assert!(!test_cond_jump(1, 2, Cond::Gte));
+}
+
+#[test]
+fn should_jump_if_lt() {
+    assert!(test_cond_jump(1, 2, Cond::Lt));
+}
+
+#[test]
+fn should_not_jump_if_not_lt() {
+    assert!(!test_cond_jump(2, 2, Cond::Lt));
+    assert!(!test_cond_jump(2, 1, Cond::Lt));
+}
+
+#[test]
+fn should_jump_if_lte() {
+    assert!(test_cond_jump(1, 2, Cond::Lte));
+    assert!(test_cond_jump(2, 2, Cond::Lte));
+}
+
+#[test]
+fn should_not_jump_if_not_lte() {
+    assert!(!test_cond_jump(2, 1, Cond::Lte));
+}
+
+#[test]
+fn should_jump_if_ne() {
+    assert!(test_cond_jump(2, 1, Cond::Ne));
+}
+
+#[test]
+fn should_not_jump_if_not_ne() {
+    assert!(!test_cond_jump(2, 2, Cond::Ne));
+}
+
+#[test]
+fn output_const() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None }
+        ],
+        vec![
+            (Constant(1), Value::Str("Const Hello".into()))
+        ]
+    );
+
+    assert_eq!("Const Hello", res);
+}
+
+#[test]
+fn run_function() {
+    let (res, errors) = run_template(
+        || Template::<Value>::empty()
+            .with_call("add", Call(1))
+            .with_constant(Constant(1), Value::Int(2))
+            .with_constant(Constant(2), Value::Int(3))
+            .with_instructions(vec![
+                Instruction::Push { location: Mem::Const(Constant(1)) },
+                Instruction::Push { location: Mem::Const(Constant(2)) },
+                Instruction::Call { call: Call(1), argc: 2, push_result_to_stack: true },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            ]),
+        Value::Null
+    );
+
+    assert!(errors.is_empty());
+    assert_eq!("5", res);
+}
+
+#[test]
+fn call_error_reports_template_pc() {
+    let template = || Template::empty()
+        .with_call("add", Call(0))
+        .with_constant(Constant(0), Value::Str("a".into()))
+        .with_constant(Constant(1), Value::Str("b".into()))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Call { call: Call(0), argc: 2, push_result_to_stack: true },
+        ]);
+
+    let (_, errors) = run_template(&template, Value::Null);
+    assert_eq!(vec!["call error"], errors);
+
+    let add = |_: &[Value]| -> LittleResult<Value> { Err("not a number".into()) };
+    let mut funs = HashMap::new();
+    funs.insert("add", &add as &Function<Value>);
+
+    // Merged output would move the call, keep template pcs as written.
+    let mut c = Compiler::new();
+    c.set_optimizer(Optimizer::empty());
+    let p = c.build("", template(), &funs).unwrap();
+    let err = p.execute(Value::Null).read_to_end(&mut Vec::new()).err().unwrap();
+    match err.get_ref().unwrap().downcast_ref::<LittleError>() {
+        Some(&LittleError::CallError { pc, .. }) => assert_eq!(4, pc),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn output_string_named_property() {
+    let (res, _) = run_template(
+        || Template::<Value>::empty()
+            .with_constant(Constant(2), Value::Str("your_name".into()))
+            .with_instructions(vec![
+                Instruction::Push { location: Mem::Parameters },
+                Instruction::Property { name: Mem::Const(Constant(2)) },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            ]),
+        your_name("hello")
+    );
+
+    assert_eq!("hello", res);
+}
+
+#[test]
+fn output_string_named_property_direct() {
+    let (res, _) = run_template(
+        || Template::<Value>::empty()
+            .with_constant(Constant(2), Value::Str("your_name".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Parameter { name: Constant(2) }, escape: Escape::None },
+            ]),
+        your_name("hello")
+    );
+
+    assert_eq!("hello", res);
+}
+
+#[test]
+fn push_const_output_stack_top1() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello Stack 1".into()))
+        ]
+    );
+
+    assert_eq!("Hello Stack 1", res);
+}
+
+#[test]
+fn push_constants_output_stack_top2() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Push { location: Mem::Const(Constant(2)) },
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Do not show this".into())),
+            (Constant(2), Value::Str("Hello Stack 2".into())),
+        ]
+    );
+
+    assert_eq!("Hello Stack 2", res);
+}
+
+#[test]
+fn load_binding_from_const_output_binding() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Load { binding: Binding(2), location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::Binding(Binding(2)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello Binding".into()))
+        ]
+    );
+
+    assert_eq!("Hello Binding", res);
+}
+
+#[test]
+fn load_binding_from_param_output_binding() {
+    let res = from_instructions_and_params(
+        vec![
+            Instruction::Load { binding: Binding(0), location: Mem::Parameters },
+            Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
+        ],
+        Value::Str("Hello Binding".into())
+    );
+
+    assert_eq!("Hello Binding", res);
+}
+
+#[test]
+fn load_binding_from_binding_stack1_stack2_output3() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Load { binding: Binding(0), location: Mem::Const(Constant(1)) },
+            Instruction::Load { binding: Binding(2), location: Mem::Const(Constant(2)) },
+            Instruction::Load { binding: Binding(1), location: Mem::Binding(Binding(0)) },
+            Instruction::Push { location: Mem::Binding(Binding(2)) },
+            Instruction::Push { location: Mem::Binding(Binding(1)) },
+            Instruction::Load { binding: Binding(3), location: Mem::StackTop1 },
+            Instruction::Load { binding: Binding(4), location: Mem::StackTop2 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello".into())),
+            (Constant(2), Value::Str("World".into())),
+        ]
+    );
+
+    assert_eq!("HelloWorld", res);
+}
+
+#[test]
+fn push_from_stack_to_stack() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Push { location: Mem::Const(Constant(2)) },
+            Instruction::Push { location: Mem::StackTop1 },
+            Instruction::Push { location: Mem::StackTop2 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello".into())),
+            (Constant(2), Value::Str("World".into())),
+        ]
+    );
+
+    assert_eq!("WorldWorld", res);
+}
+
+#[test]
+fn output_constant_twice() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello".into())),
+        ]
+    );
+
+    assert_eq!("HelloHello", res);
+}
+
+#[test]
+fn output_different_constants() {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+        ],
+        vec![
+            (Constant(1), Value::Str("Hello".into())),
+            (Constant(2), Value::Str("World".into())),
+            (Constant(3), Value::Str(" ".into())),
+        ]
+    );
+
+    assert_eq!("Hello World", res);
+}
+
+fn your_name(name: &str) -> Value {
+    Value::Obj(
+        vec![
+            ("your_name".into(), Value::Str(name.into()))
+        ].into_iter().collect()
+    )
+}
+
+fn from_instructions_and_params(
+    instructions: Vec<Instruction>,
+    params: Value
+) -> String {
+    let (res, errors) = run_template(
+        || Template::empty().with_instructions(instructions.clone()),
+        params
+    );
+    assert!(errors.is_empty(), "unexpected errors {:?}", errors);
+    res
+}
+
+fn from_instructions_and_constants(
+    instructions: Vec<Instruction>,
+    constants: Vec<(Constant, Value)>
+) -> String {
+    let (res, errors) = run_template(
+        || {
+            let mut template = Template::empty()
+                .with_instructions(instructions.clone());
+            for (constant, value) in &constants {
+                template = template.with_constant(*constant, value.clone());
+            }
+            template
+        },
+        Value::Null
+    );
+    assert!(errors.is_empty(), "unexpected errors {:?}", errors);
+    res
+}
+
+/// Check if stack compared to mem using condition produces a jump.
+fn test_cond_jump(stack: i64, mem: i64, cond: Cond) -> bool {
+    let res = from_instructions_and_constants(
+        vec![
+            Instruction::Push { location: Mem::Const(Constant(2)) },
+            Instruction::CondJump { pc: 3, location: Mem::Const(Constant(1)), test: cond },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None }, // should continue here if not jumped
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None }, // should skip to this line if jumped
+        ],
+        vec![
+            (Constant(1), Value::Int(mem)),
+            (Constant(2), Value::Int(stack)),
+            (Constant(3), Value::Int(1)),
+        ]
+    );
+
+    match res.as_ref() {
+        "1" => true,
+        "11" => false,
+        v => panic!("test_cond_jump produced unexpected output {:?}", v),
+    }
+}
+
+/// Runs template with compiler using different read buffer sizes,
+/// and checks that output and errors are identical to interpreter.
+///
+/// Returns output and descriptions of received errors.
+fn run_template<F>(template: F, params: Value) -> (String, Vec<String>)
+    where F: Fn() -> Template<Value>
+{
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+
+    let mut result = None;
+
+    for &buf_size in &[1, 3, 64] {
+        let mut i = Interpreter::new();
+        let expected = read_all(&mut i.build("", template(), &funs).unwrap().execute(params.clone()), buf_size);
+        let mut c = Compiler::new();
+        let actual = read_all(&mut c.build("", template(), &funs).unwrap().execute(params.clone()), buf_size);
+        assert_eq!(expected, actual, "compiler output differs with buffer size {}", buf_size);
+        result = Some(actual);
+    }
+
+    result.unwrap()
+}
+
+fn read_all<R: Read>(stream: &mut R, buf_size: usize) -> (String, Vec<String>) {
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    let mut buf = vec![0; buf_size];
+
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => output.extend_from_slice(&buf[..len]),
+            Err(e) => {
+                let description = e.get_ref().unwrap().description().to_string();
+                let interupt = description == "interupt";
+                errors.push(description);
+                if !interupt {
+                    break;
+                }
+            },
+        }
+    }
+
+    (String::from_utf8(output).unwrap(), errors)
+}
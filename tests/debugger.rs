@@ -0,0 +1,94 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::interpreter::{ DebugEvent, Interpreter };
+
+use mock::Value;
+
+fn template() -> Template<Value> {
+    Template::empty()
+        .with_constant(Constant(0), Value::Str("a".into()))
+        .with_constant(Constant(1), Value::Str("b".into()))
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Const(Constant(0)) },
+            Instruction::Load { binding: Binding(0), location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Pop { times: 1 },
+            Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
+        ])
+}
+
+#[test]
+fn steps_single_instructions() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut stream = p.execute(Value::Null);
+
+    assert_eq!(0, stream.pc());
+    assert_eq!(DebugEvent::Step, stream.step().unwrap());
+    assert_eq!(&[Value::Str("a".into())], stream.stack());
+    assert_eq!(DebugEvent::Step, stream.step().unwrap());
+    assert_eq!(&[Value::Str("b".into())], stream.bindings());
+    match stream.instruction() {
+        Some(&Instruction::Output { location: Mem::StackTop1, escape: Escape::None }) => (),
+        other => panic!("unexpected instruction {:?}", other),
+    }
+    assert_eq!(DebugEvent::Step, stream.step().unwrap());
+    assert_eq!(b"a", stream.pending_output());
+}
+
+#[test]
+fn steps_to_output() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut stream = p.execute(Value::Null);
+
+    assert_eq!(DebugEvent::Output, stream.step_output().unwrap());
+    assert_eq!(3, stream.pc());
+    assert_eq!(DebugEvent::Output, stream.step_output().unwrap());
+    assert_eq!(b"ab", stream.pending_output());
+    assert_eq!(DebugEvent::Done, stream.step_output().unwrap());
+}
+
+#[test]
+fn stops_at_breakpoints_and_reads_rest() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut stream = p.execute(Value::Null);
+
+    stream.add_breakpoint(1);
+    stream.add_breakpoint(3);
+    assert_eq!(DebugEvent::Breakpoint(1), stream.resume().unwrap());
+    assert_eq!(DebugEvent::Breakpoint(3), stream.resume().unwrap());
+    assert!(stream.remove_breakpoint(3));
+    assert_eq!(&[Value::Str("a".into())], stream.stack());
+
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    assert_eq!("ab", res);
+    assert_eq!(DebugEvent::Done, stream.resume().unwrap());
+}
+
+#[test]
+fn stops_at_breakpoint_on_first_instruction() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut stream = p.execute(Value::Null);
+
+    stream.add_breakpoint(0);
+    assert_eq!(DebugEvent::Breakpoint(0), stream.resume().unwrap());
+    assert_eq!(0, stream.pc());
+    assert_eq!(DebugEvent::Step, stream.step().unwrap());
+    assert_eq!(1, stream.pc());
+    assert_eq!(DebugEvent::Done, stream.resume().unwrap());
+    assert_eq!(b"ab", stream.pending_output());
+}
@@ -0,0 +1,133 @@
+extern crate little;
+
+mod mock;
+
+use std::sync::Arc;
+
+use little::*;
+use little::environment::Backend;
+use little::link::link;
+
+use mock::Value;
+
+fn environment(backend: Backend) -> Environment<Value> {
+    let mut env = Environment::new();
+    env.set_backend(backend);
+    env.add_function("upper", mock::upper);
+    env.add_global("site", Value::Str("Little".into()));
+    env.add_template("nav", "<nav>{{ upper(site) }}</nav>").unwrap();
+    env.add_template("page", "{% include \"nav\" %}{{ title }} - {{ site }}").unwrap();
+    env
+}
+
+#[test]
+fn renders_templates_by_name_with_any_backend() {
+    for &backend in &[Backend::Interpreter, Backend::Compiler] {
+        let env = environment(backend);
+        assert_eq!(
+            "<nav>LITTLE</nav>Home - Little",
+            env.render("page", Value::obj(vec![("title", Value::Str("Home".into()))])).unwrap()
+        );
+    }
+}
+
+#[test]
+fn parameters_hide_globals() {
+    let env = environment(Backend::Interpreter);
+    assert_eq!(
+        "<nav>OTHER</nav>Home - Other",
+        env.render("page", Value::obj(vec![("title", Value::Str("Home".into())), ("site", Value::Str("Other".into()))])).unwrap()
+    );
+}
+
+#[test]
+fn renders_into_writer() {
+    let env = environment(Backend::Compiler);
+    let mut out = Vec::new();
+    env.render_into("nav", Value::Null, &mut out).unwrap();
+    assert_eq!("<nav>LITTLE</nav>", String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn adds_linked_templates() {
+    let mut env = environment(Backend::Interpreter);
+    let layout = parser::parse_file("layout", "[{% block content %}{% endblock %}]").unwrap();
+    let child = parser::parse_file("child", "{% block content %}{% include \"nav\" %}{% endblock %}").unwrap();
+    env.add_template("child", link(layout, child).unwrap()).unwrap();
+
+    assert_eq!("[<nav>LITTLE</nav>]", env.render("child", Value::Null).unwrap());
+}
+
+#[test]
+fn shares_functions_between_environments() {
+    let shared: Arc<Function<Value>> = Arc::new(mock::upper);
+    let mut first = Environment::new();
+    let mut second = Environment::new();
+    first.add_shared_function("upper", shared.clone());
+    second.add_shared_function("up", shared);
+    first.add_template("t", "{{ upper(\"a\") }}").unwrap();
+    second.add_template("t", "{{ up(\"b\") }}").unwrap();
+
+    assert_eq!("A", first.render("t", Value::Null).unwrap());
+    assert_eq!("B", second.render("t", Value::Null).unwrap());
+}
+
+#[test]
+fn keeps_built_executables_until_functions_change() {
+    let shared: Arc<Function<Value>> = Arc::new(mock::upper);
+    let mut env = Environment::new();
+    env.add_shared_function("upper", shared.clone());
+    env.add_template("t", "{{ upper(\"a\") }}").unwrap();
+
+    assert_eq!("A", env.render("t", Value::Null).unwrap());
+    assert_eq!("A", env.render("t", Value::Null).unwrap());
+    // Held by us, by environment and by the built executable.
+    assert_eq!(3, Arc::strong_count(&shared));
+
+    env.add_function("other", mock::upper);
+    assert_eq!(2, Arc::strong_count(&shared));
+    assert_eq!("A", env.render("t", Value::Null).unwrap());
+    assert_eq!(3, Arc::strong_count(&shared));
+}
+
+#[test]
+fn rebuilds_replaced_templates() {
+    let mut env = environment(Backend::Compiler);
+    assert_eq!("<nav>LITTLE</nav>", env.render("nav", Value::Null).unwrap());
+    env.add_template("nav", "<ul>{{ site }}</ul>").unwrap();
+    assert_eq!("<ul>Little</ul>", env.render("nav", Value::Null).unwrap());
+}
+
+#[test]
+fn replaces_and_removes_templates() {
+    let mut env = environment(Backend::Interpreter);
+    env.add_template("nav", String::from("<ul>")).unwrap();
+    assert_eq!("<ul>", env.render("nav", Value::Null).unwrap());
+
+    assert!(env.remove_template("nav"));
+    assert!(!env.contains_template("nav"));
+    match env.render("nav", Value::Null) {
+        Err(LittleError::BuildError(BuildError::ExecutableNotFound { ref id })) if id == "nav" => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_template_source_is_invalid() {
+    let mut env = Environment::<Value>::new();
+    match env.add_template("bad", "{% if a %}") {
+        Err(BuildError::Parse(ref e)) => assert_eq!(ParseErrorKind::UnclosedBlock { tag: "if" }, e.kind),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(!env.contains_template("bad"));
+}
+
+#[test]
+fn error_if_function_is_not_registered() {
+    let mut env = Environment::<Value>::new();
+    env.add_template("t", "{{ missing() }}").unwrap();
+    match env.render("t", Value::Null) {
+        Err(LittleError::BuildError(BuildError::FunctionNotFound { ref required })) if required == "missing" => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -0,0 +1,119 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::parser;
+
+use mock::Value;
+
+fn render_template(template: &Fn() -> Template<Value>, default: Escape, value: Value) -> String {
+    let funs = HashMap::new();
+
+    let mut i = Interpreter::new();
+    let mut p = i.build("", template(), &funs).unwrap();
+    p.set_escape(default);
+    let mut interpreted = String::new();
+    p.execute(value.clone()).read_to_string(&mut interpreted).unwrap();
+
+    let mut c = Compiler::new();
+    let mut p = c.build("", template(), &funs).unwrap();
+    p.set_escape(default);
+    let mut compiled = String::new();
+    p.execute(value).read_to_string(&mut compiled).unwrap();
+
+    assert_eq!(interpreted, compiled);
+    interpreted
+}
+
+/// Renders parameters with both backends.
+fn render(escape: Escape, default: Escape, value: Value) -> String {
+    render_template(
+        &|| Template::empty()
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Parameters, escape: escape },
+            ]),
+        default,
+        value
+    )
+}
+
+fn render_str(escape: Escape, text: &str) -> String {
+    render(escape, Escape::None, Value::Str(text.into()))
+}
+
+#[test]
+fn none_outputs_as_is() {
+    assert_eq!("<a>&", render_str(Escape::None, "<a>&"));
+}
+
+#[test]
+fn escapes_html() {
+    assert_eq!(
+        "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;",
+        render_str(Escape::Html, "<a href=\"x\">Tom & 'Jerry'</a>")
+    );
+}
+
+#[test]
+fn escapes_html_attribute() {
+    assert_eq!("a&#x20;b&#x3D;&#x22;c&#x22;é", render_str(Escape::HtmlAttribute, "a b=\"c\"é"));
+}
+
+#[test]
+fn escapes_json_string() {
+    assert_eq!("say \\\"hi\\\"\\n\\u003c/script\\u003e", render_str(Escape::Json, "say \"hi\"\n</script>"));
+}
+
+#[test]
+fn escapes_url_component() {
+    assert_eq!("a%20b%2Fc%3F%C3%A9-_.~", render_str(Escape::Url, "a b/c?é-_.~"));
+}
+
+#[test]
+fn inherits_escape_from_executable() {
+    assert_eq!("&lt;b&gt;", render(Escape::Inherit, Escape::Html, Value::Str("<b>".into())));
+    assert_eq!("<b>", render(Escape::Inherit, Escape::None, Value::Str("<b>".into())));
+}
+
+#[test]
+fn safe_values_are_not_escaped() {
+    assert_eq!("<b>", render(Escape::Html, Escape::None, Value::Safe("<b>".into())));
+}
+
+#[test]
+fn escapes_constant_output() {
+    let output = render_template(
+        &|| Template::empty()
+            .with_constant(Constant(0), Value::Str("<".into()))
+            .with_constant(Constant(1), Value::Safe("<".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::Html },
+                Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::Inherit },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::Html },
+                Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            ]),
+        Escape::Url,
+        Value::Null
+    );
+    assert_eq!("&lt;%3C<<", output);
+}
+
+#[test]
+fn parser_escapes_expressions_but_not_text() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let template = parser::parse::<Value>("<p>{{ name }}</p>").unwrap();
+    let mut p = i.build("", template, &funs).unwrap();
+    p.set_escape(Escape::Html);
+
+    let params = Value::Obj(vec![("name".to_string(), Value::Str("<script>".into()))].into_iter().collect());
+    let mut res = String::new();
+    p.execute(params).read_to_string(&mut res).unwrap();
+    assert_eq!("<p>&lt;script&gt;</p>", res);
+}
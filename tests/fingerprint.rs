@@ -0,0 +1,68 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+
+use little::*;
+
+use mock::Value;
+
+#[test]
+fn same_template_has_same_fingerprint() {
+    assert_eq!(template().fingerprint(), template().fingerprint());
+    assert!(template().fingerprint().is_some());
+}
+
+#[test]
+fn fingerprint_changes_with_instructions() {
+    let changed = template().with_instructions(vec![Instruction::Output { location: Mem::Parameters, escape: Escape::None }]);
+    assert!(template().fingerprint() != changed.fingerprint());
+}
+
+#[test]
+fn fingerprint_changes_with_constants() {
+    let changed = template().with_constant(Constant(0), Value::Str("Bye, ".into()));
+    assert!(template().fingerprint() != changed.fingerprint());
+}
+
+#[test]
+fn fingerprint_changes_with_call_names() {
+    let changed = Template::empty()
+        .with_call("sub", Call(0))
+        .with_constant(Constant(0), Value::Str("Hello, ".into()))
+        .with_instructions(instructions());
+    assert!(template().fingerprint() != changed.fingerprint());
+}
+
+#[test]
+fn fingerprint_changes_with_blocks() {
+    let block = template().with_block(Block::new("content", 1));
+    let renamed = template().with_block(Block::new("body", 1));
+    let with_super = template().with_block(Block { name: "content".into(), pc: 1, super_calls: vec![2] });
+    assert!(template().fingerprint() != block.fingerprint());
+    assert!(block.fingerprint() != renamed.fingerprint());
+    assert!(block.fingerprint() != with_super.fingerprint());
+}
+
+#[test]
+fn no_fingerprint_if_constant_can_not_be_hashed() {
+    let changed = template().with_constant(Constant(1), Value::Obj(HashMap::new()));
+    assert_eq!(None, changed.fingerprint());
+}
+
+fn template() -> Template<Value> {
+    Template::empty()
+        .with_call("add", Call(0))
+        .with_constant(Constant(0), Value::Str("Hello, ".into()))
+        .with_instructions(instructions())
+}
+
+fn instructions() -> Vec<Instruction> {
+    vec![
+        Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+        Instruction::Push { location: Mem::Parameters },
+        Instruction::Call { call: Call(0), argc: 1, push_result_to_stack: true },
+        Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+    ]
+}
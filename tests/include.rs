@@ -0,0 +1,200 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::registry::{ IncludeContext, Registry, Render };
+
+use mock::Value;
+
+fn template(file: &str, source: &str) -> Template<Value> {
+    parser::parse_file(file, source).unwrap()
+}
+
+fn render(registry: &Registry<Value>, name: &str, params: Value) -> Result<String, LittleError> {
+    let mut out = Vec::new();
+    try!(registry.render(name, params, &mut out));
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn includes_executables_of_any_backend() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut i2 = Interpreter::new();
+    let mut c = Compiler::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", template("page", "<{% include \"nav\" %}|{% include \"item\" with user %}>"), &funs).unwrap());
+    registry.insert("nav", c.build("nav", template("nav", "nav of {{ user.name }}"), &funs).unwrap());
+    registry.insert("item", i2.build("item", template("item", "{{ name }}"), &funs).unwrap());
+
+    let params = Value::obj(vec![("user", Value::obj(vec![("name", Value::Str("Bob".into()))]))]);
+    assert_eq!("<nav of Bob|Bob>", render(&registry, "page", params).unwrap());
+}
+
+#[test]
+fn include_name_can_be_expression() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut a = Interpreter::new();
+    let mut b = Compiler::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", template("page", "{% for item in items %}{% include item.kind with item %}{% endfor %}"), &funs).unwrap());
+    registry.insert("a", a.build("a", template("a", "[{{ v }}]"), &funs).unwrap());
+    registry.insert("b", b.build("b", template("b", "({{ v }})"), &funs).unwrap());
+
+    let params = Value::obj(vec![("items", Value::List(vec![
+        Value::obj(vec![("kind", Value::Str("a".into())), ("v", Value::Int(1))]),
+        Value::obj(vec![("kind", Value::Str("b".into())), ("v", Value::Int(2))]),
+    ]))]);
+    assert_eq!("[1](2)", render(&registry, "page", params).unwrap());
+}
+
+#[test]
+fn errors_report_include_chain() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut i2 = Interpreter::new();
+    let mut c = Compiler::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", template("page", "{% include \"nav\" %}"), &funs).unwrap());
+    registry.insert("nav", i2.build("nav", template("nav", "\n{% include \"item\" %}"), &funs).unwrap());
+    registry.insert("item", c.build("item", template("item", "{{ user.missing }}"), &funs).unwrap());
+
+    let params = Value::obj(vec![("user", Value::obj(vec![]))]);
+    let error = render(&registry, "page", params).err().unwrap();
+    assert_eq!(vec!["nav", "item"], error.include_chain());
+    assert_eq!("page:1:1", error.location().unwrap().to_string());
+    match *error.original() {
+        LittleError::PropertyMissing { ref name, .. } => assert_eq!("missing", name),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(
+        "Property \"missing\" is missing at instruction 1. At item:1:4. In included \"item\". At nav:2:1. In included \"nav\". At page:1:1.",
+        error.to_string()
+    );
+}
+
+#[test]
+fn error_if_include_is_not_registered() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", Template::empty()
+        .with_constant(Constant(0), Value::Str("nav".into()))
+        .with_instructions(vec![
+            Instruction::Include { name: Mem::Const(Constant(0)), location: Mem::Parameters },
+        ]), &funs).unwrap());
+
+    match render(&registry, "page", Value::Null) {
+        Err(LittleError::IncludeMissing { ref name, pc: 0 }) => assert_eq!("nav", name),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match render(&registry, "nav", Value::Null) {
+        Err(LittleError::BuildError(BuildError::ExecutableNotFound { ref id })) => assert_eq!("nav", id),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_includes_are_nested_too_deep() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+
+    let mut registry = Registry::new();
+    registry.insert("self", i.build("self", template("self", "x{% include \"self\" %}"), &funs).unwrap());
+    registry.set_limits(Limits { max_include_depth: Some(3), .. Limits::default() });
+
+    let error = render(&registry, "self", Value::Null).err().unwrap();
+    assert_eq!(vec!["self"; 3], error.include_chain());
+    match *error.original() {
+        LittleError::IncludeDepthExceeded(3) => (),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn relative_names_are_resolved_from_including_executable() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut i2 = Interpreter::new();
+    let mut c = Compiler::new();
+
+    let mut registry = Registry::new();
+    registry.insert("pages/home", i.build("pages/home", template("home", "{% include \"../partials/nav\" %}|{% include \"./body\" %}"), &funs).unwrap());
+    registry.insert("partials/nav", c.build("partials/nav", template("nav", "nav"), &funs).unwrap());
+    registry.insert("pages/body", i2.build("pages/body", template("body", "body"), &funs).unwrap());
+
+    assert_eq!("nav|body", render(&registry, "pages/home", Value::Null).unwrap());
+}
+
+#[test]
+fn fallback_renders_executables_that_are_not_inserted() {
+    struct Echo;
+
+    impl Render<Value> for Echo {
+        fn render(&self, _data: Value, out: &mut io::Write, context: &mut IncludeContext<Value>) -> Result<(), LittleError> {
+            match context.name.as_ref() {
+                "missing" => Err(BuildError::ExecutableNotFound { id: context.name.clone() }.into()),
+                name => out.write_all(name.as_bytes()).map_err(LittleError::from),
+            }
+        }
+    }
+
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", template("page", "[{% include \"a\" %}][{% include \"b\" %}]{% include \"missing\" %}"), &funs).unwrap());
+    registry.set_fallback(Echo);
+
+    let err = render(&registry, "page", Value::Null).err().unwrap();
+    match *err.unlocated() {
+        LittleError::IncludeMissing { ref name, .. } => assert_eq!("missing", name),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!("fallback", render(&registry, "fallback", Value::Null).unwrap());
+}
+
+#[test]
+fn included_executables_share_limits() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut c = Compiler::new();
+
+    let mut registry = Registry::new();
+    registry.insert("page", i.build("page", template("page", "abc{% include \"nav\" %}{% include \"nav\" %}"), &funs).unwrap());
+    registry.insert("nav", c.build("nav", template("nav", "def"), &funs).unwrap());
+
+    assert_eq!("abcdefdef", render(&registry, "page", Value::Null).unwrap());
+
+    registry.set_limits(Limits { max_output_bytes: Some(8), .. Limits::default() });
+    let error = render(&registry, "page", Value::Null).err().unwrap();
+    assert_eq!(vec!["nav"], error.include_chain());
+    match *error.original() {
+        LittleError::OutputLimitExceeded(8) => (),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn executable_without_registry_can_not_include() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let executable = i.build("page", template("page", "{% include \"nav\" %}"), &funs).unwrap();
+
+    let mut out = Vec::new();
+    match executable.execute_into(Value::Null, &mut out).map_err(|e| e.original().to_string()) {
+        Err(e) => assert_eq!("Executable \"nav\" included at instruction 0 is not in the registry.", e),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -4,7 +4,6 @@ mod mock;
 
 use std::collections::HashMap;
 use std::io::Read;
-use std::error::Error;
 
 use little::*;
 use little::interpreter::Interpreter;
@@ -15,23 +14,16 @@ use mock::Value;
 fn error_if_missing_constant() {
     let funs = HashMap::new();
     let mut i = Interpreter::new();
-    let p = i.build(
+    let err = i.build(
         "",
-        Template::empty()
+        Template::<Value>::empty()
             .with_instructions(vec![
-                Instruction::Output { location: Mem::Const(Constant(1)) },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
             ]),
         &funs
-    ).unwrap();
+    ).err().expect("expected to receive error from build");
 
-    let mut res = String::new();
-
-    let res = p.execute(Value::Null)
-        .read_to_string(&mut res)
-        .err()
-        .expect("expected to receive error from read");
-
-    assert_eq!("constant is missing", res.description());
+    assert_eq!("invalid template", err.description());
 }
 
 #[test]
@@ -43,9 +35,9 @@ fn can_handle_interupt() {
         Template::empty()
             .with_constant(Constant(1), Value::Str("Abr".into()))
             .with_instructions(vec![
-                Instruction::Output { location: Mem::Const(Constant(1)) },
-                Instruction::Interupt,
-                Instruction::Output { location: Mem::Const(Constant(1)) },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+                Instruction::Interupt { code: 3 },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
             ]),
         &funs
     ).unwrap();
@@ -57,63 +49,92 @@ fn can_handle_interupt() {
     loop {
         match interpreter.read_to_string(&mut res) {
             Err(e) => {
-                match e.description() {
-                    "interupt" => received_interupt = true,
-                    e => panic!("other error {:?}", e),
+                match LittleError::from_io_error(&e).and_then(|e| e.interupt_code()) {
+                    Some(3) => received_interupt = true,
+                    other => panic!("other error {:?}", other),
                 };
+                assert_eq!(Some(3), interpreter.interupted());
             },
             Ok(_) => break,
         }
     }
 
     assert!(received_interupt);
+    assert_eq!(None, interpreter.interupted());
     assert_eq!("AbrAbr", &res);
 }
 
 #[test]
-fn error_if_missing_const() {
+fn interupt_lets_host_fetch_data() {
     let funs = HashMap::new();
     let mut i = Interpreter::new();
     let p = i.build(
         "",
-        Template::<Value>::empty()
+        Template::empty()
+            .with_constant(Constant(1), Value::Null)
             .with_instructions(vec![
-                Instruction::Output { location: Mem::Const(Constant(1)) }
+                Instruction::Push { location: Mem::Const(Constant(1)) },
+                Instruction::Interupt { code: 1 },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+                Instruction::Pop { times: 1 },
+                Instruction::Interupt { code: 2 },
+                Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
             ]),
         &funs
     ).unwrap();
 
     let mut res = String::new();
+    let mut interpreter = p.execute(Value::Null);
+    loop {
+        match interpreter.read_to_string(&mut res) {
+            Err(e) => match LittleError::from_io_error(&e).and_then(|e| e.interupt_code()) {
+                Some(1) => {
+                    assert_eq!(Some(Value::Null), interpreter.pop_stack());
+                    interpreter.push_stack(Value::Str("user ".into())).unwrap();
+                },
+                Some(2) => {
+                    assert_eq!(Value::Null, interpreter.get_binding(Binding(0)).into_owned());
+                    interpreter.set_binding(Binding(0), Value::Int(42)).unwrap();
+                },
+                other => panic!("unexpected interupt {:?}", other),
+            },
+            Ok(_) => break,
+        }
+    }
 
-    let res = p.execute(Value::Null)
-        .read_to_string(&mut res)
-        .err()
-        .expect("expected to receive error from read");
+    assert_eq!("user 42", &res);
+}
 
-    assert_eq!("constant is missing", res.description());
+#[test]
+fn error_if_missing_const() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let err = i.build(
+        "",
+        Template::<Value>::empty()
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None }
+            ]),
+        &funs
+    ).err().expect("expected to receive error from build");
+
+    assert_eq!("invalid template", err.description());
 }
 
 #[test]
 fn error_if_pop_empty_stack() {
     let funs = HashMap::new();
     let mut i = Interpreter::new();
-    let p = i.build(
+    let err = i.build(
         "",
-        Template::empty()
+        Template::<Value>::empty()
             .with_instructions(vec![
                 Instruction::Pop { times: 1 }
             ]),
         &funs
-    ).unwrap();
-
-    let mut res = String::new();
-
-    let res = p.execute(Value::Null)
-        .read_to_string(&mut res)
-        .err()
-        .expect("expected to receive error from read");
+    ).err().expect("expected to receive error from build");
 
-    assert_eq!("stack underflow", res.description());
+    assert_eq!("invalid template", err.description());
 }
 
 #[test]
@@ -126,7 +147,7 @@ fn exit() {
 fn output_params() {
     let res = from_instructions_and_params(
         vec![
-            Instruction::Output { location: Mem::Parameters }
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None }
         ],
         Value::Str("Hello".into())
     );
@@ -138,10 +159,10 @@ fn output_params() {
 fn should_jump() {
     let res = from_instructions_and_constants(
         vec![
-            Instruction::Output { location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
             Instruction::Jump { pc: 3 },
-            Instruction::Output { location: Mem::Const(Constant(2)) },
-            Instruction::Output { location: Mem::Const(Constant(3)) },
+            Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello".into())),
@@ -221,7 +242,7 @@ fn should_not_jump_if_not_ne() {
 fn output_const() {
     let res = from_instructions_and_constants(
         vec![
-            Instruction::Output { location: Mem::Const(Constant(1)) }
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None }
         ],
         vec![
             (Constant(1), Value::Str("Const Hello".into()))
@@ -254,7 +275,7 @@ fn run_function() {
                 Instruction::Push { location: Mem::Const(Constant(1)) },
                 Instruction::Push { location: Mem::Const(Constant(2)) },
                 Instruction::Call { call: Call(1), argc: 2, push_result_to_stack: true },
-                Instruction::Output { location: Mem::StackTop1 },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
             ]),
         &funs
     ).unwrap();
@@ -268,6 +289,59 @@ fn run_function() {
     assert_eq!("5", &res);
 }
 
+#[test]
+fn error_if_function_fails() {
+    let fail = |_: &[Value]| -> LittleResult<Value> {
+        Err("no luck".into())
+    };
+
+    let mut funs = HashMap::new();
+    funs.insert("fail", &fail as &Function<Value>);
+
+    let mut i = Interpreter::new();
+    let p = i.build("", failing_call_template(), &funs).unwrap();
+
+    let mut res = String::new();
+    let err = p.execute(Value::Null)
+        .read_to_string(&mut res)
+        .err()
+        .expect("expected to receive error from read");
+
+    match err.get_ref().unwrap().downcast_ref::<LittleError>() {
+        Some(&LittleError::CallError { ref name, pc, argc, ref error }) => {
+            assert_eq!("fail", name);
+            assert_eq!(2, pc);
+            assert_eq!(1, argc);
+            assert_eq!("no luck", error.to_string());
+        },
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn call_error_handler_can_substitute_value() {
+    let fail = |_: &[Value]| -> LittleResult<Value> {
+        Err("no luck".into())
+    };
+
+    let mut funs = HashMap::new();
+    funs.insert("fail", &fail as &Function<Value>);
+
+    let mut i = Interpreter::new();
+    let mut p = i.build("", failing_call_template(), &funs).unwrap();
+    p.set_call_error_handler(|e: &LittleError, args: &[Value]| match *e {
+        LittleError::CallError { ref name, .. } => Some(Value::Str(format!("{}({})", name, args[0]))),
+        _ => None,
+    });
+
+    let mut res = String::new();
+    p.execute(Value::Null)
+        .read_to_string(&mut res)
+        .unwrap();
+
+    assert_eq!("a fail(2)", &res);
+}
+
 #[test]
 fn output_string_named_property() {
     let funs = HashMap::new();
@@ -279,7 +353,7 @@ fn output_string_named_property() {
             .with_instructions(vec![
                 Instruction::Push { location: Mem::Parameters },
                 Instruction::Property { name: Mem::Const(Constant(2)) },
-                Instruction::Output { location: Mem::StackTop1 },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
             ]),
         &funs
     ).unwrap();
@@ -299,6 +373,65 @@ fn output_string_named_property() {
     assert_eq!("hello", &res);
 }
 
+#[test]
+fn error_if_property_is_missing() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build("", missing_property_template(), &funs).unwrap();
+
+    let mut res = String::new();
+    let err = p.execute(Value::Obj(HashMap::new()))
+        .read_to_string(&mut res)
+        .err()
+        .expect("expected to receive error from read");
+
+    match err.get_ref().unwrap().downcast_ref::<LittleError>() {
+        Some(&LittleError::PropertyMissing { ref name, pc }) => {
+            assert_eq!("nickname", name);
+            assert_eq!(1, pc);
+        },
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn missing_property_can_be_default() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut p = i.build("", missing_property_template(), &funs).unwrap();
+    p.set_missing_policy(MissingPolicy::Default);
+
+    let mut res = String::new();
+    p.execute(Value::Obj(HashMap::new()))
+        .read_to_string(&mut res)
+        .unwrap();
+
+    assert_eq!("[]", &res);
+}
+
+#[test]
+fn missing_property_and_parameter_can_be_provided_by_hook() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut p = i.build(
+        "",
+        missing_property_template()
+            .with_constant(Constant(2), Value::Str("user".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Parameter { name: Constant(2) }, escape: Escape::None },
+            ]),
+        &funs
+    ).unwrap();
+    p.set_missing_policy(MissingPolicy::Hook(Box::new(|_: &Value, name: &Value| Some(Value::Str(format!("<{}>", name))))));
+
+    let mut res = String::new();
+    p.execute(Value::Obj(HashMap::new()))
+        .read_to_string(&mut res)
+        .unwrap();
+
+    assert_eq!("[<nickname>]<user>", &res);
+}
+
 #[test]
 fn output_string_named_property_direct() {
     let funs = HashMap::new();
@@ -308,7 +441,7 @@ fn output_string_named_property_direct() {
         Template::<Value>::empty()
             .with_constant(Constant(2), Value::Str("your_name".into()))
             .with_instructions(vec![
-                Instruction::Output { location: Mem::Parameter { name: Constant(2) } },
+                Instruction::Output { location: Mem::Parameter { name: Constant(2) }, escape: Escape::None },
             ]),
         &funs
     ).unwrap();
@@ -333,7 +466,7 @@ fn push_const_output_stack_top1() {
     let res = from_instructions_and_constants(
         vec![
             Instruction::Push { location: Mem::Const(Constant(1)) },
-            Instruction::Output { location: Mem::StackTop1 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello Stack 1".into()))
@@ -349,7 +482,7 @@ fn push_constants_output_stack_top2() {
         vec![
             Instruction::Push { location: Mem::Const(Constant(2)) },
             Instruction::Push { location: Mem::Const(Constant(1)) },
-            Instruction::Output { location: Mem::StackTop2 },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Do not show this".into())),
@@ -365,7 +498,7 @@ fn load_binding_from_const_output_binding() {
     let res = from_instructions_and_constants(
         vec![
             Instruction::Load { binding: Binding(2), location: Mem::Const(Constant(1)) },
-            Instruction::Output { location: Mem::Binding(Binding(2)) },
+            Instruction::Output { location: Mem::Binding(Binding(2)), escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello Binding".into()))
@@ -380,7 +513,7 @@ fn load_binding_from_param_output_binding() {
     let res = from_instructions_and_params(
         vec![
             Instruction::Load { binding: Binding(0), location: Mem::Parameters },
-            Instruction::Output { location: Mem::Binding(Binding(0)) },
+            Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
         ],
         Value::Str("Hello Binding".into())
     );
@@ -399,8 +532,8 @@ fn load_binding_from_binding_stack1_stack2_output3() {
             Instruction::Push { location: Mem::Binding(Binding(1)) },
             Instruction::Load { binding: Binding(3), location: Mem::StackTop1 },
             Instruction::Load { binding: Binding(4), location: Mem::StackTop2 },
-            Instruction::Output { location: Mem::StackTop1 },
-            Instruction::Output { location: Mem::StackTop2 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello".into())),
@@ -419,8 +552,8 @@ fn push_from_stack_to_stack() {
             Instruction::Push { location: Mem::Const(Constant(2)) },
             Instruction::Push { location: Mem::StackTop1 },
             Instruction::Push { location: Mem::StackTop2 },
-            Instruction::Output { location: Mem::StackTop1 },
-            Instruction::Output { location: Mem::StackTop2 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello".into())),
@@ -435,8 +568,8 @@ fn push_from_stack_to_stack() {
 fn output_constant_twice() {
     let res = from_instructions_and_constants(
         vec![
-            Instruction::Output { location: Mem::Const(Constant(1)) },
-            Instruction::Output { location: Mem::Const(Constant(1)) },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello".into())),
@@ -450,9 +583,9 @@ fn output_constant_twice() {
 fn output_different_constants() {
     let res = from_instructions_and_constants(
         vec![
-            Instruction::Output { location: Mem::Const(Constant(1)) },
-            Instruction::Output { location: Mem::Const(Constant(3)) },
-            Instruction::Output { location: Mem::Const(Constant(2)) },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(2)), escape: Escape::None },
         ],
         vec![
             (Constant(1), Value::Str("Hello".into())),
@@ -464,6 +597,34 @@ fn output_different_constants() {
     assert_eq!("Hello World", res);
 }
 
+fn missing_property_template() -> Template<Value> {
+    Template::<Value>::empty()
+        .with_constant(Constant(0), Value::Str("nickname".into()))
+        .with_constant(Constant(1), Value::Str("[".into()))
+        .with_constant(Constant(3), Value::Str("]".into()))
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Property { name: Mem::Const(Constant(0)) },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None },
+            Instruction::Pop { times: 1 },
+        ])
+}
+
+fn failing_call_template() -> Template<Value> {
+    Template::<Value>::empty()
+        .with_call("fail", Call(0))
+        .with_constant(Constant(0), Value::Str("a ".into()))
+        .with_constant(Constant(1), Value::Int(2))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Call { call: Call(0), argc: 1, push_result_to_stack: true },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+        ])
+}
+
 fn from_instructions_and_params(
     instructions: Vec<Instruction>,
     params: Value
@@ -520,8 +681,8 @@ fn test_cond_jump(stack: i64, mem: i64, cond: Cond) -> bool {
         vec![
             Instruction::Push { location: Mem::Const(Constant(2)) },
             Instruction::CondJump { pc: 3, location: Mem::Const(Constant(1)), test: cond },
-            Instruction::Output { location: Mem::Const(Constant(3)) }, // should continue here if not jumped
-            Instruction::Output { location: Mem::Const(Constant(3)) }, // should skip to this line if jumped
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None }, // should continue here if not jumped
+            Instruction::Output { location: Mem::Const(Constant(3)), escape: Escape::None }, // should skip to this line if jumped
         ],
         vec![
             (Constant(1), Value::Int(mem)),
@@ -0,0 +1,90 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+/// Outputs `<item>` for every item of parameters.
+fn template() -> Template<Value> {
+    Template::empty()
+        .with_constant(Constant(0), Value::Str("<".into()))
+        .with_constant(Constant(1), Value::Str(">".into()))
+        .with_instructions(vec![
+            Instruction::IterStart { location: Mem::Parameters },
+            Instruction::IterNext { binding: Binding(0), pc: 6 },
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Binding(Binding(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            Instruction::Jump { pc: 1 },
+        ])
+}
+
+fn render<'a, E: Execute<'a, Value>>(executable: &'a E, params: Value) -> Result<String, LittleError> {
+    let mut out = Vec::new();
+    try!(executable.execute_into(params, &mut out));
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn iterates_list_items() {
+    let funs = HashMap::new();
+    let list = Value::List(vec![Value::Int(1), Value::Str("a".into()), Value::Int(3)]);
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    assert_eq!("<1><a><3>", render(&p, list.clone()).unwrap());
+    assert_eq!("", render(&p, Value::List(vec![])).unwrap());
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    assert_eq!("<1><a><3>", render(&p, list).unwrap());
+    assert_eq!("", render(&p, Value::List(vec![])).unwrap());
+}
+
+#[test]
+fn error_if_value_is_not_iterable() {
+    let funs = HashMap::new();
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    match render(&p, Value::Int(1)) {
+        Err(LittleError::NotIterable { pc: 0 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    match render(&p, Value::Int(1)) {
+        Err(LittleError::NotIterable { pc: 0 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn nested_loops_keep_own_position() {
+    let funs = HashMap::new();
+    let template = parser::parse::<Value>(
+        "{% for row in rows %}[{% for cell in row %}{{ cell }}{% endfor %}]{% endfor %}"
+    ).unwrap();
+    let mut params = HashMap::new();
+    params.insert("rows".to_string(), Value::List(vec![
+        Value::List(vec![Value::Int(1), Value::Int(2)]),
+        Value::List(vec![]),
+        Value::List(vec![Value::Int(3)]),
+    ]));
+
+    let mut i = Interpreter::new();
+    let mut res = String::new();
+    i.build("", template, &funs).unwrap()
+        .execute(Value::Obj(params))
+        .read_to_string(&mut res)
+        .unwrap();
+    assert_eq!("[12][][3]", res);
+}
@@ -0,0 +1,144 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+#[test]
+fn error_if_instruction_limit_is_exceeded() {
+    let limits = Limits { max_instructions: Some(100), .. Limits::default() };
+    let template = || Template::empty()
+        .with_constant(Constant(0), Value::Str("a".into()))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Jump { pc: 0 },
+        ]);
+
+    assert_eq!(vec!["instruction limit exceeded"; 2], errors(template, limits));
+}
+
+#[test]
+fn error_if_stack_limit_is_exceeded() {
+    let limits = Limits { max_stack_depth: Some(2), .. Limits::default() };
+    let template = || Template::empty()
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Push { location: Mem::Parameters },
+        ]);
+
+    assert_eq!(vec!["stack limit exceeded"; 2], errors(template, limits));
+}
+
+#[test]
+fn error_if_binding_limit_is_exceeded() {
+    let limits = Limits { max_bindings: Some(4), .. Limits::default() };
+    let template = || Template::empty()
+        .with_instructions(vec![
+            Instruction::Load { binding: Binding(3), location: Mem::Parameters },
+            Instruction::Load { binding: Binding(4), location: Mem::Parameters },
+        ]);
+
+    assert_eq!(vec!["binding limit exceeded"; 2], errors(template, limits));
+}
+
+#[test]
+fn error_if_default_binding_limit_is_exceeded() {
+    let template = || Template::empty()
+        .with_instructions(vec![
+            Instruction::Load { binding: Binding(1000000), location: Mem::Parameters },
+        ]);
+
+    assert_eq!(vec!["binding limit exceeded"; 2], errors(template, Limits::default()));
+}
+
+#[test]
+fn error_if_output_limit_is_exceeded() {
+    let limits = Limits { max_output_bytes: Some(5), .. Limits::default() };
+    let template = || Template::empty()
+        .with_constant(Constant(0), Value::Str("abc".into()))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+        ]);
+
+    assert_eq!(vec!["output limit exceeded"; 2], errors(template, limits));
+}
+
+#[test]
+fn output_is_not_written_past_limit() {
+    let funs = HashMap::new();
+    let limits = Limits { max_output_bytes: Some(5), .. Limits::default() };
+    let template = || Template::empty()
+        .with_constant(Constant(0), Value::Str("abcdefghij".into()))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+        ]);
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut out = Vec::new();
+    match p.execute_with_limits(Value::Null, limits).write_to(&mut out) {
+        Err(LittleError::OutputLimitExceeded(5)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(b"abcde", &out[..]);
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    let mut out = Vec::new();
+    match p.execute_with_limits(Value::Null, limits).write_to(&mut out) {
+        Err(LittleError::OutputLimitExceeded(5)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(b"abcde", &out[..]);
+}
+
+#[test]
+fn limits_can_be_set_on_executable() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let mut p = i.build(
+        "",
+        Template::<Value>::empty()
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+            ]),
+        &funs
+    ).unwrap();
+    p.set_limits(Limits { max_output_bytes: Some(3), .. Limits::unlimited() });
+
+    let mut res = String::new();
+    assert!(p.execute(Value::Str("abc".into())).read_to_string(&mut res).is_ok());
+    let err = p.execute(Value::Str("abcd".into())).read_to_string(&mut res).err().unwrap();
+    assert_eq!("output limit exceeded", err.get_ref().unwrap().description());
+}
+
+/// Returns error descriptions of interpreter and compiler.
+fn errors<F>(template: F, limits: Limits) -> Vec<String>
+    where F: Fn() -> Template<Value>
+{
+    let funs = HashMap::new();
+    let params = Value::Str("xyz".into());
+    let mut res = Vec::new();
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let i_err = p.execute_with_limits(params.clone(), limits).read_to_end(&mut res).err().unwrap();
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    let c_err = p.execute_with_limits(params, limits).read_to_end(&mut res).err().unwrap();
+
+    vec![
+        i_err.get_ref().unwrap().description().to_string(),
+        c_err.get_ref().unwrap().description().to_string(),
+    ]
+}
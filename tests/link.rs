@@ -0,0 +1,142 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::link::link;
+
+use mock::Value;
+
+const LAYOUT: &'static str = "\
+<title>{% block title %}Site{% endblock %}</title>
+{% block content %}Empty{% endblock %}
+<footer>{% block footer %}{{ year }}{% endblock %}</footer>";
+
+fn template(file: &str, source: &str) -> Template<Value> {
+    parser::parse_file(file, source).unwrap()
+}
+
+fn render<F: Fn() -> Template<Value>>(template: F, params: Value) -> Result<String, LittleError> {
+    let mut funs = HashMap::new();
+    funs.insert("upper", &mock::upper as &Function<Value>);
+    funs.insert("twice", &mock::twice as &Function<Value>);
+
+    let mut out = Vec::new();
+    let mut i = Interpreter::new();
+    let interpreted = i.build("page", template(), &funs).unwrap()
+        .execute(params.clone())
+        .write_to(&mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    let mut out = Vec::new();
+    let mut c = Compiler::new();
+    let compiled = c.build("page", template(), &funs).unwrap()
+        .execute(params)
+        .write_to(&mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    match (interpreted, compiled) {
+        (Ok(a), Ok(b)) => {
+            assert_eq!(a, b);
+            Ok(a)
+        },
+        (Err(e), Err(_)) => Err(e),
+        (a, b) => panic!("backends disagree: {:?} and {:?}", a, b),
+    }
+}
+
+#[test]
+fn parent_renders_own_blocks() {
+    let params = Value::obj(vec![("year", Value::Int(2016))]);
+    assert_eq!(
+        "<title>Site</title>\nEmpty\n<footer>2016</footer>",
+        render(|| template("layout", LAYOUT), params).unwrap()
+    );
+}
+
+#[test]
+fn child_blocks_replace_parent_blocks() {
+    let page = || link(
+        template("layout", LAYOUT),
+        template("page", "ignored {% block content %}{% for item in items %}[{{ item }}]{% endfor %}{% endblock %} ignored\n\
+                          {% block title %}Page{% endblock %}")
+    ).unwrap();
+
+    let params = Value::obj(vec![
+        ("year", Value::Int(2016)),
+        ("items", Value::List(vec![Value::Int(1), Value::Int(2)])),
+    ]);
+    assert_eq!(
+        "<title>Page</title>\n[1][2]\n<footer>2016</footer>",
+        render(page, params).unwrap()
+    );
+}
+
+#[test]
+fn super_renders_parent_block() {
+    let page = || link(
+        template("layout", LAYOUT),
+        template("page", "{% block title %}{{ super() }} - {{ title }}{% endblock %}")
+    ).unwrap();
+
+    let params = Value::obj(vec![("year", Value::Int(2016)), ("title", Value::Str("Page".into()))]);
+    assert_eq!(
+        "<title>Site - Page</title>\nEmpty\n<footer>2016</footer>",
+        render(page, params).unwrap()
+    );
+}
+
+#[test]
+fn super_without_parent_block_renders_nothing() {
+    let page = || template("page", "{% block title %}[{{ super() }}]{% endblock %}");
+    assert_eq!("[]", render(page, Value::Null).unwrap());
+}
+
+#[test]
+fn nested_blocks_can_be_replaced() {
+    let page = || link(
+        template("layout", "{% block body %}<{% block inner %}inner{% endblock %}>{% endblock %}"),
+        template("page", "{% block inner %}{{ super() }}!{% endblock %}")
+    ).unwrap();
+    assert_eq!("<inner!>", render(page, Value::Null).unwrap());
+}
+
+#[test]
+fn multiple_levels_link_in_any_order() {
+    let base = || template("base", "{% block title %}Base{% endblock %}|{% block content %}{% endblock %}");
+    let layout = || template("layout", "{% block title %}{{ super() }}/Layout{% endblock %}");
+    let page = || template("page", "{% block title %}{{ super() }}/Page{% endblock %}{% block content %}text{% endblock %}");
+
+    let expected = "Base/Layout/Page|text";
+    assert_eq!(expected, render(|| link(base(), link(layout(), page()).unwrap()).unwrap(), Value::Null).unwrap());
+    assert_eq!(expected, render(|| link(link(base(), layout()).unwrap(), page()).unwrap(), Value::Null).unwrap());
+}
+
+#[test]
+fn constants_and_calls_are_renumbered() {
+    let page = || link(
+        template("layout", "{{ upper(\"a\") }}{% block content %}{% endblock %}{{ 1 }}"),
+        template("page", "{% block content %}{{ 2 }}{{ twice(\"b\") }}{{ upper(\"c\") }}{% endblock %}")
+    ).unwrap();
+    assert_eq!("A2bbC1", render(page, Value::Null).unwrap());
+
+    let linked = page();
+    assert_eq!(Some(Call(0)), linked.calls_template.index_of("upper"));
+    assert_eq!(Some(Call(1)), linked.calls_template.index_of("twice"));
+}
+
+#[test]
+fn errors_in_child_blocks_point_to_child_source() {
+    let page = || link(
+        template("layout", LAYOUT),
+        template("page", "{% block title %}\n  {{ user.name }}{% endblock %}")
+    ).unwrap();
+
+    let err = render(page, Value::obj(vec![("user", Value::obj(vec![]))])).err().unwrap();
+    let message = format!("{}", err);
+    assert!(message.ends_with(" At page:2:6."), "unexpected error: {}", message);
+}
@@ -0,0 +1,194 @@
+extern crate little;
+
+mod mock;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use little::*;
+use little::bytecode::Bytecode;
+use little::environment::Backend;
+use little::loader::*;
+
+use mock::Value;
+
+/// Memory loader that can be changed after it is given away.
+struct Shared(Rc<RefCell<MemoryLoader>>);
+
+impl TemplateLoader for Shared {
+    fn load(&self, name: &str) -> Result<LoadedTemplate, BuildError> {
+        self.0.borrow().load(name)
+    }
+
+    fn modified(&self, name: &str) -> Result<u64, BuildError> {
+        self.0.borrow().modified(name)
+    }
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("little-loader-test-{}-{}", std::process::id(), test));
+    fs::create_dir_all(dir.join("pages")).unwrap();
+    fs::create_dir_all(dir.join("partials")).unwrap();
+    dir
+}
+
+fn is_not_found<T>(result: Result<T, BuildError>, name: &str) -> bool {
+    match result {
+        Err(BuildError::ExecutableNotFound { ref id }) => id == name,
+        _ => false,
+    }
+}
+
+#[test]
+fn resolves_names() {
+    assert_eq!("partials/nav", resolve_name("pages/home", "../partials/nav").unwrap());
+    assert_eq!("pages/nav", resolve_name("pages/home", "./nav").unwrap());
+    assert_eq!("nav", resolve_name("pages/home", "nav").unwrap());
+    assert_eq!("a/c", resolve_name("", "a/./b/../c").unwrap());
+    assert_eq!("a/b", resolve_name("", "/a//b").unwrap());
+
+    match resolve_name("home", "../nav") {
+        Err(BuildError::InvalidName { ref name }) => assert_eq!("../nav", name),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(resolve_name("", "a/..").is_err());
+}
+
+#[test]
+fn memory_loader_stamp_changes_on_insert() {
+    let mut loader = MemoryLoader::new();
+    loader.insert_source("a", "A");
+    let first = loader.modified("a").unwrap();
+    loader.insert_source("a", "B");
+
+    let loaded = loader.load("a").unwrap();
+    assert_eq!(TemplateSource::Text("B".into()), loaded.source);
+    assert!(loaded.modified != first);
+    assert_eq!(loaded.modified, loader.modified("a").unwrap());
+
+    assert!(loader.remove("a"));
+    assert!(is_not_found(loader.load("a"), "a"));
+    assert!(is_not_found(loader.modified("a"), "a"));
+}
+
+#[test]
+fn directory_loader_reads_source_and_bytecode() {
+    let dir = temp_dir("directory");
+    fs::write(dir.join("pages/home.html"), "Hello, {{ name }}!").unwrap();
+    let mut bytes = Vec::new();
+    parser::parse::<Value>("nav").unwrap().write_bytecode(&mut bytes).unwrap();
+    fs::write(dir.join("partials/nav.html"), &bytes).unwrap();
+
+    let loader = DirectoryLoader::new(&dir).with_extension("html");
+    let home = loader.load("pages/home");
+    let nav = loader.load("partials/nav");
+    let missing = loader.load("pages/missing");
+    let outside = loader.load("../pages/home");
+    let directory = loader.load("pages");
+    fs::remove_dir_all(&dir).unwrap();
+
+    let home = home.unwrap();
+    assert_eq!(TemplateSource::Text("Hello, {{ name }}!".into()), home.source);
+    assert!(home.modified > 0);
+    assert_eq!(TemplateSource::Bytecode(bytes), nav.unwrap().source);
+    assert!(is_not_found(missing, "pages/missing"));
+    assert!(is_not_found(directory, "pages"));
+    match outside {
+        Err(BuildError::InvalidName { .. }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn directory_loader_rejects_names_outside_of_root() {
+    let loader = DirectoryLoader::new("templates");
+    for name in &["pages\\..\\..\\secret", "C:secret", "c:/secret", "pages/a:b"] {
+        match loader.path(name) {
+            Err(BuildError::InvalidName { name: ref invalid }) => assert_eq!(name, invalid),
+            other => panic!("unexpected result {:?} for {:?}", other, name),
+        }
+    }
+    assert_eq!(PathBuf::from("templates/pages/home"), loader.path("pages/home").unwrap());
+}
+
+#[test]
+fn chain_loader_uses_first_loader_that_has_template() {
+    let mut first = MemoryLoader::new();
+    first.insert_source("a", "first");
+    let mut second = MemoryLoader::new();
+    second.insert_source("a", "second");
+    second.insert_source("b", "second");
+
+    let loader = ChainLoader::new().with(first).with(second);
+    assert_eq!(TemplateSource::Text("first".into()), loader.load("a").unwrap().source);
+    assert_eq!(TemplateSource::Text("second".into()), loader.load("b").unwrap().source);
+    assert!(is_not_found(loader.load("c"), "c"));
+}
+
+fn site_loader() -> MemoryLoader {
+    let mut bytes = Vec::new();
+    parser::parse::<Value>("<nav>{{ title }}</nav>").unwrap().write_bytecode(&mut bytes).unwrap();
+
+    let mut loader = MemoryLoader::new();
+    loader.insert_source("pages/home", "{% include \"../partials/nav\" %}{% include \"./body\" %}");
+    loader.insert_source("pages/body", "Home");
+    loader.insert_bytecode("partials/nav", bytes);
+    loader
+}
+
+#[test]
+fn environment_loads_templates_with_relative_includes() {
+    for &backend in &[Backend::Interpreter, Backend::Compiler] {
+        let mut env = Environment::new();
+        env.set_backend(backend);
+        env.set_loader(site_loader());
+        env.add_global("title", Value::Str("Little".into()));
+
+        assert_eq!("<nav>Little</nav>Home", env.render("pages/home", Value::Null).unwrap());
+    }
+}
+
+#[test]
+fn environment_prefers_added_templates() {
+    let mut env = Environment::new();
+    env.set_loader(site_loader());
+    env.add_template("pages/body", "Added").unwrap();
+    env.add_global("title", Value::Str("Little".into()));
+
+    assert_eq!("<nav>Little</nav>Added", env.render("pages/home", Value::Null).unwrap());
+}
+
+#[test]
+fn environment_reloads_changed_templates() {
+    let loader = Rc::new(RefCell::new(site_loader()));
+    let mut env = Environment::new();
+    env.set_loader(Shared(loader.clone()));
+
+    assert_eq!("Home", env.render("pages/body", Value::Null).unwrap());
+    loader.borrow_mut().insert_source("pages/body", "Changed");
+    assert_eq!("Changed", env.render("pages/body", Value::Null).unwrap());
+}
+
+#[test]
+fn error_if_included_template_is_not_found() {
+    let mut loader = MemoryLoader::new();
+    loader.insert_source("pages/home", "{% include \"../../nav\" %}");
+    loader.insert_source("pages/about", "\n{% include \"missing\" %}");
+    let mut env = Environment::<Value>::new();
+    env.set_loader(loader);
+
+    let err = env.render("pages/home", Value::Null).err().unwrap();
+    match *err.unlocated() {
+        LittleError::IncludeMissing { ref name, .. } => assert_eq!("../../nav", name),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    let err = env.render("pages/about", Value::Null).err().unwrap();
+    match *err.unlocated() {
+        LittleError::IncludeMissing { ref name, .. } => assert_eq!("missing", name),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(Some("pages/about:2:1".into()), err.location().map(|l| l.to_string()));
+}
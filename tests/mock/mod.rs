@@ -3,8 +3,11 @@
 use std::collections::HashMap;
 use std::cmp::Ordering;
 use std::fmt;
+use std::io;
 
-use little::{ GetProperty, LittleValue, IdentifyValue, Sha1Hasher, Fingerprint };
+use little::{ Call, Constant, Escape, Instruction, Mem, Template };
+use little::{ GetProperty, IterateValue, OperateValue, Operator, LittleResult, LittleValue, IdentifyValue, Sha1Hasher, Fingerprint };
+use little::bytecode::{ self, ValueSerializer };
 
 /// Simple value implementation.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -12,7 +15,75 @@ pub enum Value {
     Null,
     Int(i64),
     Str(String),
+    List(Vec<Value>),
     Obj(HashMap<String, Value>),
+    /// Already escaped string.
+    Safe(String),
+    /// Value that fails to display.
+    Unprintable,
+}
+
+impl Value {
+    /// Object with `items` as properties.
+    pub fn obj(items: Vec<(&str, Value)>) -> Value {
+        Value::Obj(items.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+/// `add(a, b)`: sum of two integers.
+pub fn add(args: &[Value]) -> LittleResult<Value> {
+    match (args.get(0), args.get(1)) {
+        (Some(&Value::Int(a)), Some(&Value::Int(b))) => Ok(Value::Int(a + b)),
+        _ => Err("add expects two integers".into()),
+    }
+}
+
+/// `upper(s)`: string in upper case.
+pub fn upper(args: &[Value]) -> LittleResult<Value> {
+    match args.get(0) {
+        Some(&Value::Str(ref s)) => Ok(Value::Str(s.to_uppercase())),
+        _ => Err("upper expects a string".into()),
+    }
+}
+
+/// `twice(s)`: string repeated two times.
+pub fn twice(args: &[Value]) -> LittleResult<Value> {
+    match args.get(0) {
+        Some(&Value::Str(ref s)) => Ok(Value::Str(format!("{}{}", s, s))),
+        _ => Err("twice expects a string".into()),
+    }
+}
+
+/// Outputs `"Hello, "`, the result of `upper(params)` and `42`.
+pub fn hello_template() -> Template<Value> {
+    let mut template = Template::empty()
+        .with_call("upper", Call(0))
+        .with_constant(Constant(0), Value::Str("Hello, ".into()))
+        .with_constant(Constant(1), Value::Int(42))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Call { call: Call(0), argc: 1, push_result_to_stack: true },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+        ]);
+    template.bindings_capacity = 2;
+    template
+}
+
+/// Outputs `"Sum: "` and the result of `add(params, 2)`.
+pub fn sum_template() -> Template<Value> {
+    Template::empty()
+        .with_call("add", Call(0))
+        .with_constant(Constant(0), Value::Str("Sum: ".into()))
+        .with_constant(Constant(1), Value::Int(2))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Push { location: Mem::Const(Constant(1)) },
+            Instruction::Call { call: Call(0), argc: 2, push_result_to_stack: true },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+        ])
 }
 
 impl PartialOrd for Value {
@@ -21,13 +92,26 @@ impl PartialOrd for Value {
             (&Value::Null, &Value::Null) => Some(Ordering::Equal),
             (&Value::Int(ref a), &Value::Int(ref b)) => a.partial_cmp(b),
             (&Value::Str(ref a), &Value::Str(ref b)) => a.partial_cmp(b),
+            (&Value::Safe(ref a), &Value::Safe(ref b)) => a.partial_cmp(b),
+            (&Value::List(ref a), &Value::List(ref b)) => a.partial_cmp(b),
             (&Value::Obj(_), &Value::Obj(_)) => None,
             _ => None,
         }
     }
 }
 
-impl LittleValue for Value { }
+impl LittleValue for Value {
+    fn concat_output(&self, other: &Value) -> Option<Value> {
+        Some(Value::Str(format!("{}{}", self, other)))
+    }
+
+    fn is_safe(&self) -> bool {
+        match *self {
+            Value::Safe(_) => true,
+            _ => false,
+        }
+    }
+}
 
 impl GetProperty<Value> for Value {
     fn get_property(&self, name: Value) -> Option<Value> {
@@ -41,16 +125,164 @@ impl GetProperty<Value> for Value {
     }
 }
 
+impl IterateValue<Value> for Value {
+    fn iterate_len(&self) -> Option<usize> {
+        match *self {
+            Value::List(ref list) => Some(list.len()),
+            _ => None,
+        }
+    }
+
+    fn iterate_item(&self, index: usize) -> Option<Value> {
+        match *self {
+            Value::List(ref list) => list.get(index).cloned(),
+            _ => None,
+        }
+    }
+}
+
+/// `true` is `Int(1)` and `false` is `Null`, the default value.
+impl OperateValue<Value> for Value {
+    fn operate(&self, op: Operator, other: &Value) -> Option<Value> {
+        match (self, other) {
+            (&Value::Int(a), &Value::Int(b)) => match op {
+                Operator::Add => a.checked_add(b),
+                Operator::Sub => a.checked_sub(b),
+                Operator::Mul => a.checked_mul(b),
+                Operator::Div => a.checked_div(b),
+                Operator::Rem => a.checked_rem(b),
+            }.map(Value::Int),
+            (&Value::Str(ref a), &Value::Str(ref b)) if op == Operator::Add => Some(Value::Str(format!("{}{}", a, b))),
+            _ => None,
+        }
+    }
+
+    fn negate(&self) -> Option<Value> {
+        match *self {
+            Value::Int(i) => i.checked_neg().map(Value::Int),
+            _ => None,
+        }
+    }
+
+    fn from_bool(value: bool) -> Option<Value> {
+        Some(if value { Value::Int(1) } else { Value::Null })
+    }
+}
+
 impl IdentifyValue for Value {
     fn identify_value(&self) -> Option<Fingerprint> {
         None
     }
 
-    fn hash_value<H: Sha1Hasher>(&self, _hasher: &mut H) -> Result<(), ()> {
-        Err(())
+    fn hash_value<H: Sha1Hasher>(&self, hasher: &mut H) -> Result<(), ()> {
+        match *self {
+            Value::Null => hasher.write_u8(0),
+            Value::Int(i) => {
+                hasher.write_u8(1);
+                hasher.write_i64(i);
+            },
+            Value::Str(ref s) => {
+                hasher.write_u8(2);
+                hasher.write_u64(s.len() as u64);
+                hasher.write(s.as_bytes());
+            },
+            Value::List(ref list) => {
+                hasher.write_u8(3);
+                hasher.write_u64(list.len() as u64);
+                for item in list {
+                    try!(item.hash_value(hasher));
+                }
+            },
+            Value::Safe(ref s) => {
+                hasher.write_u8(4);
+                hasher.write_u64(s.len() as u64);
+                hasher.write(s.as_bytes());
+            },
+            Value::Obj(_) | Value::Unprintable => return Err(()),
+        };
+        Ok(())
+    }
+}
+
+impl ValueSerializer for Value {
+    fn serialize_value<O: io::Write>(&self, writer: &mut O) -> Result<u64, bytecode::Error> {
+        Ok(match *self {
+            Value::Null => {
+                try!(writer.write_all(&[0]));
+                1
+            },
+            Value::Int(i) => {
+                try!(writer.write_all(&[1]));
+                try!(writer.write_all(&i.to_le_bytes()));
+                9
+            },
+            Value::Str(ref s) => {
+                try!(writer.write_all(&[2]));
+                try!(writer.write_all(&(s.len() as u64).to_le_bytes()));
+                try!(writer.write_all(s.as_bytes()));
+                9 + s.len() as u64
+            },
+            Value::List(ref items) => {
+                try!(writer.write_all(&[3]));
+                try!(writer.write_all(&(items.len() as u64).to_le_bytes()));
+                let mut len = 9;
+                for item in items {
+                    len += try!(item.serialize_value(writer));
+                }
+                len
+            },
+            Value::Safe(ref s) => {
+                try!(writer.write_all(&[4]));
+                try!(writer.write_all(&(s.len() as u64).to_le_bytes()));
+                try!(writer.write_all(s.as_bytes()));
+                9 + s.len() as u64
+            },
+            Value::Obj(_) | Value::Unprintable => return Err(bytecode::Error::InvalidBinaryFormat),
+        })
+    }
+
+    fn deserialize_value<I: io::Read>(reader: &mut I) -> Result<(u64, Value), bytecode::Error> {
+        let mut tag = [0; 1];
+        try!(reader.read_exact(&mut tag));
+        Ok(match tag[0] {
+            0 => (1, Value::Null),
+            1 => (9, Value::Int(i64::from_le_bytes(try!(read_8(reader))))),
+            2 => {
+                let len = u64::from_le_bytes(try!(read_8(reader)));
+                let mut bytes = vec![0; len as usize];
+                try!(reader.read_exact(&mut bytes));
+                let s = try!(String::from_utf8(bytes).map_err(|_| bytecode::Error::InvalidBinaryFormat));
+                (9 + len, Value::Str(s))
+            },
+            4 => {
+                let len = u64::from_le_bytes(try!(read_8(reader)));
+                let mut bytes = vec![0; len as usize];
+                try!(reader.read_exact(&mut bytes));
+                let s = try!(String::from_utf8(bytes).map_err(|_| bytecode::Error::InvalidBinaryFormat));
+                (9 + len, Value::Safe(s))
+            },
+            3 => {
+                let count = u64::from_le_bytes(try!(read_8(reader)));
+                let mut len = 9;
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    let (item_len, item) = try!(Value::deserialize_value(reader));
+                    len += item_len;
+                    items.push(item);
+                }
+                (len, Value::List(items))
+            },
+            _ => return Err(bytecode::Error::InvalidBinaryFormat),
+        })
     }
 }
 
+fn read_8<I: io::Read>(reader: &mut I) -> Result<[u8; 8], bytecode::Error> {
+    let mut buf = [0; 8];
+    try!(reader.read_exact(&mut buf));
+    Ok(buf)
+}
+
 impl Default for Value {
     fn default() -> Value {
         Value::Null
@@ -63,7 +295,22 @@ impl fmt::Display for Value {
             Value::Null => Ok(()),
             Value::Int(ref i) => write!(f, "{}", i),
             Value::Str(ref s) => write!(f, "{}", s),
+            Value::Safe(ref s) => write!(f, "{}", s),
+            Value::List(ref s) => write!(f, "{:?}", s),
             Value::Obj(ref s) => write!(f, "{:?}", s),
+            Value::Unprintable => Err(fmt::Error),
         }
     }
 }
+
+impl From<String> for Value {
+    fn from(other: String) -> Value {
+        Value::Str(other)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(other: i64) -> Value {
+        Value::Int(other)
+    }
+}
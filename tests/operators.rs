@@ -0,0 +1,97 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+fn template(instructions: Vec<Instruction>) -> Template<Value> {
+    Template::empty()
+        .with_constant(Constant(0), Value::Int(3))
+        .with_constant(Constant(1), Value::Int(0))
+        .with_instructions(instructions)
+}
+
+fn render(instructions: Vec<Instruction>, params: Value) -> Result<String, LittleError> {
+    let funs = HashMap::new();
+
+    let mut out = Vec::new();
+    let mut i = Interpreter::new();
+    let interpreted = i.build("", template(instructions.clone()), &funs).unwrap()
+        .execute_into(params.clone(), &mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    let mut out = Vec::new();
+    let mut c = Compiler::new();
+    let compiled = c.build("", template(instructions), &funs).unwrap()
+        .execute_into(params, &mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    match (interpreted, compiled) {
+        (Ok(a), Ok(b)) => {
+            assert_eq!(a, b);
+            Ok(a)
+        },
+        (Err(e), Err(_)) => Err(e),
+        (a, b) => panic!("backends disagree: {:?} and {:?}", a, b),
+    }
+}
+
+fn output_top() -> Instruction {
+    Instruction::Output { location: Mem::StackTop1, escape: Escape::None }
+}
+
+#[test]
+fn operate_replaces_stack_value() {
+    let result = render(vec![
+        Instruction::Push { location: Mem::Parameters },
+        Instruction::Operate { op: Operator::Mul, location: Mem::Const(Constant(0)) },
+        Instruction::Operate { op: Operator::Sub, location: Mem::Parameters },
+        Instruction::Negate,
+        output_top(),
+        Instruction::Pop { times: 1 },
+    ], Value::Int(5));
+    assert_eq!("-10", result.unwrap());
+}
+
+#[test]
+fn compare_and_not_push_boolean() {
+    let result = render(vec![
+        Instruction::Push { location: Mem::Parameters },
+        Instruction::Compare { location: Mem::Const(Constant(0)), test: Cond::Gt },
+        output_top(),
+        Instruction::Not,
+        output_top(),
+        Instruction::Pop { times: 1 },
+    ], Value::Int(5));
+    assert_eq!("1", result.unwrap());
+}
+
+#[test]
+fn error_if_values_do_not_support_operator() {
+    let result = render(vec![
+        Instruction::Push { location: Mem::Parameters },
+        Instruction::Operate { op: Operator::Add, location: Mem::Const(Constant(0)) },
+    ], Value::Str("a".into()));
+    match result {
+        Err(LittleError::InvalidOperation { operation: "add", pc: 1 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_dividing_by_zero() {
+    let result = render(vec![
+        Instruction::Push { location: Mem::Parameters },
+        Instruction::Operate { op: Operator::Div, location: Mem::Const(Constant(1)) },
+    ], Value::Int(1));
+    match result {
+        Err(LittleError::InvalidOperation { operation: "div", pc: 1 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -0,0 +1,223 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::asm::{ assemble, disassemble };
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::optimizer::{ Optimizer, Pass, PassReport };
+
+use mock::Value;
+
+#[test]
+fn merges_consecutive_constant_output() {
+    let (text, report) = optimize(Pass::MergeOutput, "
+        .const 0 0x02010000000000000061 ; \"a\"
+        .const 1 0x02010000000000000062 ; \"b\"
+        .const 2 0x02010000000000000063 ; \"c\"
+            output const 0
+            output const 1
+            output const 2
+            output params
+    ");
+
+    assert_eq!(2, report.changes);
+    assert_eq!("\
+.bindings 0
+
+.const 3 0x020300000000000000616263 ; \"abc\"
+
+    output const 3
+    output params
+", text);
+}
+
+#[test]
+fn merges_only_output_with_the_same_escape() {
+    let (text, report) = optimize(Pass::MergeOutput, "
+        .const 0 0x02010000000000000061 ; \"a\"
+        .const 1 0x02010000000000000062 ; \"b\"
+            output.html const 0
+            output.html const 1
+            output const 1
+    ");
+
+    assert_eq!(1, report.changes);
+    assert_eq!("\
+.bindings 0
+
+.const 1 0x02010000000000000062 ; \"b\"
+.const 2 0x0202000000000000006162 ; \"ab\"
+
+    output.html const 2
+    output const 1
+", text);
+}
+
+#[test]
+fn does_not_merge_output_that_is_jump_target() {
+    let (text, report) = optimize(Pass::MergeOutput, "
+        .const 0 0x00
+            jump @second
+            output const 0
+        @second:
+            output const 0
+    ");
+
+    assert_eq!(0, report.changes);
+    assert!(text.contains("    output const 0\n@l2:\n    output const 0\n"));
+}
+
+#[test]
+fn removes_push_followed_by_pop() {
+    let (text, report) = optimize(Pass::RemovePushPop, "
+        .const 0 0x00
+            push params
+            push const 0
+            pop 2
+            push param 0
+            pop 1
+    ");
+
+    assert_eq!(1, report.changes);
+    assert!(text.ends_with("\n    push params\n    pop 1\n    push param 0\n    pop 1\n"));
+}
+
+#[test]
+fn keeps_block_that_starts_with_removed_instruction() {
+    let (text, report) = optimize(Pass::RemovePushPop, "
+        .block 2 \"content\"
+            call.local @content
+            jump @end
+        @content:
+            push params
+            pop 1
+            output params
+            return
+        @end:
+            output params
+    ");
+
+    assert_eq!(1, report.changes);
+    assert!(text.contains(".block 2 \"content\"\n"));
+}
+
+#[test]
+fn threads_jumps() {
+    let (text, report) = optimize(Pass::ThreadJumps, "
+            push params
+            cjump.eq @a, params
+            jump @end
+        @a:
+            jump @b
+        @b:
+            jump @end
+        @end:
+            pop 1
+    ");
+
+    assert_eq!(3, report.changes);
+    assert!(text.ends_with("\n    push params\n    cjump.eq @l4, params\n    jump @l4\n    jump @l4\n@l4:\n    pop 1\n"));
+}
+
+#[test]
+fn removes_dead_code_after_jump() {
+    let (text, report) = optimize(Pass::RemoveDeadCode, "
+            jump @end
+            output params
+            output params
+        @end:
+            output params
+    ");
+
+    assert_eq!(2, report.changes);
+    assert!(text.ends_with("\n    jump @l1\n@l1:\n    output params\n"));
+}
+
+#[test]
+fn keeps_body_of_block_that_is_not_called() {
+    let (text, report) = optimize(Pass::RemoveDeadCode, "
+        .block 3 \"content\"
+            jump @end
+            call.local @content
+            jump @end
+        @content:
+            output params
+            return
+        @end:
+            interupt 1
+    ");
+
+    assert_eq!(2, report.changes);
+    assert!(text.contains(".block 1 \"content\"\n"));
+    assert!(text.ends_with("\n    jump @l3\n    output params\n    return\n@l3:\n    interupt 1\n"));
+}
+
+#[test]
+fn reports_every_pass() {
+    let mut template = assemble::<Value>("
+            jump @end
+            output params
+        @end:
+    ").unwrap();
+
+    let report = Optimizer::new().optimize(&mut template);
+
+    assert_eq!(2, report.instructions);
+    assert_eq!(vec![
+        PassReport { pass: Pass::ThreadJumps, changes: 0, instructions: 2 },
+        PassReport { pass: Pass::RemoveDeadCode, changes: 1, instructions: 1 },
+        PassReport { pass: Pass::RemovePushPop, changes: 0, instructions: 1 },
+        PassReport { pass: Pass::MergeOutput, changes: 0, instructions: 1 },
+    ], report.passes);
+    assert_eq!(0, Optimizer::empty().optimize(&mut template).passes.len());
+}
+
+#[test]
+fn optimized_templates_render_the_same() {
+    let sources = [
+        "Hello, {{ \"dear\" }} {{ user.name }}!",
+        "{% for item in items %}[{{ item }}]{% if item %}yes{% else %}no{% endif %}{% endfor %}.",
+        "{% if items %}{% else %}a{{ \"b\" }}c{% endif %}",
+    ];
+    let mut user = HashMap::new();
+    user.insert("name".to_string(), Value::Str("Bob".into()));
+    let mut params = HashMap::new();
+    params.insert("user".to_string(), Value::Obj(user));
+    params.insert("items".to_string(), Value::List(vec![Value::Int(1), Value::Int(0), Value::Int(2)]));
+    let params = Value::Obj(params);
+
+    for source in &sources {
+        let expected = render(source, params.clone(), Optimizer::empty(), false);
+        assert_eq!(expected, render(source, params.clone(), Optimizer::new(), false), "interpreter {:?}", source);
+        assert_eq!(expected, render(source, params.clone(), Optimizer::new(), true), "compiler {:?}", source);
+    }
+}
+
+fn optimize(pass: Pass, source: &str) -> (String, PassReport) {
+    let mut template = assemble::<Value>(source).unwrap();
+    let report = Optimizer::empty().with_pass(pass).optimize(&mut template);
+    verifier::verify(&template).unwrap();
+    (disassemble(&template).unwrap(), report.passes[0])
+}
+
+fn render(source: &str, params: Value, optimizer: Optimizer, compile: bool) -> String {
+    let funs: HashMap<&str, &Function<Value>> = HashMap::new();
+
+    let template = parser::parse(source).unwrap();
+    let mut res = String::new();
+    if compile {
+        let mut c = Compiler::new();
+        c.set_optimizer(optimizer);
+        c.build("", template, &funs).unwrap().execute(params).read_to_string(&mut res).unwrap();
+    } else {
+        let mut i = Interpreter::new();
+        i.set_optimizer(optimizer);
+        i.build("", template, &funs).unwrap().execute(params).read_to_string(&mut res).unwrap();
+    }
+    res
+}
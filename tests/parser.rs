@@ -0,0 +1,210 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::parser::{ self, Position };
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+#[test]
+fn static_text() {
+    assert_eq!("Hello World", render("Hello World", Value::Null));
+}
+
+#[test]
+fn output_parameter() {
+    assert_eq!("Hello, Nerijus!", render("Hello, {{ name }}!", Value::obj(vec![("name", Value::Str("Nerijus".into()))])));
+}
+
+#[test]
+fn output_property() {
+    let params = Value::obj(vec![("user", Value::obj(vec![("name", Value::Str("Bob".into()))]))]);
+    assert_eq!("Bob", render("{{ user.name }}", params));
+}
+
+#[test]
+fn output_literals() {
+    assert_eq!("a 42", render("{{ \"a\" }} {{42}}", Value::Null));
+}
+
+#[test]
+fn comments_are_skipped() {
+    assert_eq!("ab", render("a{# ignore {{ me }} #}b", Value::Null));
+}
+
+#[test]
+fn call_function() {
+    let params = Value::obj(vec![("a", Value::Int(2)), ("b", Value::Int(3))]);
+    assert_eq!("5 and 7", render("{{ add(a, b) }} and {{ add(add(a, 3), 2 ) }}", params));
+}
+
+#[test]
+fn if_else() {
+    let source = "{% if flag %}yes{% else %}no{% endif %}";
+    assert_eq!("yes", render(source, Value::obj(vec![("flag", Value::Int(1))])));
+    assert_eq!("no", render(source, Value::obj(vec![("flag", Value::Null)])));
+}
+
+#[test]
+fn if_without_else() {
+    let source = "[{% if flag %}yes{% endif %}]";
+    assert_eq!("[yes]", render(source, Value::obj(vec![("flag", Value::Int(1))])));
+    assert_eq!("[]", render(source, Value::obj(vec![("flag", Value::Null)])));
+}
+
+#[test]
+fn for_loop() {
+    let params = Value::obj(vec![
+        ("items", Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)])),
+    ]);
+    assert_eq!("<1><2><3>", render("{% for item in items %}<{{ item }}>{% endfor %}", params));
+}
+
+#[test]
+fn nested_for_loop_with_properties() {
+    let params = Value::obj(vec![
+        ("rows", Value::List(vec![
+            Value::obj(vec![("cells", Value::List(vec![Value::Int(1), Value::Int(2)]))]),
+            Value::obj(vec![("cells", Value::List(vec![]))]),
+            Value::obj(vec![("cells", Value::List(vec![Value::Int(3)]))]),
+        ])),
+    ]);
+    let source = "{% for row in rows %}[{% for cell in row.cells %}{{ cell }}{% endfor %}]{% endfor %}";
+    assert_eq!("[12][][3]", render(source, params));
+}
+
+#[test]
+fn arithmetic_operators_follow_precedence() {
+    let params = Value::obj(vec![("a", Value::Int(7)), ("b", Value::Int(2))]);
+    assert_eq!("11 1 -3 18", render("{{ a + b * 2 }} {{ a % b }} {{ -(a - 4) }} {{ (a + b) * (b + 0) }}", params));
+}
+
+#[test]
+fn operands_can_be_calls_and_properties() {
+    let params = Value::obj(vec![("user", Value::obj(vec![("age", Value::Int(30))]))]);
+    assert_eq!("35", render("{{ user.age + add(2, 3) }}", params));
+}
+
+#[test]
+fn comparison_and_not_in_if() {
+    let source = "{% if n > 1 %}many{% endif %}{% if not n == 1 %}!{% endif %}";
+    assert_eq!("many!", render(source, Value::obj(vec![("n", Value::Int(3))])));
+    assert_eq!("", render(source, Value::obj(vec![("n", Value::Int(1))])));
+    assert_eq!("!", render(source, Value::obj(vec![("n", Value::Int(0))])));
+}
+
+#[test]
+fn constants_are_reused() {
+    let template = parser::parse::<Value>("{{ a }}{{ a }}x{{ \"x\" }}").unwrap();
+    assert_eq!(4, template.instructions.len());
+    assert!(template.constants.get(Constant(2)).is_none());
+}
+
+#[test]
+fn error_unclosed_block() {
+    let err = parser::parse::<Value>("abc\n  {% if a %}text").err().unwrap();
+    assert_eq!(ParseErrorKind::UnclosedBlock { tag: "if" }, err.kind);
+    assert_eq!(Position::new(2, 3), err.span.start);
+}
+
+#[test]
+fn error_unexpected_token() {
+    let err = parser::parse::<Value>("{{ user. }}").err().unwrap();
+    assert_eq!(
+        ParseErrorKind::UnexpectedToken { found: "\"}}\"".into(), expected: "property name" },
+        err.kind
+    );
+    assert_eq!(Position::new(1, 10), err.span.start);
+    assert_eq!(Position::new(1, 12), err.span.end);
+}
+
+#[test]
+fn error_unterminated_string() {
+    let err = parser::parse::<Value>("{{ \"abc }}").err().unwrap();
+    assert_eq!(ParseErrorKind::UnterminatedString, err.kind);
+    assert_eq!(Position::new(1, 4), err.span.start);
+}
+
+#[test]
+fn error_unknown_tag() {
+    let err = parser::parse::<Value>("{% while x %}").err().unwrap();
+    assert_eq!(ParseErrorKind::UnknownTag("while".into()), err.kind);
+}
+
+#[test]
+fn error_stray_end_tag() {
+    let err = parser::parse::<Value>("a{% endfor %}").err().unwrap();
+    assert_eq!(Position::new(1, 5), err.span.start);
+}
+
+#[test]
+fn error_unexpected_operator() {
+    let err = parser::parse::<Value>("{{ a + * b }}").err().unwrap();
+    assert_eq!(
+        ParseErrorKind::UnexpectedToken { found: "\"*\"".into(), expected: "expression" },
+        err.kind
+    );
+    assert_eq!(Position::new(1, 8), err.span.start);
+}
+
+#[test]
+fn error_duplicate_block() {
+    let err = parser::parse::<Value>("{% block a %}{% endblock %}\n{% block a %}{% endblock %}").err().unwrap();
+    assert_eq!(ParseErrorKind::DuplicateBlock("a".into()), err.kind);
+    assert_eq!(Position::new(2, 1), err.span.start);
+}
+
+#[test]
+fn error_super_outside_block() {
+    let err = parser::parse::<Value>("{% if a %}{{ super() }}{% endif %}").err().unwrap();
+    assert_eq!(ParseErrorKind::SuperOutsideBlock, err.kind);
+    assert_eq!(Position::new(1, 14), err.span.start);
+}
+
+#[test]
+fn error_super_with_arguments() {
+    let err = parser::parse::<Value>("{% block a %}{{ super(x) }}{% endblock %}").err().unwrap();
+    assert_eq!(ParseErrorKind::InvalidSuperCall, err.kind);
+    assert_eq!(Position::new(1, 17), err.span.start);
+
+    let err = parser::parse::<Value>("{% block a %}{{ super() + 1 }}{% endblock %}").err().unwrap();
+    assert_eq!(ParseErrorKind::InvalidSuperCall, err.kind);
+}
+
+#[test]
+fn error_too_deeply_nested() {
+    let nested = |depth: usize| format!("{{{{ {}a{} }}}}", "(".repeat(depth), ")".repeat(depth));
+    assert!(parser::parse::<Value>(&nested(parser::MAX_DEPTH - 1)).is_ok());
+
+    let err = parser::parse::<Value>(&nested(100000)).err().unwrap();
+    assert_eq!(ParseErrorKind::TooDeeplyNested, err.kind);
+
+    let err = parser::parse::<Value>(&format!("{{{{ {}a }}}}", "-".repeat(100000))).err().unwrap();
+    assert_eq!(ParseErrorKind::TooDeeplyNested, err.kind);
+
+    let blocks = format!("{}{}", "{% if a %}".repeat(100000), "{% endif %}".repeat(100000));
+    let err = parser::parse::<Value>(&blocks).err().unwrap();
+    assert_eq!(ParseErrorKind::TooDeeplyNested, err.kind);
+}
+
+fn render(source: &str, params: Value) -> String {
+    let mut funs = HashMap::new();
+    funs.insert("add", &mock::add as &Function<Value>);
+
+    let template = parser::parse(source).unwrap();
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template, &funs).unwrap();
+
+    let mut res = String::new();
+    p.execute(params)
+        .read_to_string(&mut res)
+        .unwrap();
+
+    res
+}
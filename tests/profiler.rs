@@ -0,0 +1,72 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use little::*;
+use little::interpreter::Interpreter;
+use little::parser;
+
+use mock::Value;
+
+#[test]
+fn counts_instructions_output_and_calls() {
+    let mut funs = HashMap::<&str, &Function<Value>>::new();
+    let upper = |args: &[Value]| -> LittleResult<Value> {
+        Ok(Value::Str(args[0].to_string().to_uppercase()))
+    };
+    funs.insert("upper", &upper);
+
+    let mut i = Interpreter::new();
+    let p = i.build(
+        "",
+        Template::empty()
+            .with_call("upper", Call(0))
+            .with_constant(Constant(0), Value::Str("ab".into()))
+            .with_constant(Constant(1), Value::Int(3))
+            .with_instructions(vec![
+                Instruction::Push { location: Mem::Const(Constant(0)) },
+                Instruction::Call { call: Call(0), argc: 1, push_result_to_stack: true },
+                Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+                Instruction::Pop { times: 2 },
+                Instruction::Output { location: Mem::Const(Constant(1)), escape: Escape::None },
+            ]),
+        &funs
+    ).unwrap();
+
+    let mut stream = p.execute(Value::Null);
+    assert!(stream.profile().is_none());
+    stream.enable_profiler();
+
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    assert_eq!("AB3", res);
+
+    let profile = stream.profile().unwrap();
+    assert_eq!(5, profile.executions());
+    assert_eq!(2, profile.instructions[2].output_bytes);
+    assert_eq!(1, profile.instructions[4].output_bytes);
+    assert_eq!(1, profile.calls["upper"].calls);
+    assert_eq!(vec![(0, profile.instructions[0])], profile.hottest().into_iter().take(1).collect::<Vec<_>>());
+    assert!(profile.to_string().starts_with("5 instructions executed\n"));
+}
+
+#[test]
+fn reports_counters_by_source_location() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let template = parser::parse_file::<Value>("page.html", "a\n{{ b }}").unwrap();
+    let p = i.build("", template, &funs).unwrap();
+
+    let mut stream = p.execute(Value::Obj(vec![("b".to_string(), Value::Int(10))].into_iter().collect()));
+    stream.enable_profiler();
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+
+    let locations = stream.profile().unwrap().by_location(p.source_map().unwrap());
+    assert_eq!(2, locations.len());
+    assert_eq!("page.html:2:4", locations[1].0.to_string());
+    assert_eq!(2, locations[1].1.output_bytes);
+}
@@ -0,0 +1,145 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::io;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+
+use mock::Value;
+
+fn template() -> Template<Value> {
+    Template::empty()
+        .with_constant(Constant(0), Value::Str("Grüße, ".into()))
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::HtmlAttribute },
+        ])
+}
+
+#[test]
+fn renders_into_writer() {
+    let funs = HashMap::new();
+    let expected = "Grüße, a&#x20;é";
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut out = Vec::new();
+    p.execute_into(Value::Str("a é".into()), &mut out).unwrap();
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    let mut out = Vec::new();
+    p.execute_into(Value::Str("a é".into()), &mut out).unwrap();
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn renders_into_string() {
+    let funs = HashMap::new();
+    let expected = "Grüße, a&#x20;é";
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let mut out = String::from(">");
+    p.execute_fmt(Value::Str("a é".into()), &mut out).unwrap();
+    assert_eq!(format!(">{}", expected), out);
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    let mut out = String::new();
+    p.execute_fmt(Value::Str("a é".into()), &mut out).unwrap();
+    assert_eq!(expected, out);
+}
+
+#[test]
+fn interupt_stops_writing_and_can_resume() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let p = i.build(
+        "",
+        Template::empty()
+            .with_constant(Constant(0), Value::Str("a".into()))
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+                Instruction::Interupt { code: 5 },
+                Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            ]),
+        &funs
+    ).unwrap();
+
+    let mut out = Vec::new();
+    match p.execute_into(Value::Null, &mut out) {
+        Err(LittleError::Interupt(5)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(b"a", &out[..]);
+
+    let mut stream = p.execute(Value::Null);
+    let mut out = Vec::new();
+    assert!(stream.write_to(&mut out).is_err());
+    stream.write_to(&mut out).unwrap();
+    assert_eq!(b"aa", &out[..]);
+}
+
+struct FailingWriter;
+
+impl io::Write for FailingWriter {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn error_if_writer_fails() {
+    let funs = HashMap::new();
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    match p.execute_into(Value::Null, &mut FailingWriter) {
+        Err(LittleError::OutputError(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn streams_large_output_with_small_reads() {
+    use std::io::Read;
+
+    let funs = HashMap::new();
+    let large = "0123456789".repeat(100000);
+    let template = || Template::empty()
+        .with_instructions(vec![
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+        ]);
+
+    let read_all = |stream: &mut Read| {
+        let mut buf = [0; 7];
+        let mut out = Vec::new();
+        loop {
+            match stream.read(&mut buf).unwrap() {
+                0 => return out,
+                len => out.extend_from_slice(&buf[..len]),
+            }
+        }
+    };
+
+    let mut i = Interpreter::new();
+    let p = i.build("", template(), &funs).unwrap();
+    let out = read_all(&mut p.execute(Value::Str(large.clone())));
+    assert_eq!(large.len() * 2, out.len());
+    assert_eq!(format!("{}{}", large, large).as_bytes(), &out[..]);
+
+    let mut c = Compiler::new();
+    let p = c.build("", template(), &funs).unwrap();
+    let out = read_all(&mut p.execute(Value::Str(large.clone())));
+    assert_eq!(format!("{}{}", large, large).as_bytes(), &out[..]);
+}
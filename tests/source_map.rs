@@ -0,0 +1,79 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{ Cursor, Read };
+
+use little::*;
+use little::bytecode::Serializer;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::optimizer::{ Optimizer, Pass };
+use little::parser;
+
+use mock::Value;
+
+fn read_error<R: Read>(stream: &mut R) -> String {
+    let mut res = String::new();
+    let err = stream.read_to_string(&mut res).err().expect("expected execution error");
+    let err = LittleError::from_io_error(&err).expect("expected LittleError");
+    assert_eq!("parameter is missing", err.unlocated().description());
+    err.location().expect("expected location").to_string()
+}
+
+#[test]
+fn interpreter_error_includes_location() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let template = parser::parse_file::<Value>("page.html", "Hello\n  {{ user.name }}").unwrap();
+    let p = i.build("", template, &funs).unwrap();
+
+    assert_eq!("page.html:2:6", read_error(&mut p.execute(Value::Obj(HashMap::new()))));
+}
+
+#[test]
+fn compiler_error_includes_location() {
+    let funs = HashMap::new();
+    let mut c = Compiler::new();
+    let template = parser::parse::<Value>("Hello {{ name }}").unwrap();
+    let p = c.build("", template, &funs).unwrap();
+
+    assert_eq!("1:10", read_error(&mut p.execute(Value::Obj(HashMap::new()))));
+}
+
+#[test]
+fn source_map_survives_serialization() {
+    let template = parser::parse_file::<Value>("page.html", "a{{ b }}").unwrap();
+
+    let mut data = Vec::new();
+    template.serialize(&mut data).unwrap();
+    let (_, restored) = Template::<Value>::deserialize(&mut Cursor::new(&data[..])).unwrap();
+
+    assert_eq!(template.source_map, restored.source_map);
+    assert_eq!(Some(SourceLocation { file: "page.html".into(), line: 1, column: 5 }), restored.source_map.unwrap().resolve(1));
+}
+
+#[test]
+fn optimizer_keeps_spans_of_kept_instructions() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("page.html");
+    for line in 1..5 {
+        source_map.push(Some(SourceSpan::new(file, line, 1)));
+    }
+    let mut template = Template::<Value>::empty()
+        .with_constant(Constant(0), Value::Int(1))
+        .with_instructions(vec![
+            Instruction::Jump { pc: 2 },
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Const(Constant(0)), escape: Escape::None },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+        ])
+        .with_source_map(source_map);
+
+    Optimizer::empty().with_pass(Pass::RemoveDeadCode).optimize(&mut template);
+
+    let source_map = template.source_map.unwrap();
+    assert_eq!(vec![Some(SourceSpan::new(file, 1, 1)), Some(SourceSpan::new(file, 3, 1)), Some(SourceSpan::new(file, 4, 1))], source_map.spans);
+}
@@ -0,0 +1,134 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+
+use little::*;
+use little::compiler::Compiler;
+use little::interpreter::Interpreter;
+use little::verifier::verify;
+
+use mock::Value;
+
+fn render<F: Fn() -> Template<Value>>(template: F, limits: Limits) -> Result<String, LittleError> {
+    let funs = HashMap::new();
+
+    let mut out = Vec::new();
+    let mut i = Interpreter::new();
+    let interpreted = i.build("", template(), &funs).unwrap()
+        .execute_with_limits(Value::Null, limits)
+        .write_to(&mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    let mut out = Vec::new();
+    let mut c = Compiler::new();
+    let compiled = c.build("", template(), &funs).unwrap()
+        .execute_with_limits(Value::Null, limits)
+        .write_to(&mut out)
+        .map(|_| String::from_utf8(out).unwrap());
+
+    match (interpreted, compiled) {
+        (Ok(a), Ok(b)) => {
+            assert_eq!(a, b);
+            Ok(a)
+        },
+        (Err(e), Err(_)) => Err(e),
+        (a, b) => panic!("backends disagree: {:?} and {:?}", a, b),
+    }
+}
+
+fn output(location: Mem) -> Instruction {
+    Instruction::Output { location: location, escape: Escape::None }
+}
+
+#[test]
+fn subroutine_is_called_with_own_bindings() {
+    let template = || {
+        let mut template = Template::empty()
+        .with_constant(Constant(0), Value::Str("main".into()))
+        .with_constant(Constant(1), Value::Str("a".into()))
+        .with_constant(Constant(2), Value::Str("b".into()))
+        .with_constant(Constant(3), Value::Str("<".into()))
+        .with_constant(Constant(4), Value::Str(">".into()))
+        .with_instructions(vec![
+            Instruction::Load { binding: Binding(0), location: Mem::Const(Constant(0)) },
+            // Binding(1) of the caller is Binding(0) of subroutine.
+            Instruction::Load { binding: Binding(1), location: Mem::Const(Constant(1)) },
+            Instruction::CallLocal { pc: 7 },
+            Instruction::Load { binding: Binding(1), location: Mem::Const(Constant(2)) },
+            Instruction::CallLocal { pc: 7 },
+            output(Mem::Binding(Binding(0))),
+            Instruction::Jump { pc: 12 },
+            output(Mem::Const(Constant(3))),
+            output(Mem::Binding(Binding(0))),
+            Instruction::Load { binding: Binding(0), location: Mem::Const(Constant(4)) },
+            output(Mem::Binding(Binding(0))),
+            Instruction::Return,
+        ]);
+        template.bindings_capacity = 1;
+        template
+    };
+
+    assert_eq!("<a><b>main", render(template, Limits::default()).unwrap());
+}
+
+#[test]
+fn error_if_subroutines_are_nested_too_deep() {
+    let template = || Template::empty()
+        .with_instructions(vec![
+            Instruction::CallLocal { pc: 2 },
+            Instruction::Jump { pc: 4 },
+            Instruction::CallLocal { pc: 2 },
+            Instruction::Return,
+        ]);
+
+    let limits = Limits { max_stack_depth: Some(10), .. Limits::default() };
+    match render(template, limits) {
+        Err(LittleError::StackLimitExceeded(10)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn verifier_rejects_return_outside_of_subroutine() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::CallLocal { pc: 2 },
+            Instruction::Return,
+            Instruction::Return,
+        ]);
+    assert_eq!(Err(VerifyError::new(VerifyErrorKind::ReturnWithoutCall, 1)), verify(&template));
+}
+
+#[test]
+fn verifier_rejects_subroutine_that_changes_stack() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::CallLocal { pc: 2 },
+            Instruction::Jump { pc: 4 },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Return,
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::StackMismatch { expected: 0, found: 1 }, 3)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn verifier_rejects_subroutine_that_reads_caller_stack() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::CallLocal { pc: 4 },
+            Instruction::Pop { times: 1 },
+            Instruction::Jump { pc: 6 },
+            Instruction::Output { location: Mem::StackTop1, escape: Escape::None },
+            Instruction::Return,
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::StackUnderflow { required: 1, depth: 0 }, 4)),
+        verify(&template)
+    );
+}
@@ -0,0 +1,189 @@
+extern crate little;
+
+mod mock;
+
+use std::collections::HashMap;
+
+use little::*;
+use little::interpreter::Interpreter;
+use little::verifier::{ verify, verify_with_limits };
+
+use mock::Value;
+
+#[test]
+fn accepts_parsed_templates() {
+    let template = parser::parse::<Value>(
+        "{% for item in items %}{% if item %}{{ item.name }}{% else %}-{% endif %}{% endfor %}"
+    ).unwrap();
+    assert_eq!(Ok(()), verify(&template));
+}
+
+#[test]
+fn accepts_jump_to_end() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Jump { pc: 1 },
+        ]);
+    assert_eq!(Ok(()), verify(&template));
+}
+
+#[test]
+fn error_if_jump_is_out_of_bounds() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::CondJump { pc: 5, location: Mem::Parameters, test: Cond::Eq },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::JumpOutOfBounds { target: 5 }, 1)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_stack_top2_underflows() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Output { location: Mem::StackTop2, escape: Escape::None },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::StackUnderflow { required: 2, depth: 1 }, 1)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_call_has_more_arguments_than_stack() {
+    let template = Template::<Value>::empty()
+        .with_call("add", Call(0))
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Call { call: Call(0), argc: 2, push_result_to_stack: true },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::StackUnderflow { required: 2, depth: 1 }, 1)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_call_is_missing() {
+    let template = Template::<Value>::empty()
+        .with_call("add", Call(0))
+        .with_instructions(vec![
+            Instruction::Call { call: Call(1), argc: 0, push_result_to_stack: false },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::CallMissing(Call(1)), 0)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_paths_reach_instruction_with_different_stack_depth() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::CondJump { pc: 3, location: Mem::Parameters, test: Cond::Eq },
+            Instruction::Push { location: Mem::Parameters },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+        ]);
+    match verify(&template) {
+        Err(VerifyError { kind: VerifyErrorKind::StackMismatch { .. }, pc: 3 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_iteration_is_not_started() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::IterNext { binding: Binding(0), pc: 2 },
+            Instruction::Jump { pc: 0 },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::IterationMissing, 0)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_iteration_is_left_unfinished_in_loop() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::IterStart { location: Mem::Parameters },
+            Instruction::IterNext { binding: Binding(0), pc: 3 },
+            Instruction::Jump { pc: 0 },
+        ]);
+    match verify(&template) {
+        Err(VerifyError { kind: VerifyErrorKind::IterationMismatch { expected: 0, found: 1 }, pc: 0 }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn error_if_subroutine_binding_is_out_of_bounds() {
+    let mut template = Template::<Value>::empty()
+        .with_instructions(vec![
+            // Binding(1) of the caller is Binding(0) of subroutine.
+            Instruction::Load { binding: Binding(1), location: Mem::Parameters },
+            Instruction::CallLocal { pc: 3 },
+            Instruction::Jump { pc: 5 },
+            Instruction::Output { location: Mem::Binding(Binding(1)), escape: Escape::None },
+            Instruction::Return,
+        ]);
+    template.bindings_capacity = 1;
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::BindingOutOfBounds { binding: Binding(1), capacity: 1 }, 3)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn error_if_binding_exceeds_limit() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::Load { binding: Binding(4), location: Mem::Parameters },
+        ]);
+    let limits = Limits { max_bindings: Some(4), .. Limits::default() };
+    assert_eq!(Ok(()), verify(&template));
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::BindingLimitExceeded { binding: Binding(4), max: 4 }, 0)),
+        verify_with_limits(&template, &limits)
+    );
+}
+
+#[test]
+fn error_if_subroutine_runs_past_last_instruction() {
+    let template = Template::<Value>::empty()
+        .with_instructions(vec![
+            Instruction::CallLocal { pc: 2 },
+            Instruction::Jump { pc: 3 },
+            Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+        ]);
+    assert_eq!(
+        Err(VerifyError::new(VerifyErrorKind::MissingReturn, 2)),
+        verify(&template)
+    );
+}
+
+#[test]
+fn build_reports_offending_instruction() {
+    let funs = HashMap::new();
+    let mut i = Interpreter::new();
+    let err = i.build(
+        "",
+        Template::<Value>::empty()
+            .with_instructions(vec![
+                Instruction::Output { location: Mem::Parameters, escape: Escape::None },
+                Instruction::Property { name: Mem::Parameters },
+            ]),
+        &funs
+    ).err().unwrap();
+
+    match err.downcast_ref::<BuildError>() {
+        Some(&BuildError::Verify(ref e)) => assert_eq!(1, e.pc),
+        other => panic!("unexpected error {:?}", other),
+    }
+}